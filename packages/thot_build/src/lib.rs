@@ -0,0 +1,251 @@
+extern crate serde_json;
+extern crate thot_core;
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thot_core::ast::conversion_error::{ConversionError, Errors};
+use thot_core::ast::core::from_json::from_json;
+use thot_core::ast::core::to_rust::{to_rust, RustOptions};
+use thot_core::ast::core::Core;
+use thot_core::ast::location::{Location, Point};
+use thot_core::ast::statechart::from_scxml::from_scxml;
+use thot_core::ast::statechart::from_xstate::{from_xstate, Symbols};
+use thot_core::ast::statechart::Statechart;
+
+// compiles a chart from `build.rs` into `$OUT_DIR/<name>.rs`, where the name is the file
+// name up to its first `.`. Include the module it contains with
+//
+// include!(concat!(env!("OUT_DIR"), "/player.rs"));
+//
+// Prints the chart errors and exits, which fails the build.
+pub fn compile<P: AsRef<Path>>(path: P) -> PathBuf {
+    compile_with(path, &BuildOptions::default())
+}
+
+pub fn compile_with<P: AsRef<Path>>(path: P, options: &BuildOptions) -> PathBuf {
+    match try_compile(path, options) {
+        Ok(out) => out,
+        Err(err) => {
+            eprint!("{}", err);
+            std::process::exit(1)
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BuildOptions {
+    // defaults to `$OUT_DIR`
+    pub out_dir: Option<PathBuf>,
+    // defaults to the file name of the chart
+    pub module: Option<String>,
+    pub visibility: String,
+    // prints `cargo:rerun-if-changed` for the chart. Charts cannot include other files,
+    // so the chart is the only file read.
+    pub rerun_if_changed: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> BuildOptions {
+        BuildOptions {
+            out_dir: None,
+            module: None,
+            visibility: "pub".to_string(),
+            rerun_if_changed: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BuildError {
+    pub path: PathBuf,
+    pub errors: Errors,
+}
+
+// one `file:line: message` line per error, like the thot CLI prints them
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for err in &self.errors {
+            writeln!(
+                f,
+                "{}:{}: {}",
+                self.path.display(),
+                err.loc.start.line,
+                err.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+pub fn try_compile<P: AsRef<Path>>(path: P, options: &BuildOptions) -> Result<PathBuf, BuildError> {
+    let path = path.as_ref();
+    let fail = |errors| BuildError {
+        path: path.to_path_buf(),
+        errors,
+    };
+    if options.rerun_if_changed {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    let source = fs::read_to_string(path).map_err(|err| fail(vec![error(err.to_string(), 0)]))?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let (core, symbols) = read_chart(&file_name, &source).map_err(fail)?;
+
+    let module = match options.module {
+        Some(ref module) => module.clone(),
+        None => module_name(&file_name),
+    };
+    let named = |names: &[String]| -> Vec<(String, usize)> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(id, name)| (name.clone(), id))
+            .collect()
+    };
+    let rust_options = RustOptions {
+        module: module.clone(),
+        visibility: options.visibility.clone(),
+        events: named(&symbols.events),
+        guards: named(&symbols.guards),
+        actions: named(&symbols.actions),
+    };
+    let rust = to_rust(&core, &rust_options).map_err(fail)?;
+
+    let out_dir = match options.out_dir {
+        Some(ref out_dir) => out_dir.clone(),
+        None => PathBuf::from(env::var_os("OUT_DIR").ok_or_else(|| {
+            fail(vec![error(
+                "OUT_DIR is not set, so this is not running in a build script".to_string(),
+                0,
+            )])
+        })?),
+    };
+    let out = out_dir.join(format!("{}.rs", module));
+    fs::write(&out, rust).map_err(|err| fail(vec![error(err.to_string(), 0)]))?;
+    Ok(out)
+}
+
+// picks the reader the same way the CLI does: by the file extension
+fn read_chart(file_name: &str, source: &str) -> Result<(Core, Symbols), Errors> {
+    if file_name.ends_with(".core.json") {
+        return Ok((from_json(source)?, Symbols::default()));
+    }
+    let (statechart, symbols) = if file_name.ends_with(".scxml") {
+        (from_scxml(source)?, Symbols::default())
+    } else if file_name.ends_with(".xstate.json") {
+        let machine = from_xstate(source)?;
+        (machine.statechart, machine.symbols)
+    } else {
+        let statechart: Statechart =
+            serde_json::from_str(source).map_err(|err| vec![error(err.to_string(), err.line())])?;
+        (statechart, Symbols::default())
+    };
+    let core: Result<Core, Errors> = statechart.into();
+    Ok((core?, symbols))
+}
+
+// `traffic-light.xstate.json` becomes `traffic_light`
+fn module_name(file_name: &str) -> String {
+    let stem = file_name.split('.').next().unwrap_or_default();
+    let mut module: String = stem
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if module.is_empty() || module.starts_with(|c: char| c.is_numeric()) {
+        module.insert(0, '_');
+    }
+    module
+}
+
+fn error(message: String, line: usize) -> ConversionError {
+    ConversionError {
+        message,
+        fatal: true,
+        source: "thot_build".to_string(),
+        loc: Location {
+            start: Point { line, column: 0 },
+            ..Default::default()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a directory of its own for each test, since they run in parallel
+    fn out_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("thot_build-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn options(out_dir: &Path) -> BuildOptions {
+        BuildOptions {
+            out_dir: Some(out_dir.to_path_buf()),
+            rerun_if_changed: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn charts_compile_into_the_out_dir() {
+        let dir = out_dir("compile");
+        let chart = dir.join("traffic-light.scxml");
+        fs::write(
+            &chart,
+            "<scxml><state id=\"green\"><transition event=\"0\" target=\"red\"/></state><state id=\"red\"/></scxml>",
+        )
+        .unwrap();
+
+        let out = try_compile(&chart, &options(&dir)).unwrap();
+        assert_eq!(out, dir.join("traffic_light.rs"));
+        let rust = fs::read_to_string(&out).unwrap();
+        assert!(rust.contains("pub mod traffic_light {"));
+        assert!(rust.contains("State::Green => \"green\""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_are_listed_by_file_and_line() {
+        let dir = out_dir("errors");
+        let chart = dir.join("bad.scxml");
+        fs::write(
+            &chart,
+            "<scxml>\n  <state id=\"a\">\n    <transition target=\"nowhere\"/>\n  </state>\n</scxml>\n",
+        )
+        .unwrap();
+
+        let err = try_compile(&chart, &options(&dir)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{}:3: Missing target: \"nowhere\"\n", chart.display())
+        );
+        let err = try_compile(dir.join("missing.scxml"), &options(&dir)).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}:0: ", dir.join("missing.scxml").display())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn modules_are_named_after_the_file() {
+        assert_eq!(module_name("traffic-light.xstate.json"), "traffic_light");
+        assert_eq!(module_name("Player.scxml"), "player");
+        assert_eq!(module_name("2fa.json"), "_2fa");
+        assert_eq!(module_name(".json"), "_");
+    }
+}
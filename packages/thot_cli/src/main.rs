@@ -1,13 +1,35 @@
 extern crate clap;
+extern crate serde;
 extern crate serde_json;
 extern crate thot_core;
 
-use clap::{App, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, SubCommand};
 use std::fs::File;
 use std::io::prelude::*;
+use std::time::Instant;
+use thot_core::analysis::livelock::livelocks;
+use thot_core::analysis::nondeterminism::nondeterminism;
+use thot_core::analysis::reachability::{reachability, ReachabilityOptions};
+use thot_core::analysis::test_generation::{generate_tests, GenerationOptions};
+use thot_core::ast::conversion_error::{ConversionError, Errors};
+use thot_core::ast::core::from_json::from_json;
+use thot_core::ast::core::to_dot::{to_dot, DotOptions};
+use thot_core::ast::core::to_smt::{to_smt, SmtOptions};
+use thot_core::ast::core::to_smv::{to_smv, SmvOptions};
+use thot_core::ast::core::to_state_diagram::{to_mermaid, to_plantuml};
 use thot_core::ast::core::Core;
+use thot_core::ast::location::{Location, Point};
 use thot_core::ast::microstep::Microstep;
-use thot_core::ast::statechart::Statechart;
+use thot_core::ast::statechart::from_scxml::from_scxml;
+use thot_core::ast::statechart::from_xstate::{from_xstate, XState};
+use thot_core::ast::statechart::to_scxml::to_scxml;
+use thot_core::ast::statechart::to_xstate::to_xstate;
+use thot_core::runtime::coverage::Coverage;
+use thot_core::runtime::interpreter::RuntimeError;
+use thot_core::runtime::scenario::{
+    parse_scenarios, run_scenario_with_coverage, ScenarioFailureKind,
+};
+use thot_core::runtime::simulator::{Changes, Simulator};
 
 fn main() {
     let matches = App::new("Statechart CLI")
@@ -18,31 +40,991 @@ fn main() {
                 .version("1.0")
                 .arg(
                     Arg::with_name("INPUT")
-                        .help("Sets the input file to use")
+                        .help("Sets the input file to use, or - to read from stdin")
                         .required(true),
                 )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .possible_values(&["json", "scxml", "xstate", "core"])
+                        .help("Sets the input format, detected from the file extension by default"),
+                )
+                .arg(
+                    Arg::with_name("emit")
+                        .long("emit")
+                        .takes_value(true)
+                        .possible_values(&[
+                            "statechart",
+                            "core",
+                            "microstep",
+                            "scxml",
+                            "xstate",
+                            "dot",
+                            "mermaid",
+                            "plantuml",
+                            "smv",
+                            "smt",
+                        ])
+                        .default_value("microstep")
+                        .help("Sets the stage or backend to output"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true)
+                        .help("Sets the output file, stdout by default"),
+                )
                 .arg(
                     Arg::with_name("debug")
+                        .long("debug")
                         .short("d")
-                        .help("print debug information verbosely"),
+                        .help("Prints the time and output size of every stage to stderr"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("graph")
+                .about("Render statechart diagrams")
+                .version("1.0")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the input file to use")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .short("f")
+                        .takes_value(true)
+                        .possible_values(&["dot", "mermaid", "plantuml"])
+                        .default_value("dot")
+                        .help("Sets the diagram format"),
+                )
+                .arg(
+                    Arg::with_name("active")
+                        .long("active")
+                        .short("a")
+                        .takes_value(true)
+                        .use_delimiter(true)
+                        .multiple(true)
+                        .help("Highlights the given active state ids"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("convert")
+                .about("Convert statecharts between formats")
+                .version("1.0")
+                // `scxml <INPUT>` predates the other formats
+                .alias("scxml")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the input file to use")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .takes_value(true)
+                        .possible_values(&["json", "scxml", "xstate"])
+                        .help("Sets the input format, detected from the file extension by default"),
+                )
+                .arg(
+                    Arg::with_name("to")
+                        .long("to")
+                        .short("t")
+                        .takes_value(true)
+                        .possible_values(&["json", "scxml", "xstate"])
+                        .default_value("scxml")
+                        .help("Sets the output format"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("generate")
+                .about("Generate test scenarios that fire every transition")
+                .version("1.0")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the input file to use")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("state-pairs")
+                        .long("state-pairs")
+                        .help("Also covers every pair of states that can be active together"),
+                )
+                .arg(
+                    Arg::with_name("out-dir")
+                        .long("out-dir")
+                        .short("o")
+                        .takes_value(true)
+                        .help(
+                            "Writes one <name>.scenario.json per scenario instead of printing them",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("max-snapshots")
+                        .long("max-snapshots")
+                        .takes_value(true)
+                        .default_value("10000")
+                        .help("Stops exploring after this many machine snapshots"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("run")
+                .about("Simulate a statechart interactively")
+                .version("1.0")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the input file to use")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("test")
+                .about("Run scenario files against a statechart")
+                .version("1.0")
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("Sets the input file to use")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("SCENARIO")
+                        .help("Sets the scenario files to run")
+                        .required(true)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("coverage")
+                        .long("coverage")
+                        .takes_value(true)
+                        .help(
+                            "Writes the states, transitions and executables the scenarios reached",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("coverage-format")
+                        .long("coverage-format")
+                        .takes_value(true)
+                        .possible_values(&["lcov", "json"])
+                        .default_value("lcov")
+                        .help("Sets the coverage format"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Export statecharts to model checkers")
+                .version("1.0")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("smv")
+                        .about("Emit a NuSMV/nuXmv model of the microstep relation")
+                        .arg(
+                            Arg::with_name("INPUT")
+                                .help("Sets the input file to use")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("ctl")
+                                .long("ctl")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("Adds a CTL property, states are referenced as in(<id>)"),
+                        )
+                        .arg(
+                            Arg::with_name("ltl")
+                                .long("ltl")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("Adds an LTL property, states are referenced as in(<id>)"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("smt")
+                        .about("Emit SMT-LIB2 formulas for init and a bounded number of microsteps")
+                        .arg(
+                            Arg::with_name("INPUT")
+                                .help("Sets the input file to use")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("steps")
+                                .long("steps")
+                                .short("k")
+                                .takes_value(true)
+                                .default_value("1")
+                                .help("Sets the number of microsteps to unroll"),
+                        )
+                        .arg(
+                            Arg::with_name("query")
+                                .long("query")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1)
+                                .help("Asserts that a formula over in(<id>) holds at some step"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("analyze")
+                .about("Analyze statecharts")
+                .version("1.0")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("reachability")
+                        .about("Explore every configuration reachable from init")
+                        .arg(
+                            Arg::with_name("INPUT")
+                                .help("Sets the input file to use")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("max-snapshots")
+                                .long("max-snapshots")
+                                .takes_value(true)
+                                .default_value("10000")
+                                .help("Stops exploring after this many machine snapshots"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("nondeterminism")
+                        .about("Report shadowed and nondeterministic transitions")
+                        .arg(
+                            Arg::with_name("INPUT")
+                                .help("Sets the input file to use")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("livelock")
+                        .about("Report cycles of eventless transitions that may never stabilize")
+                        .arg(
+                            Arg::with_name("INPUT")
+                                .help("Sets the input file to use")
+                                .required(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("verify")
+                        .about("Report derived Core fields that do not match the state tree")
+                        .arg(
+                            Arg::with_name("INPUT")
+                                .help("Sets the input file to use")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("repair")
+                                .long("repair")
+                                .help("Prints the Core with every mismatch repaired"),
+                        ),
                 ),
         )
         .get_matches();
 
     if let Some(matches) = matches.subcommand_matches("compile") {
+        compile(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("graph") {
         let input = matches.value_of("INPUT").unwrap();
-        let mut f = File::open(input).expect("file not found");
+        let core = read_core(input);
 
-        let mut contents = String::new();
-        f.read_to_string(&mut contents)
-            .expect("something went wrong reading the file");
+        let configuration = match matches.values_of("active") {
+            Some(ids) => ids
+                .map(|id| find_state(&core, id))
+                .collect::<Result<_, _>>()
+                .unwrap_or_else(|err| {
+                    eprintln!("--active: {}", err);
+                    std::process::exit(1)
+                }),
+            None => vec![],
+        };
 
-        let statechart: Statechart = serde_json::from_str(&contents).unwrap();
-        let core: Result<Core, _> = statechart.into();
-        let microstep: Result<Microstep, _> = core.unwrap().into();
+        let out = match matches.value_of("format").unwrap() {
+            "dot" => to_dot(&core, &DotOptions { configuration }),
+            "mermaid" => to_mermaid(&core),
+            "plantuml" => to_plantuml(&core),
+            _ => unreachable!(),
+        };
 
-        let out = serde_json::to_string(&microstep.unwrap()).unwrap();
+        print!("{}", out);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("analyze") {
+        analyze(matches);
+    }
 
-        println!("{}", out);
+    if let Some(matches) = matches.subcommand_matches("export") {
+        export(matches);
     }
+
+    if let Some(matches) = matches.subcommand_matches("generate") {
+        let input = matches.value_of("INPUT").unwrap();
+        let core = read_core(input);
+        let options = GenerationOptions {
+            max_snapshots: matches
+                .value_of("max-snapshots")
+                .unwrap()
+                .parse()
+                .expect("invalid --max-snapshots"),
+            state_pairs: matches.is_present("state-pairs"),
+        };
+        let result = or_exit(input, generate_tests(&core, &options));
+
+        if !result.is_complete {
+            eprintln!("warning: exploration stopped early, some scenarios may be missing");
+        }
+        for transition in &result.uncovered_transitions {
+            eprintln!(
+                "warning: no scenario fires {}",
+                describe_transition(&core, *transition)
+            );
+        }
+        for (a, b) in &result.uncovered_pairs {
+            eprintln!(
+                "warning: no scenario reaches {} and {} together",
+                core.state_name(*a),
+                core.state_name(*b)
+            );
+        }
+        for unstable in &result.unstable {
+            eprintln!(
+                "warning: {} does not stabilize: {}\n  {}",
+                unstable.scenario.name,
+                unstable.message,
+                serde_json::to_string(&unstable.scenario.steps).unwrap()
+            );
+        }
+
+        match matches.value_of("out-dir") {
+            Some(dir) => {
+                std::fs::create_dir_all(dir).expect("could not create --out-dir");
+                for scenario in &result.scenarios {
+                    let name: String = scenario
+                        .name
+                        .chars()
+                        .map(|c| {
+                            if c.is_alphanumeric() || c == '-' {
+                                c
+                            } else {
+                                '_'
+                            }
+                        })
+                        .collect();
+                    let path = std::path::Path::new(dir).join(format!("{}.scenario.json", name));
+                    let out = serde_json::to_string_pretty(scenario).unwrap();
+                    std::fs::write(path, out + "\n").expect("could not write scenario");
+                }
+            }
+            None => println!(
+                "{}",
+                serde_json::to_string_pretty(&result.scenarios).unwrap()
+            ),
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("run") {
+        run(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("test") {
+        test(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("convert") {
+        let input = matches.value_of("INPUT").unwrap();
+        let machine = read_machine(input, matches.value_of("from"));
+
+        let out = match matches.value_of("to").unwrap() {
+            "json" => serde_json::to_string(&machine.statechart).unwrap() + "\n",
+            "scxml" => to_scxml(&machine.statechart),
+            "xstate" => to_xstate(&machine) + "\n",
+            _ => unreachable!(),
+        };
+
+        print!("{}", out);
+    }
+}
+
+fn export(matches: &clap::ArgMatches) {
+    if let Some(matches) = matches.subcommand_matches("smv") {
+        let input = matches.value_of("INPUT").unwrap();
+        let core = read_core(input);
+        let properties = |name| match matches.values_of(name) {
+            Some(values) => values.map(String::from).collect(),
+            None => vec![],
+        };
+        let options = SmvOptions {
+            ctl: properties("ctl"),
+            ltl: properties("ltl"),
+        };
+
+        print!("{}", or_exit(input, to_smv(&core, &options)));
+    }
+
+    if let Some(matches) = matches.subcommand_matches("smt") {
+        let input = matches.value_of("INPUT").unwrap();
+        let core = read_core(input);
+        let options = SmtOptions {
+            steps: matches
+                .value_of("steps")
+                .unwrap()
+                .parse()
+                .expect("invalid --steps"),
+            queries: match matches.values_of("query") {
+                Some(values) => values.map(String::from).collect(),
+                None => vec![],
+            },
+        };
+
+        print!("{}", or_exit(input, to_smt(&core, &options)));
+    }
+}
+
+fn analyze(matches: &clap::ArgMatches) {
+    if let Some(matches) = matches.subcommand_matches("reachability") {
+        let input = matches.value_of("INPUT").unwrap();
+        let core = read_core(input);
+        let options = ReachabilityOptions {
+            max_snapshots: matches
+                .value_of("max-snapshots")
+                .unwrap()
+                .parse()
+                .expect("invalid --max-snapshots"),
+        };
+        let result = or_exit(input, reachability(&core, &options));
+
+        println!("reachable configurations: {}", result.configurations);
+        if !result.is_complete {
+            println!(
+                "warning: exploration stopped after {} snapshots, results are incomplete",
+                result.explored
+            );
+        }
+        println!("unreachable states: {}", result.unreachable_states.len());
+        for state in &result.unreachable_states {
+            println!("  {}", core.state_name(*state));
+        }
+        println!("unfired transitions: {}", result.unfired_transitions.len());
+        for transition in &result.unfired_transitions {
+            println!("  {}", describe_transition(&core, *transition));
+        }
+        println!(
+            "livelocking transitions: {}",
+            result.livelock_transitions.len()
+        );
+        for transition in &result.livelock_transitions {
+            println!("  {}", describe_transition(&core, *transition));
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("nondeterminism") {
+        let input = matches.value_of("INPUT").unwrap();
+        let core = read_core(input);
+        let result = nondeterminism(&core);
+
+        println!("shadowed transitions: {}", result.shadowed.len());
+        for shadowed in &result.shadowed {
+            println!(
+                "  {} at {}",
+                describe_transition(&core, shadowed.transition),
+                describe_location(&shadowed.loc)
+            );
+            println!(
+                "    always preempted by {} at {}",
+                describe_transition(&core, shadowed.by),
+                describe_location(&shadowed.by_loc)
+            );
+        }
+        println!("nondeterministic pairs: {}", result.pairs.len());
+        for pair in &result.pairs {
+            println!(
+                "  {} at {}",
+                describe_transition(&core, pair.first),
+                describe_location(&pair.first_loc)
+            );
+            println!(
+                "    and {} at {}",
+                describe_transition(&core, pair.second),
+                describe_location(&pair.second_loc)
+            );
+        }
+    }
+
+    if let Some(matches) = matches.subcommand_matches("verify") {
+        verify(matches);
+    }
+
+    if let Some(matches) = matches.subcommand_matches("livelock") {
+        let input = matches.value_of("INPUT").unwrap();
+        let core = read_core(input);
+        let result = livelocks(&core);
+
+        println!("livelocks: {}", result.cycles.len());
+        for cycle in &result.cycles {
+            println!("  cycle:");
+            for (transition, loc) in cycle.transitions.iter().zip(&cycle.locs) {
+                println!(
+                    "    {} at {}",
+                    describe_transition(&core, *transition),
+                    describe_location(loc)
+                );
+            }
+        }
+    }
+}
+
+fn verify(matches: &clap::ArgMatches) {
+    let input = matches.value_of("INPUT").unwrap();
+    // a Core is read as is, since its derived fields are what is being checked
+    let mut core: Core = if is_core_input(input, None) {
+        or_exit(input, parse_json(&read_source(input)))
+    } else {
+        read_core(input)
+    };
+    let errors = if matches.is_present("repair") {
+        core.repair()
+    } else {
+        core.verify()
+    };
+
+    for err in &errors {
+        eprintln!("{} at {}", err.message, describe_location(&err.loc));
+    }
+    if errors.iter().any(|err| err.fatal) {
+        std::process::exit(1);
+    }
+    if matches.is_present("repair") {
+        println!("{}", serde_json::to_string(&core).unwrap());
+    } else {
+        println!("mismatches: {}", errors.len());
+        if !errors.is_empty() {
+            std::process::exit(1);
+        }
+    }
+}
+
+fn describe_location(loc: &Location) -> String {
+    format!("{}:{}", loc.start.line, loc.start.column)
+}
+
+fn describe_transition(core: &Core, idx: usize) -> String {
+    let transition = &core.transitions[idx];
+    let targets: Vec<_> = transition
+        .targets
+        .iter()
+        .map(|target| core.state_name(*target))
+        .collect();
+    let label = core.transition_label(transition);
+    format!(
+        "#{} {} -> {}{}",
+        idx,
+        core.state_name(transition.source),
+        if targets.is_empty() {
+            "(targetless)".to_string()
+        } else {
+            targets.join(" ")
+        },
+        if label.is_empty() {
+            label
+        } else {
+            format!(" : {}", label)
+        }
+    )
+}
+
+// runs the pipeline up to the stage `--emit` asks for
+fn compile(matches: &clap::ArgMatches) {
+    let input = matches.value_of("INPUT").unwrap();
+    let emit = matches.value_of("emit").unwrap();
+    let mut stages = Stages {
+        is_debug: matches.is_present("debug"),
+        start: Instant::now(),
+    };
+
+    let from = matches.value_of("from");
+    let (core, out) = if is_core_input(input, from) {
+        if let "statechart" | "scxml" | "xstate" = emit {
+            eprintln!("--emit {} needs a statechart, not a Core", emit);
+            std::process::exit(1);
+        }
+        (Some(read_core_as(input, from)), None)
+    } else {
+        let machine = read_machine(input, from);
+        stages.finish("statechart", || {
+            serde_json::to_string(&machine.statechart).unwrap().len()
+        });
+        match emit {
+            "statechart" => (
+                None,
+                Some(serde_json::to_string(&machine.statechart).unwrap() + "\n"),
+            ),
+            "scxml" => (None, Some(to_scxml(&machine.statechart))),
+            "xstate" => (None, Some(to_xstate(&machine) + "\n")),
+            _ => {
+                let core: Result<Core, _> = machine.statechart.into();
+                (Some(or_exit(input, core)), None)
+            }
+        }
+    };
+
+    let out = out.unwrap_or_else(|| {
+        let core = core.unwrap();
+        stages.finish("core", || serde_json::to_string(&core).unwrap().len());
+        let out = match emit {
+            "core" => Some(serde_json::to_string(&core).unwrap() + "\n"),
+            "dot" => Some(to_dot(&core, &DotOptions::default())),
+            "mermaid" => Some(to_mermaid(&core)),
+            "plantuml" => Some(to_plantuml(&core)),
+            "smv" => Some(or_exit(input, to_smv(&core, &SmvOptions::default()))),
+            "smt" => Some(or_exit(input, to_smt(&core, &SmtOptions::default()))),
+            _ => None,
+        };
+
+        out.unwrap_or_else(|| {
+            let microstep: Result<Microstep, _> = core.into();
+            let microstep = or_exit(input, microstep);
+            stages.finish("microstep", || {
+                serde_json::to_string(&microstep).unwrap().len()
+            });
+            serde_json::to_string(&microstep).unwrap() + "\n"
+        })
+    });
+    if emit != "statechart" && emit != "core" && emit != "microstep" {
+        stages.finish(emit, || out.len());
+    }
+
+    match matches.value_of("output") {
+        Some(path) => std::fs::write(path, out).expect("could not write --output"),
+        None => print!("{}", out),
+    }
+}
+
+struct Stages {
+    is_debug: bool,
+    start: Instant,
+}
+
+impl Stages {
+    // prints how long the stage took and how large its JSON is, if `--debug` is set
+    fn finish<F: FnOnce() -> usize>(&mut self, stage: &str, size: F) {
+        if !self.is_debug {
+            return;
+        }
+        let elapsed = self.start.elapsed();
+        eprintln!(
+            "{:<12} {:>10.3}ms {:>10} bytes",
+            stage,
+            elapsed.as_secs_f64() * 1000.0,
+            size()
+        );
+        self.start = Instant::now();
+    }
+}
+
+fn read_source(input: &str) -> String {
+    let mut contents = String::new();
+    let read = if input == "-" {
+        std::io::stdin().read_to_string(&mut contents)
+    } else {
+        File::open(input).and_then(|mut f| f.read_to_string(&mut contents))
+    };
+    if let Err(err) = read {
+        eprintln!("{}: {}", input, err);
+        std::process::exit(1);
+    }
+    contents
+}
+
+fn read_machine(input: &str, format: Option<&str>) -> XState {
+    let contents = read_source(input);
+
+    let format = format.unwrap_or(if input.ends_with(".scxml") {
+        "scxml"
+    } else if input.ends_with(".xstate.json") {
+        "xstate"
+    } else {
+        "json"
+    });
+
+    match format {
+        "scxml" => XState {
+            statechart: or_exit(input, from_scxml(&contents)),
+            ..Default::default()
+        },
+        "xstate" => or_exit(input, from_xstate(&contents)),
+        _ => XState {
+            statechart: or_exit(input, parse_json(&contents)),
+            ..Default::default()
+        },
+    }
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(contents: &str) -> Result<T, Errors> {
+    serde_json::from_str(contents).map_err(json_errors)
+}
+
+fn json_errors(err: serde_json::Error) -> Errors {
+    let point = Point {
+        line: err.line(),
+        column: err.column(),
+    };
+    vec![ConversionError {
+        message: err.to_string(),
+        fatal: true,
+        source: "thot_cli".to_string(),
+        loc: Location {
+            start: point,
+            end: point,
+            source: None,
+        },
+    }]
+}
+
+// the value, or one `file:line: message` line per error on stderr and exit code 1
+fn or_exit<T>(input: &str, result: Result<T, Errors>) -> T {
+    result.unwrap_or_else(|errors| {
+        for err in &errors {
+            eprintln!("{}:{}: {}", input, err.loc.start.line, err.message);
+        }
+        std::process::exit(1)
+    })
+}
+
+const RUN_HELP: &str = "commands:
+  send <event>              dispatches an external event
+  set <condition> <bool>    sets the value a condition evaluates to
+  step                      takes one eventless or internal microstep
+  history                   lists the commands so far
+  undo                      reverts the last command
+  reset                     restarts the machine
+  quit";
+
+fn run(matches: &clap::ArgMatches) {
+    let input = matches.value_of("INPUT").unwrap();
+    let core = read_core(input);
+    let microstep: Result<Microstep, _> = core.clone().into();
+    let microstep = or_exit(input, microstep);
+    let mut simulator = Simulator::new(&core, &microstep);
+
+    let result = simulator.start();
+    print_changes(&core, &simulator, result);
+    let stdin = std::io::stdin();
+    loop {
+        print!("> ");
+        std::io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            println!();
+            break;
+        }
+        let words: Vec<_> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => {}
+            ["send", event] => match parse_id(event, "e") {
+                Some(event) => {
+                    let result = simulator.send(event);
+                    print_changes(&core, &simulator, result)
+                }
+                None => println!("invalid event: {}", event),
+            },
+            ["set", condition, value] => match (parse_id(condition, "c"), value.parse()) {
+                (Some(condition), Ok(value)) => simulator.set(condition, value),
+                _ => println!("usage: set <condition> true|false"),
+            },
+            ["step"] => {
+                let result = simulator.step();
+                match result {
+                    Ok(ref changes) if changes.is_empty() => println!("stable"),
+                    _ => print_changes(&core, &simulator, result),
+                }
+            }
+            ["history"] => {
+                for (idx, entry) in simulator.history().iter().enumerate() {
+                    println!(
+                        "{:>3}  {:<24} {}",
+                        idx + 1,
+                        entry.command,
+                        describe_active(&core, &entry.active)
+                    );
+                }
+                let conditions: Vec<_> = simulator
+                    .conditions()
+                    .iter()
+                    .map(|(id, value)| format!("c{}={}", id, value))
+                    .collect();
+                if !conditions.is_empty() {
+                    println!("conditions: {}", conditions.join(" "));
+                }
+            }
+            ["undo"] => match simulator.undo() {
+                Some(command) => {
+                    println!("undid {}", command);
+                    println!("{}", describe_active(&core, &simulator.active_states()));
+                }
+                None => println!("nothing to undo"),
+            },
+            ["reset"] => {
+                let result = simulator.reset();
+                print_changes(&core, &simulator, result)
+            }
+            ["quit"] | ["exit"] => break,
+            _ => println!("{}", RUN_HELP),
+        }
+    }
+}
+
+// accepts `3` as well as the prefixed `e3`, `c3` or `#3`
+fn parse_id(word: &str, prefix: &str) -> Option<usize> {
+    word.trim_start_matches(prefix)
+        .trim_start_matches('#')
+        .parse()
+        .ok()
+}
+
+fn describe_active(core: &Core, active: &[usize]) -> String {
+    let names: Vec<_> = active
+        .iter()
+        .filter(|state| **state != 0)
+        .map(|state| core.state_name(*state))
+        .collect();
+    format!("active: {}", names.join(", "))
+}
+
+fn print_changes(core: &Core, simulator: &Simulator, result: Result<Vec<Changes>, RuntimeError>) {
+    let steps = match result {
+        Ok(steps) => steps,
+        Err(err) => {
+            println!("error: {}", err.message);
+            return;
+        }
+    };
+    for changes in &steps {
+        for state in &changes.exited {
+            for executable in &core.states[*state].on_exit {
+                println!(
+                    "  exit {}: {}",
+                    core.state_name(*state),
+                    core.executable_label(executable)
+                );
+            }
+        }
+        for transition in &changes.transitions {
+            println!("  take {}", describe_transition(core, *transition));
+            for executable in &core.transitions[*transition].on_transition {
+                println!("    {}", core.executable_label(executable));
+            }
+        }
+        for state in &changes.entered {
+            if changes.initialized.contains(state) {
+                for executable in &core.states[*state].on_init {
+                    println!(
+                        "  init {}: {}",
+                        core.state_name(*state),
+                        core.executable_label(executable)
+                    );
+                }
+            }
+            for executable in &core.states[*state].on_enter {
+                println!(
+                    "  enter {}: {}",
+                    core.state_name(*state),
+                    core.executable_label(executable)
+                );
+            }
+        }
+    }
+    println!("{}", describe_active(core, &simulator.active_states()));
+}
+
+fn test(matches: &clap::ArgMatches) {
+    let input = matches.value_of("INPUT").unwrap();
+    let core = read_core(input);
+    let microstep: Result<Microstep, _> = core.clone().into();
+    let microstep = or_exit(input, microstep);
+    let mut coverage = Coverage::new(&core);
+
+    let mut failed = 0;
+    let mut passed = 0;
+    for path in matches.values_of("SCENARIO").unwrap() {
+        let source = read_source(path);
+        let scenarios = or_exit(path, parse_scenarios(&source).map_err(json_errors));
+
+        for (scenario, lines) in scenarios {
+            let failure =
+                match run_scenario_with_coverage(&core, &microstep, &scenario, &mut coverage) {
+                    Ok(()) => {
+                        println!("ok {}", scenario.name);
+                        passed += 1;
+                        continue;
+                    }
+                    Err(failure) => failure,
+                };
+            failed += 1;
+            println!("FAILED {}", scenario.name);
+            match lines.get(failure.step) {
+                Some(line) => println!(
+                    "  {}:{}: step {}: {}",
+                    path, line, failure.step, failure.message
+                ),
+                None => println!("  {}: step {}: {}", path, failure.step, failure.message),
+            }
+            if !failure.expected.is_empty() || !failure.actual.is_empty() {
+                println!("  expected: [{}]", failure.expected.join(", "));
+                println!("  actual:   [{}]", failure.actual.join(", "));
+            }
+            if failure.kind == ScenarioFailureKind::UnexpectedConfiguration {
+                for state in &failure.expected {
+                    if !failure.actual.contains(state) {
+                        println!("  - {} is not active", state);
+                    }
+                }
+                for state in &failure.actual {
+                    if !failure.expected.contains(state) {
+                        println!("  + {} is active", state);
+                    }
+                }
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", passed, failed);
+    if let Some(path) = matches.value_of("coverage") {
+        let out = match matches.value_of("coverage-format").unwrap() {
+            "lcov" => coverage.to_lcov(&core, input),
+            "json" => serde_json::to_string_pretty(&coverage.summary(&core)).unwrap() + "\n",
+            _ => unreachable!(),
+        };
+        std::fs::write(path, out).expect("could not write coverage");
+    }
+    if failed > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn is_core_input(input: &str, format: Option<&str>) -> bool {
+    format == Some("core") || (format.is_none() && input.ends_with(".core.json"))
+}
+
+fn read_core(input: &str) -> Core {
+    read_core_as(input, None)
+}
+
+// reads a statechart and converts it, or reads a serialized Core directly
+fn read_core_as(input: &str, format: Option<&str>) -> Core {
+    if is_core_input(input, format) {
+        return or_exit(input, from_json(&read_source(input)));
+    }
+    let core: Result<Core, _> = read_machine(input, format).statechart.into();
+    or_exit(input, core)
+}
+
+fn find_state(core: &Core, id: &str) -> Result<usize, String> {
+    core.states
+        .iter()
+        .position(|state| state.id.as_deref() == Some(id))
+        .ok_or_else(|| format!("unknown state: {:?}", id))
 }
@@ -0,0 +1,252 @@
+use analysis::nondeterminism::{are_exclusive, is_selectable};
+use ast::core::{Condition, Core, In, StateId, Transition, TransitionId};
+use ast::location::Location;
+use std::collections::{HashSet, VecDeque};
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Livelocks {
+    pub cycles: Vec<Livelock>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Livelock {
+    pub transitions: Vec<TransitionId>,
+    pub locs: Vec<Location>,
+}
+
+// finds cycles of eventless transitions that can keep firing without ever reaching a stable configuration
+pub fn livelocks(core: &Core) -> Livelocks {
+    let nodes: Vec<&Transition> = core
+        .transitions
+        .iter()
+        .filter(|transition| transition.event.is_none() && can_fire(core, transition))
+        .collect();
+
+    let edges: Vec<Vec<usize>> = nodes
+        .iter()
+        .map(|transition| {
+            let after = active_after(core, transition);
+            nodes
+                .iter()
+                .enumerate()
+                .filter(|(_, next)| after.contains(&next.source))
+                .map(|(idx, _)| idx)
+                .collect()
+        })
+        .collect();
+
+    let mut result = Livelocks::default();
+    for component in components(&edges) {
+        let cycle = match shortest_cycle(&edges, &component) {
+            Some(cycle) => cycle,
+            None => continue,
+        };
+        let transitions: Vec<_> = cycle.iter().map(|idx| nodes[*idx]).collect();
+        if has_exclusive_guards(core, &transitions) {
+            continue;
+        }
+        result.cycles.push(Livelock {
+            transitions: transitions.iter().map(|t| t.idx).collect(),
+            locs: transitions.iter().map(|t| t.loc).collect(),
+        });
+    }
+    result
+}
+
+fn can_fire(core: &Core, transition: &Transition) -> bool {
+    if !is_selectable(core, transition) {
+        return false;
+    }
+    match transition.condition {
+        Some(ref condition) => {
+            let source = Condition::In(In {
+                state: transition.source,
+                loc: transition.loc,
+            });
+            !are_exclusive(core, condition, &source)
+        }
+        None => true,
+    }
+}
+
+// over-approximates the states that `transition` can leave active along its own branch;
+// orthogonal regions are left out since firing in one region never enables another
+fn active_after(core: &Core, transition: &Transition) -> HashSet<StateId> {
+    let mut states = HashSet::new();
+    for target in &transition.targets {
+        let target = &core.states[*target];
+        states.insert(target.idx);
+        states.extend(target.ancestors.iter().cloned());
+        states.extend(target.descendants.iter().cloned());
+    }
+    let source = &core.states[transition.source];
+    let branch = source
+        .ancestors
+        .iter()
+        .chain(&source.descendants)
+        .chain(Some(&source.idx));
+    for state in branch {
+        if !transition.exits.contains(state) {
+            states.insert(*state);
+        }
+    }
+    states
+}
+
+fn has_exclusive_guards(core: &Core, transitions: &[&Transition]) -> bool {
+    transitions.iter().enumerate().any(|(i, a)| {
+        transitions[i + 1..]
+            .iter()
+            .any(|b| match (&a.condition, &b.condition) {
+                (Some(a), Some(b)) => are_exclusive(core, a, b),
+                _ => false,
+            })
+    })
+}
+
+// strongly connected components, ordered by their smallest member
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    struct Tarjan<'a> {
+        edges: &'a [Vec<usize>],
+        index: Vec<Option<usize>>,
+        lowlink: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        next: usize,
+        components: Vec<Vec<usize>>,
+    }
+
+    impl<'a> Tarjan<'a> {
+        fn visit(&mut self, node: usize) {
+            self.index[node] = Some(self.next);
+            self.lowlink[node] = self.next;
+            self.next += 1;
+            self.stack.push(node);
+            self.on_stack[node] = true;
+
+            for &next in &self.edges[node] {
+                match self.index[next] {
+                    None => {
+                        self.visit(next);
+                        self.lowlink[node] = self.lowlink[node].min(self.lowlink[next]);
+                    }
+                    Some(index) if self.on_stack[next] => {
+                        self.lowlink[node] = self.lowlink[node].min(index);
+                    }
+                    _ => {}
+                }
+            }
+
+            if Some(self.lowlink[node]) == self.index[node] {
+                let mut component = vec![];
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack[member] = false;
+                    component.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                component.sort();
+                self.components.push(component);
+            }
+        }
+    }
+
+    let mut tarjan = Tarjan {
+        edges,
+        index: vec![None; edges.len()],
+        lowlink: vec![0; edges.len()],
+        on_stack: vec![false; edges.len()],
+        stack: vec![],
+        next: 0,
+        components: vec![],
+    };
+    for node in 0..edges.len() {
+        if tarjan.index[node].is_none() {
+            tarjan.visit(node);
+        }
+    }
+    let mut components = tarjan.components;
+    components.sort();
+    components
+}
+
+// the shortest cycle through the first node of the component, if there is one
+fn shortest_cycle(edges: &[Vec<usize>], component: &[usize]) -> Option<Vec<usize>> {
+    let start = component[0];
+    let mut parents = vec![None; edges.len()];
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+
+    while let Some(node) = queue.pop_front() {
+        for &next in &edges[node] {
+            if next == start {
+                let mut cycle = vec![node];
+                let mut idx = node;
+                while let Some(parent) = parents[idx] {
+                    cycle.push(parent);
+                    idx = parent;
+                }
+                cycle.reverse();
+                return Some(cycle);
+            }
+            if component.contains(&next) && parents[next].is_none() && next != start {
+                parents[next] = Some(node);
+                queue.push_back(next);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::{self, Statechart};
+
+    fn cycles(core: &Core) -> Vec<Vec<TransitionId>> {
+        livelocks(core)
+            .cycles
+            .into_iter()
+            .map(|cycle| cycle.transitions)
+            .collect()
+    }
+
+    #[test]
+    fn eventless_cycles_are_reported() {
+        let core = Statechart::builder()
+            .state("a", |s| s.always("b"))
+            .state("b", |s| s.always("c"))
+            .state("c", |s| s.on(0, "a").always("a"))
+            .build_core()
+            .unwrap();
+        assert_eq!(cycles(&core), vec![vec![0, 1, 3]]);
+    }
+
+    #[test]
+    fn cycles_through_events_are_not_livelocks() {
+        let core = Statechart::builder()
+            .state("a", |s| s.always("b"))
+            .state("b", |s| s.on(0, "a"))
+            .build_core()
+            .unwrap();
+        assert!(cycles(&core).is_empty());
+    }
+
+    #[test]
+    fn cycles_with_exclusive_guards_are_dropped() {
+        let not = statechart::Condition::Not(statechart::Not {
+            condition: Box::new(statechart::Condition::Predicate(statechart::Predicate {
+                id: 1,
+                ..Default::default()
+            })),
+            loc: Location::default(),
+        });
+        let core = Statechart::builder()
+            .state("a", |s| s.transition(|t| t.target("b").when(1)))
+            .state("b", |s| s.transition(|t| t.target("a").condition(not)))
+            .build_core()
+            .unwrap();
+        assert!(cycles(&core).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+use ast::core::{Condition, Core, StateId, StateType, Transition, TransitionId, TransitionType};
+use ast::location::Location;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Nondeterminism {
+    pub shadowed: Vec<Shadowed>,
+    pub pairs: Vec<NondeterministicPair>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Shadowed {
+    pub transition: TransitionId,
+    pub by: TransitionId,
+    pub loc: Location,
+    pub by_loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NondeterministicPair {
+    pub first: TransitionId,
+    pub second: TransitionId,
+    pub first_loc: Location,
+    pub second_loc: Location,
+}
+
+// transitions are selected in index order, so a lower index wins a conflict
+pub fn nondeterminism(core: &Core) -> Nondeterminism {
+    let mut result = Nondeterminism::default();
+
+    for second in &core.transitions {
+        if !is_selectable(core, second) {
+            continue;
+        }
+
+        let mut is_shadowed = false;
+        for first in &core.transitions[..second.idx] {
+            if !is_selectable(core, first)
+                || first.event != second.event
+                || !first.conflicts.contains(&second.idx)
+            {
+                continue;
+            }
+
+            if is_always_active(core, first.source, second.source)
+                && implies(core, &second.condition, &first.condition)
+            {
+                result.shadowed.push(Shadowed {
+                    transition: second.idx,
+                    by: first.idx,
+                    loc: second.loc,
+                    by_loc: first.loc,
+                });
+                is_shadowed = true;
+                break;
+            }
+        }
+        if is_shadowed {
+            continue;
+        }
+
+        for first in &core.transitions[..second.idx] {
+            if !is_selectable(core, first)
+                || first.event != second.event
+                || !first.conflicts.contains(&second.idx)
+                || !is_coactive(core, first.source, second.source)
+                || is_exclusive(core, &first.condition, &second.condition)
+            {
+                continue;
+            }
+
+            result.pairs.push(NondeterministicPair {
+                first: first.idx,
+                second: second.idx,
+                first_loc: first.loc,
+                second_loc: second.loc,
+            });
+        }
+    }
+
+    result
+}
+
+pub fn is_selectable(core: &Core, transition: &Transition) -> bool {
+    match transition.t {
+        TransitionType::Initial | TransitionType::History => false,
+        _ => is_proper(core, transition.source),
+    }
+}
+
+// returns true if `state` is active in every configuration that contains `other`
+pub fn is_always_active(core: &Core, state: StateId, other: StateId) -> bool {
+    if state == other || core.states[other].ancestors.contains(&state) {
+        return true;
+    }
+    if !core.states[state].ancestors.contains(&other) {
+        return false;
+    }
+
+    // every step down from `other` must be forced by a parallel or a single child
+    let mut idx = state;
+    while idx != other {
+        let parent = &core.states[core.states[idx].parent];
+        let is_forced = parent.t == StateType::Parallel
+            || parent
+                .children
+                .iter()
+                .filter(|child| is_proper(core, **child))
+                .count()
+                == 1;
+        if !is_forced {
+            return false;
+        }
+        idx = parent.idx;
+    }
+    true
+}
+
+// returns true if `a` and `b` can be active in the same configuration
+pub fn is_coactive(core: &Core, a: StateId, b: StateId) -> bool {
+    let (a_state, b_state) = (&core.states[a], &core.states[b]);
+    if a == b || a_state.ancestors.contains(&b) || b_state.ancestors.contains(&a) {
+        return true;
+    }
+
+    let lca = a_state
+        .ancestors
+        .iter()
+        .rev()
+        .find(|ancestor| b_state.ancestors.contains(ancestor));
+    match lca {
+        Some(lca) => core.states[*lca].t == StateType::Parallel,
+        None => false,
+    }
+}
+
+pub fn is_proper(core: &Core, idx: StateId) -> bool {
+    !matches!(
+        core.states[idx].t,
+        StateType::Initial | StateType::HistoryShallow | StateType::HistoryDeep
+    )
+}
+
+// returns true if `condition` holding guarantees `other` holds
+fn implies(core: &Core, condition: &Option<Condition>, other: &Option<Condition>) -> bool {
+    match (condition, other) {
+        (_, None) => true,
+        (None, Some(_)) => false,
+        (Some(condition), Some(other)) => {
+            if is_same(core, condition, other) {
+                return true;
+            }
+            match condition {
+                Condition::And(node) => node
+                    .children
+                    .iter()
+                    .any(|child| is_same(core, child, other)),
+                _ => false,
+            }
+        }
+    }
+}
+
+// returns true if the conditions can never hold at the same time
+fn is_exclusive(core: &Core, a: &Option<Condition>, b: &Option<Condition>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => are_exclusive(core, a, b),
+        _ => false,
+    }
+}
+
+pub fn are_exclusive(core: &Core, a: &Condition, b: &Condition) -> bool {
+    match (a, b) {
+        (Condition::Not(node), other) | (other, Condition::Not(node))
+            if is_same(core, &node.condition, other) =>
+        {
+            true
+        }
+        (Condition::In(a), Condition::In(b)) => !is_coactive(core, a.state, b.state),
+        (Condition::And(node), other) | (other, Condition::And(node)) => node
+            .children
+            .iter()
+            .any(|child| are_exclusive(core, child, other)),
+        _ => false,
+    }
+}
+
+fn is_same(core: &Core, a: &Condition, b: &Condition) -> bool {
+    core.condition_label(a) == core.condition_label(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::{self, Statechart};
+
+    fn shadowed(result: &Nondeterminism) -> Vec<(TransitionId, TransitionId)> {
+        result
+            .shadowed
+            .iter()
+            .map(|s| (s.transition, s.by))
+            .collect()
+    }
+
+    fn pairs(result: &Nondeterminism) -> Vec<(TransitionId, TransitionId)> {
+        result.pairs.iter().map(|p| (p.first, p.second)).collect()
+    }
+
+    fn predicate(id: usize) -> statechart::Condition {
+        statechart::Condition::Predicate(statechart::Predicate {
+            id,
+            ..Default::default()
+        })
+    }
+
+    fn in_state(state: &str) -> statechart::Condition {
+        statechart::Condition::In(statechart::In {
+            state: state.to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn not(condition: statechart::Condition) -> statechart::Condition {
+        statechart::Condition::Not(statechart::Not {
+            condition: Box::new(condition),
+            loc: Location::default(),
+        })
+    }
+
+    #[test]
+    fn siblings_are_shadowed_by_weaker_guards() {
+        let core = Statechart::builder()
+            .state("a", |s| {
+                s.on(0, "b")
+                    .transition(|t| t.event(0).target("c").when(1))
+                    .transition(|t| t.event(1).target("b").when(1))
+                    .transition(|t| t.event(1).target("c"))
+            })
+            .atomic("b")
+            .atomic("c")
+            .build_core()
+            .unwrap();
+        let result = nondeterminism(&core);
+        assert_eq!(shadowed(&result), vec![(1, 0)]);
+        // the guarded transition comes first, so the other one still fires without it
+        assert_eq!(pairs(&result), vec![(2, 3)]);
+    }
+
+    #[test]
+    fn children_preempt_their_parents() {
+        let core = Statechart::builder()
+            .state("p", |s| s.state("a", |s| s.on(0, "b")).on(0, "b"))
+            .atomic("b")
+            .build_core()
+            .unwrap();
+        let result = nondeterminism(&core);
+        assert_eq!(shadowed(&result), vec![(1, 0)]);
+        assert!(result.pairs.is_empty());
+    }
+
+    #[test]
+    fn parallel_regions_are_pairs() {
+        let core = Statechart::builder()
+            .parallel("p", |p| {
+                p.state("r1", |s| s.on(0, "out"))
+                    .state("r2", |s| s.on(0, "out"))
+            })
+            .atomic("out")
+            .build_core()
+            .unwrap();
+        let result = nondeterminism(&core);
+        assert!(result.shadowed.is_empty());
+        assert_eq!(pairs(&result), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn exclusive_guards_are_not_pairs() {
+        let core = Statechart::builder()
+            .parallel("p", |p| {
+                p.state("a", |s| {
+                    s.transition(|t| t.event(0).target("out").when(1))
+                        .transition(|t| t.event(0).target("out").condition(not(predicate(1))))
+                        .transition(|t| t.event(1).target("out").condition(in_state("x1")))
+                        .transition(|t| t.event(1).target("out").condition(in_state("x2")))
+                })
+                .state("x", |s| s.atomic("x1").atomic("x2"))
+            })
+            .atomic("out")
+            .build_core()
+            .unwrap();
+        let result = nondeterminism(&core);
+        assert!(result.shadowed.is_empty());
+        assert!(result.pairs.is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::core::{Core, StateId, StateType, TransitionId};
+use ast::microstep::Microstep;
+use runtime::choice::each_choice;
+use runtime::interpreter::{active, Interpreter, RuntimeError, Snapshot, Step};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ReachabilityOptions {
+    // caps the distinct snapshots explored, counting datamodel and queue changes
+    // and unstable snapshots, so fewer configurations may be reached
+    pub max_snapshots: usize,
+}
+
+impl Default for ReachabilityOptions {
+    fn default() -> ReachabilityOptions {
+        ReachabilityOptions {
+            max_snapshots: 10_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Reachability {
+    pub configurations: usize,
+    pub explored: usize,
+    pub is_complete: bool,
+    pub unreachable_states: Vec<StateId>,
+    pub unfired_transitions: Vec<TransitionId>,
+    // transitions on microstep cycles that no condition outcome leads out of
+    pub livelock_transitions: Vec<TransitionId>,
+}
+
+pub fn reachability(core: &Core, options: &ReachabilityOptions) -> Result<Reachability, Errors> {
+    let microstep: Result<Microstep, Errors> = core.clone().into();
+    let microstep = microstep?;
+    let mut explorer = Explorer {
+        events: core.events(),
+        max_snapshots: options.max_snapshots,
+        visited: HashMap::new(),
+        is_stable: vec![],
+        unstable_steps: vec![],
+        configurations: HashSet::new(),
+        entered: vec![false; core.states.len()],
+        fired: vec![false; core.transitions.len()],
+        pending: VecDeque::new(),
+        is_complete: true,
+    };
+    explorer.explore(&microstep).map_err(|err| {
+        vec![ConversionError {
+            message: err.message,
+            fatal: true,
+            source: "analysis/reachability".to_string(),
+            loc: err.loc,
+        }]
+    })?;
+
+    let unreachable_states = core
+        .states
+        .iter()
+        .filter(|state| match state.t {
+            StateType::Initial | StateType::HistoryShallow | StateType::HistoryDeep => false,
+            _ => !explorer.entered[state.idx],
+        })
+        .map(|state| state.idx)
+        .collect();
+
+    // history defaults are not compiled to microsteps yet so they are left out
+    let unfired_transitions = core
+        .transitions
+        .iter()
+        .filter(|transition| match core.states[transition.source].t {
+            StateType::HistoryShallow | StateType::HistoryDeep => false,
+            _ => !explorer.fired[transition.idx],
+        })
+        .map(|transition| transition.idx)
+        .collect();
+
+    // an incomplete search leaves snapshots unexplored, which would look stuck
+    let livelock_transitions = if explorer.is_complete {
+        explorer.livelock_transitions()
+    } else {
+        vec![]
+    };
+
+    Ok(Reachability {
+        configurations: explorer.configurations.len(),
+        explored: explorer.visited.len(),
+        is_complete: explorer.is_complete,
+        unreachable_states,
+        unfired_transitions,
+        livelock_transitions,
+    })
+}
+
+struct Explorer<'a> {
+    events: Vec<usize>,
+    max_snapshots: usize,
+    visited: HashMap<(Snapshot, bool), usize>,
+    is_stable: Vec<bool>,
+    // the microsteps out of unstable snapshots, as (from, to, fired transitions)
+    unstable_steps: Vec<(usize, usize, Vec<TransitionId>)>,
+    configurations: HashSet<Vec<bool>>,
+    entered: Vec<bool>,
+    fired: Vec<bool>,
+    pending: VecDeque<(Interpreter<'a>, usize)>,
+    is_complete: bool,
+}
+
+impl<'a> Explorer<'a> {
+    fn explore(&mut self, microstep: &'a Microstep) -> Result<(), RuntimeError> {
+        let starts = each_choice(|host| {
+            let mut interpreter = Interpreter::new(microstep);
+            let step = interpreter.init(host)?;
+            Ok((interpreter, step))
+        })?;
+        for (interpreter, step) in starts {
+            self.record(&step);
+            self.push(interpreter, false);
+        }
+
+        while let Some((interpreter, from)) = self.pending.pop_front() {
+            if !self.is_complete {
+                break;
+            }
+
+            if self.is_stable[from] {
+                for event in self.events.clone() {
+                    let successors = each_choice(|host| {
+                        let mut next = interpreter.clone();
+                        let step = next.microstep(Some(event), host)?;
+                        Ok((next, step))
+                    })?;
+                    for (next, step) in successors {
+                        if !step.is_stable {
+                            self.record(&step);
+                            self.push(next, false);
+                        }
+                    }
+                }
+            } else {
+                let successors = each_choice(|host| {
+                    let mut next = interpreter.clone();
+                    let step = next.step(host)?;
+                    Ok((next, step))
+                })?;
+                for (next, step) in successors {
+                    let (to, transitions) = match step {
+                        Some(step) => {
+                            self.record(&step);
+                            (self.push(next, false), step.transitions)
+                        }
+                        None => (self.push(next, true), vec![]),
+                    };
+                    if let Some(to) = to {
+                        self.unstable_steps.push((from, to, transitions));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn record(&mut self, step: &Step) {
+        for state in active(&step.configuration) {
+            self.entered[state] = true;
+        }
+        for transition in &step.transitions {
+            if let Some(fired) = self.fired.get_mut(*transition) {
+                *fired = true;
+            }
+        }
+    }
+
+    // returns the index of the snapshot, unless the limit stopped the search
+    fn push(&mut self, interpreter: Interpreter<'a>, is_stable: bool) -> Option<usize> {
+        let key = (interpreter.snapshot(), is_stable);
+        if let Some(idx) = self.visited.get(&key) {
+            return Some(*idx);
+        }
+        if self.visited.len() >= self.max_snapshots {
+            self.is_complete = false;
+            return None;
+        }
+        let idx = self.is_stable.len();
+        self.visited.insert(key, idx);
+        self.is_stable.push(is_stable);
+        if is_stable {
+            self.configurations
+                .insert(interpreter.configuration().to_vec());
+        }
+        self.pending.push_back((interpreter, idx));
+        Some(idx)
+    }
+
+    // the transitions between unstable snapshots that cannot reach a stable one
+    fn livelock_transitions(&self) -> Vec<TransitionId> {
+        let mut predecessors = vec![vec![]; self.is_stable.len()];
+        for (from, to, _) in &self.unstable_steps {
+            predecessors[*to].push(*from);
+        }
+        let mut can_stabilize = self.is_stable.clone();
+        let mut pending: Vec<_> = (0..can_stabilize.len())
+            .filter(|idx| can_stabilize[*idx])
+            .collect();
+        while let Some(idx) = pending.pop() {
+            for from in &predecessors[idx] {
+                if !can_stabilize[*from] {
+                    can_stabilize[*from] = true;
+                    pending.push(*from);
+                }
+            }
+        }
+
+        let mut transitions: Vec<_> = self
+            .unstable_steps
+            .iter()
+            .filter(|(from, to, _)| !can_stabilize[*from] && !can_stabilize[*to])
+            .flat_map(|(_, _, transitions)| transitions.iter().cloned())
+            .collect();
+        transitions.sort();
+        transitions.dedup();
+        transitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    #[test]
+    fn max_snapshots_stops_the_search() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b"))
+            .state("b", |s| s.on(0, "a"))
+            .atomic("c")
+            .build_core()
+            .unwrap();
+
+        let result = reachability(&core, &ReachabilityOptions::default()).unwrap();
+        assert!(result.is_complete);
+        assert_eq!(result.configurations, 2);
+        assert_eq!(result.unreachable_states, vec![3]);
+
+        // the unstable snapshots after init and after leaving a count too
+        let options = ReachabilityOptions { max_snapshots: 3 };
+        let result = reachability(&core, &options).unwrap();
+        assert!(!result.is_complete);
+        assert_eq!(result.configurations, 1);
+    }
+
+    #[test]
+    fn only_inescapable_cycles_are_livelocks() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b").on(1, "d"))
+            .state("b", |s| s.always("c"))
+            .state("c", |s| s.always("b"))
+            .state("d", |s| s.transition(|t| t.target("e").when(0)))
+            .state("e", |s| s.always("d"))
+            .build_core()
+            .unwrap();
+
+        let result = reachability(&core, &ReachabilityOptions::default()).unwrap();
+        assert_eq!(result.livelock_transitions, vec![2, 3]);
+    }
+}
@@ -0,0 +1,352 @@
+use analysis::nondeterminism::{is_coactive, is_proper};
+use ast::conversion_error::{ConversionError, Errors};
+use ast::core::{Core, StateId, StateType, TransitionId};
+use ast::microstep::Microstep;
+use runtime::choice::each_consistent_choice;
+use runtime::interpreter::{active, Interpreter, RuntimeError, Snapshot, Step};
+use runtime::scenario::{expected_states, Scenario, ScenarioStep};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+#[derive(Clone, Copy, Debug)]
+pub struct GenerationOptions {
+    // caps the distinct snapshots explored, see ReachabilityOptions
+    pub max_snapshots: usize,
+    pub state_pairs: bool,
+}
+
+impl Default for GenerationOptions {
+    fn default() -> GenerationOptions {
+        GenerationOptions {
+            max_snapshots: 10_000,
+            state_pairs: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct GeneratedTests {
+    pub scenarios: Vec<Scenario>,
+    pub is_complete: bool,
+    pub uncovered_transitions: Vec<TransitionId>,
+    pub uncovered_pairs: Vec<(StateId, StateId)>,
+    pub unstable: Vec<UnstableScenario>,
+}
+
+// a scenario whose last macrostep fails to stabilize, with the interpreter's error
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct UnstableScenario {
+    pub scenario: Scenario,
+    pub message: String,
+    pub transitions: Vec<TransitionId>,
+}
+
+// explores stable configurations breadth first and picks the shortest scenario for every
+// transition, and optionally every pair of states that are active together
+pub fn generate_tests(core: &Core, options: &GenerationOptions) -> Result<GeneratedTests, Errors> {
+    let microstep: Result<Microstep, Errors> = core.clone().into();
+    let microstep = microstep?;
+    let mut graph = Graph {
+        core,
+        events: core.events(),
+        max_snapshots: options.max_snapshots,
+        nodes: vec![],
+        edges: vec![],
+        visited: HashMap::new(),
+        failures: vec![],
+        is_complete: true,
+    };
+    graph.explore(&microstep).map_err(|err| {
+        vec![ConversionError {
+            message: err.message,
+            fatal: true,
+            source: "analysis/test_generation".to_string(),
+            loc: err.loc,
+        }]
+    })?;
+
+    let mut result = GeneratedTests {
+        is_complete: graph.is_complete,
+        ..Default::default()
+    };
+
+    // history defaults are not compiled to microsteps yet so they are left out
+    let mut covered = vec![false; core.transitions.len()];
+    for transition in &core.transitions {
+        let is_history = matches!(
+            core.states[transition.source].t,
+            StateType::HistoryShallow | StateType::HistoryDeep
+        );
+        if covered[transition.idx] || is_history {
+            continue;
+        }
+        let edge = graph
+            .edges
+            .iter()
+            .position(|edge| edge.fired.contains(&transition.idx));
+        match edge {
+            Some(edge) => {
+                let path = graph.path(edge);
+                for idx in &path {
+                    for fired in &graph.edges[*idx].fired {
+                        covered[*fired] = true;
+                    }
+                }
+                result.scenarios.push(Scenario {
+                    name: format!("transition-{}", transition.idx),
+                    steps: graph.steps(&path),
+                });
+            }
+            None => result.uncovered_transitions.push(transition.idx),
+        }
+    }
+
+    for (idx, (from, step, err)) in graph.failures.iter().enumerate() {
+        let mut steps = match from {
+            Some(node) => graph.steps(&graph.path(graph.nodes[*node].parent.unwrap())),
+            None => vec![],
+        };
+        steps.push(step.clone());
+        result.unstable.push(UnstableScenario {
+            scenario: Scenario {
+                name: format!("unstable-{}", idx),
+                steps,
+            },
+            message: err.message.clone(),
+            transitions: err.path.clone(),
+        });
+    }
+
+    if options.state_pairs {
+        let states: Vec<_> = core
+            .states
+            .iter()
+            .filter(|state| state.idx != 0 && is_proper(core, state.idx))
+            .map(|state| state.idx)
+            .collect();
+        let mut covered = BTreeSet::new();
+        for (i, a) in states.iter().enumerate() {
+            for b in &states[i + 1..] {
+                if covered.contains(&(*a, *b)) || core.states[*b].ancestors.contains(a) {
+                    continue;
+                }
+                let edge = graph.edges.iter().position(|edge| {
+                    let configuration = &graph.nodes[edge.to].configuration;
+                    configuration[*a] && configuration[*b]
+                });
+                match edge {
+                    Some(edge) => {
+                        let path = graph.path(edge);
+                        for idx in &path {
+                            let states = active(&graph.nodes[graph.edges[*idx].to].configuration);
+                            for (i, a) in states.iter().enumerate() {
+                                for b in &states[i + 1..] {
+                                    covered.insert((*a, *b));
+                                }
+                            }
+                        }
+                        result.scenarios.push(Scenario {
+                            name: format!("states-{}-{}", core.state_name(*a), core.state_name(*b)),
+                            steps: graph.steps(&path),
+                        });
+                    }
+                    None if is_coactive(core, *a, *b) => result.uncovered_pairs.push((*a, *b)),
+                    None => {}
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+struct Node<'a> {
+    interpreter: Interpreter<'a>,
+    configuration: Vec<bool>,
+    // the first edge that reached this node, which is on a shortest path
+    parent: Option<usize>,
+}
+
+struct Edge {
+    from: Option<usize>,
+    to: usize,
+    step: ScenarioStep,
+    fired: Vec<TransitionId>,
+}
+
+struct Graph<'a, 'b> {
+    core: &'b Core,
+    events: Vec<usize>,
+    max_snapshots: usize,
+    nodes: Vec<Node<'a>>,
+    edges: Vec<Edge>,
+    visited: HashMap<Snapshot, usize>,
+    // macrosteps that did not stabilize, from the node they were dispatched in
+    failures: Vec<(Option<usize>, ScenarioStep, RuntimeError)>,
+    is_complete: bool,
+}
+
+impl<'a, 'b> Graph<'a, 'b> {
+    fn explore(&mut self, microstep: &'a Microstep) -> Result<(), RuntimeError> {
+        let starts = each_consistent_choice(|host| {
+            let mut interpreter = Interpreter::new(microstep);
+            let steps = interpreter.start(host);
+            Ok((interpreter, steps, host.choices().to_vec()))
+        })?;
+        for (interpreter, steps, choices) in starts {
+            match steps {
+                Ok(steps) => {
+                    self.add(None, None, interpreter, &steps, choices);
+                }
+                Err(err) => self.fail(None, None, choices, err),
+            }
+        }
+
+        let mut pending: VecDeque<_> = (0..self.nodes.len()).collect();
+        while let Some(node) = pending.pop_front() {
+            for event in self.events.clone() {
+                let successors = each_consistent_choice(|host| {
+                    let mut next = self.nodes[node].interpreter.clone();
+                    let steps = next.dispatch(event, host);
+                    Ok((next, steps, host.choices().to_vec()))
+                })?;
+                // a macrostep that does not stabilize has no successor to test against
+                for (next, steps, choices) in successors {
+                    match steps {
+                        Ok(steps) => {
+                            if let Some(added) =
+                                self.add(Some(node), Some(event), next, &steps, choices)
+                            {
+                                pending.push_back(added);
+                            }
+                        }
+                        Err(err) => self.fail(Some(node), Some(event), choices, err),
+                    }
+                }
+            }
+            if !self.is_complete {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    // records the edge and returns the target node if it was not seen before
+    fn add(
+        &mut self,
+        from: Option<usize>,
+        event: Option<usize>,
+        interpreter: Interpreter<'a>,
+        steps: &[Step],
+        choices: Vec<(usize, bool)>,
+    ) -> Option<usize> {
+        let snapshot = interpreter.snapshot();
+        let (to, is_new) = match self.visited.get(&snapshot) {
+            Some(to) => (*to, false),
+            None => {
+                if self.nodes.len() >= self.max_snapshots {
+                    self.is_complete = false;
+                    return None;
+                }
+                let to = self.nodes.len();
+                self.nodes.push(Node {
+                    configuration: interpreter.configuration().to_vec(),
+                    interpreter,
+                    parent: Some(self.edges.len()),
+                });
+                self.visited.insert(snapshot, to);
+                (to, true)
+            }
+        };
+
+        let mut fired: Vec<_> = steps
+            .iter()
+            .flat_map(|step| step.transitions.iter().cloned())
+            .collect();
+        fired.sort();
+        fired.dedup();
+        let expect = expected_states(self.core, &self.nodes[to].configuration);
+        self.edges.push(Edge {
+            from,
+            to,
+            step: ScenarioStep {
+                event,
+                conditions: choices.into_iter().collect(),
+                expect: Some(expect),
+                actions: None,
+            },
+            fired,
+        });
+
+        if is_new {
+            Some(to)
+        } else {
+            None
+        }
+    }
+
+    fn fail(
+        &mut self,
+        from: Option<usize>,
+        event: Option<usize>,
+        choices: Vec<(usize, bool)>,
+        err: RuntimeError,
+    ) {
+        let step = ScenarioStep {
+            event,
+            conditions: choices.into_iter().collect(),
+            expect: None,
+            actions: None,
+        };
+        self.failures.push((from, step, err));
+    }
+
+    // the edges from a start to and including `edge`
+    fn path(&self, edge: usize) -> Vec<usize> {
+        let mut path = vec![edge];
+        let mut from = self.edges[edge].from;
+        while let Some(node) = from {
+            let parent = self.nodes[node].parent.unwrap();
+            path.push(parent);
+            from = self.edges[parent].from;
+        }
+        path.reverse();
+        path
+    }
+
+    fn steps(&self, path: &[usize]) -> Vec<ScenarioStep> {
+        path.iter()
+            .map(|edge| self.edges[*edge].step.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    #[test]
+    fn unstable_macrosteps_are_reported_as_scenarios() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b"))
+            .state("b", |s| s.always("c"))
+            .state("c", |s| s.always("b"))
+            .build_core()
+            .unwrap();
+
+        let result = generate_tests(&core, &GenerationOptions::default()).unwrap();
+        assert_eq!(result.uncovered_transitions, vec![0, 1, 2]);
+        assert_eq!(result.unstable.len(), 1);
+
+        let unstable = &result.unstable[0];
+        assert!(
+            unstable.message.starts_with("Livelock"),
+            "{}",
+            unstable.message
+        );
+        let steps = &unstable.scenario.steps;
+        assert_eq!(steps.len(), 2);
+        assert_eq!(steps[0].expect, Some(vec!["a".to_string()]));
+        assert_eq!((steps[1].event, &steps[1].expect), (Some(0), &None));
+    }
+}
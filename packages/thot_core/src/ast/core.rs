@@ -1,8 +1,47 @@
+use self::visit::Visit;
+use ast::datamodel::{self, Variable};
+use ast::executable;
 use ast::location::Location;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 
 #[path = "core/to_microstep.rs"]
 pub mod to_microstep;
 
+#[path = "core/diagram.rs"]
+pub mod diagram;
+
+#[path = "core/to_dot.rs"]
+pub mod to_dot;
+
+#[path = "core/to_state_diagram.rs"]
+pub mod to_state_diagram;
+
+#[path = "core/to_smv.rs"]
+pub mod to_smv;
+
+#[path = "core/to_smt.rs"]
+pub mod to_smt;
+
+#[path = "core/to_rust.rs"]
+pub mod to_rust;
+
+#[path = "core/validate.rs"]
+pub mod validate;
+
+#[path = "core/verify.rs"]
+pub mod verify;
+
+#[path = "core/from_json.rs"]
+pub mod from_json;
+
+#[path = "core/visit.rs"]
+pub mod visit;
+
+#[path = "core/bitset.rs"]
+pub mod bitset;
+
 pub type StateId = usize;
 pub type TransitionId = usize;
 pub type ExecutableId = usize;
@@ -10,6 +49,12 @@ pub type InvocationId = usize;
 pub type ConditonId = usize;
 pub type EventId = usize;
 
+// executables whose conditions name states by index
+pub type Executable = executable::Executable<Condition>;
+pub type If = executable::If<Condition>;
+pub type ElseIf = executable::ElseIf<Condition>;
+pub type Foreach = executable::Foreach<Condition>;
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Core {
@@ -18,6 +63,104 @@ pub struct Core {
     pub loc: Location,
 }
 
+impl Core {
+    pub fn state_name(&self, idx: StateId) -> String {
+        match self.states[idx].id {
+            Some(ref id) => id.clone(),
+            None => format!("#{}", idx),
+        }
+    }
+
+    pub fn condition_label(&self, condition: &Condition) -> String {
+        match condition {
+            Condition::Predicate(node) => format!("c{}", node.id),
+            Condition::In(node) => format!("In({})", self.state_name(node.state)),
+            Condition::And(node) => self.conditions_label(&node.children, " && "),
+            Condition::Or(node) => self.conditions_label(&node.children, " || "),
+            Condition::Not(node) => match node.condition.as_ref() {
+                Condition::And(_) | Condition::Or(_) => {
+                    format!("!({})", self.condition_label(&node.condition))
+                }
+                condition => format!("!{}", self.condition_label(condition)),
+            },
+            Condition::Expression(node) => node.expr.to_string(),
+        }
+    }
+
+    fn conditions_label(&self, conditions: &[Condition], separator: &str) -> String {
+        conditions
+            .iter()
+            .map(|condition| match condition {
+                Condition::And(_) | Condition::Or(_) => {
+                    format!("({})", self.condition_label(condition))
+                }
+                _ => self.condition_label(condition),
+            })
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    pub fn events(&self) -> Vec<EventId> {
+        let mut events: Vec<_> = self
+            .transitions
+            .iter()
+            .filter_map(|transition| transition.event)
+            .collect();
+        events.sort();
+        events.dedup();
+        events
+    }
+
+    pub fn transition_label(&self, transition: &Transition) -> String {
+        let event = transition.event.map(|event| format!("e{}", event));
+        let condition = transition
+            .condition
+            .as_ref()
+            .map(|condition| format!("[{}]", self.condition_label(condition)));
+        match (event, condition) {
+            (Some(event), Some(condition)) => format!("{} {}", event, condition),
+            (Some(label), None) | (None, Some(label)) => label,
+            (None, None) => String::new(),
+        }
+    }
+
+    // visits every executable of every state and transition, including nested ones
+    pub fn each_executable<F: FnMut(&Executable)>(&self, f: F) {
+        struct Executables<F>(F);
+
+        impl<'ast, F: FnMut(&Executable)> Visit<'ast> for Executables<F> {
+            fn visit_executable(&mut self, node: &'ast Executable) {
+                (self.0)(node);
+                visit::visit_executable(self, node);
+            }
+        }
+
+        Executables(f).visit_core(self);
+    }
+
+    // a one line summary, leaving out the children of `if` and `foreach`
+    pub fn executable_label(&self, executable: &Executable) -> String {
+        match executable {
+            Executable::Script(node) => format!("script {}", node.id),
+            Executable::Raise(node) => format!("raise e{}", node.event),
+            Executable::Send(node) => match node.target {
+                Some(ref target) => format!("send e{} to {}", node.event, target),
+                None => format!("send e{}", node.event),
+            },
+            Executable::Assign(node) => format!("{} = {}", node.location, node.expr),
+            Executable::Log(node) => match (&node.label, &node.expr) {
+                (Some(label), Some(expr)) => format!("log {}: {}", label, expr),
+                (Some(label), None) => format!("log {}", label),
+                (None, Some(expr)) => format!("log {}", expr),
+                (None, None) => "log".to_string(),
+            },
+            Executable::If(node) => format!("if {}", self.condition_label(&node.condition)),
+            Executable::Foreach(node) => format!("foreach {} in v{}", node.item, node.array),
+            Executable::Cancel(node) => format!("cancel {}", node.id),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StateType {
@@ -49,13 +192,16 @@ pub struct State {
     pub t: StateType,
 
     #[serde(default)]
-    pub on_init: Vec<ExecutableId>,
+    pub datamodel: Vec<Variable>,
 
     #[serde(default)]
-    pub on_enter: Vec<ExecutableId>,
+    pub on_init: Vec<Executable>,
 
     #[serde(default)]
-    pub on_exit: Vec<ExecutableId>,
+    pub on_enter: Vec<Executable>,
+
+    #[serde(default)]
+    pub on_exit: Vec<Executable>,
 
     #[serde(default)]
     pub invocations: Vec<InvocationId>,
@@ -119,10 +265,10 @@ pub struct Transition {
     pub event: Option<EventId>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub condition: Option<ConditonId>,
+    pub condition: Option<Condition>,
 
     #[serde(default)]
-    pub on_transition: Vec<ExecutableId>,
+    pub on_transition: Vec<Executable>,
 
     #[serde(default)]
     pub targets: Vec<StateId>,
@@ -136,3 +282,130 @@ pub struct Transition {
     #[serde(default)]
     pub loc: Location,
 }
+
+// serialized tagged by `type`. Charts written before conditions were structured name a
+// bare condition id, which still deserializes as `Predicate`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", remote = "Self")]
+pub enum Condition {
+    Predicate(Predicate),
+    In(In),
+    And(Conditions),
+    Or(Conditions),
+    Not(Not),
+    Expression(ExpressionCondition),
+}
+
+impl Serialize for Condition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Condition::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Condition, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if let Some(id) = value.as_u64() {
+            return Ok(Condition::Predicate(Predicate {
+                id: id as ConditonId,
+                loc: Location::default(),
+            }));
+        }
+        Condition::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Predicate {
+    pub id: ConditonId,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct In {
+    pub state: StateId,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    #[serde(default)]
+    pub children: Vec<Condition>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Not {
+    pub condition: Box<Condition>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpressionCondition {
+    pub expr: datamodel::Expression,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn bare_condition_ids_are_predicates() {
+        let transition: Transition = serde_json::from_str(
+            r#"{"type": {"type": "external"}, "source": 1, "targets": [2], "condition": 3}"#,
+        )
+        .unwrap();
+        match transition.condition {
+            Some(Condition::Predicate(Predicate { id: 3, .. })) => {}
+            ref other => panic!("expected predicate 3, got {:?}", other),
+        }
+        let condition: Condition =
+            serde_json::from_str(r#"{"type": "not", "condition": {"type": "in", "state": 1}}"#)
+                .unwrap();
+        match condition {
+            Condition::Not(Not { condition, .. }) => match *condition {
+                Condition::In(In { state: 1, .. }) => {}
+                other => panic!("expected In(1), got {:?}", other),
+            },
+            other => panic!("expected Not, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bare_if_conditions_are_predicates() {
+        let executable: Executable = serde_json::from_str(
+            r#"{"type": "if", "condition": 2, "else_if": [{"condition": {"type": "in", "state": 1}}]}"#,
+        )
+        .unwrap();
+        match executable {
+            Executable::If(node) => {
+                match node.condition {
+                    Condition::Predicate(Predicate { id: 2, .. }) => {}
+                    other => panic!("expected predicate 2, got {:?}", other),
+                }
+                match node.else_if[0].condition {
+                    Condition::In(In { state: 1, .. }) => {}
+                    ref other => panic!("expected In(1), got {:?}", other),
+                }
+            }
+            other => panic!("expected If, got {:?}", other),
+        }
+    }
+}
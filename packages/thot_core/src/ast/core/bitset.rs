@@ -0,0 +1,132 @@
+const BITS: usize = 64;
+
+// a set of state or transition ids below a fixed capacity
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    pub fn new(capacity: usize) -> BitSet {
+        BitSet {
+            words: vec![0; capacity.div_ceil(BITS)],
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.words.len() * BITS
+    }
+
+    pub fn insert(&mut self, id: usize) {
+        self.words[id / BITS] |= 1 << (id % BITS);
+    }
+
+    pub fn contains(&self, id: usize) -> bool {
+        id < self.capacity() && self.words[id / BITS] & (1 << (id % BITS)) != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    pub fn intersects(&self, other: &BitSet) -> bool {
+        self.words
+            .iter()
+            .zip(&other.words)
+            .any(|(word, other)| word & other != 0)
+    }
+
+    // ids in ascending order
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            words: &self.words,
+            idx: 0,
+            word: self.words.first().cloned().unwrap_or(0),
+        }
+    }
+}
+
+pub struct Iter<'a> {
+    words: &'a [u64],
+    idx: usize,
+    word: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.word == 0 {
+            self.idx += 1;
+            self.word = *self.words.get(self.idx)?;
+        }
+        let bit = self.word.trailing_zeros() as usize;
+        self.word &= self.word - 1;
+        Some(self.idx * BITS + bit)
+    }
+}
+
+impl Extend<usize> for BitSet {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, ids: I) {
+        for id in ids {
+            self.insert(id);
+        }
+    }
+}
+
+impl<'a> Extend<&'a usize> for BitSet {
+    fn extend<I: IntoIterator<Item = &'a usize>>(&mut self, ids: I) {
+        for &id in ids {
+            self.insert(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_kept_across_words() {
+        let mut set = BitSet::new(130);
+        assert_eq!(set.capacity(), 192);
+        assert!(set.is_empty());
+        set.extend(vec![129, 0, 64, 63, 64]);
+        assert_eq!(set.len(), 4);
+        assert!(!set.is_empty());
+        assert!(set.contains(63) && set.contains(64) && !set.contains(65));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![0, 63, 64, 129]);
+    }
+
+    #[test]
+    fn ids_past_the_capacity_are_not_contained() {
+        let mut set = BitSet::new(3);
+        set.insert(2);
+        assert!(!set.contains(64));
+        assert!(!set.contains(1000));
+        assert_eq!(BitSet::new(0).iter().next(), None);
+    }
+
+    #[test]
+    fn sets_intersect_on_shared_ids() {
+        let mut a = BitSet::new(200);
+        let mut b = BitSet::new(70);
+        a.extend(&[5, 150]);
+        b.extend(&[6, 69]);
+        assert!(!a.intersects(&b));
+        b.insert(5);
+        assert!(a.intersects(&b) && b.intersects(&a));
+
+        // the extra words of the larger set are ignored
+        let mut c = BitSet::new(1);
+        c.insert(0);
+        assert!(!a.intersects(&c) && !c.intersects(&a));
+    }
+}
@@ -0,0 +1,65 @@
+use ast::core::StateId;
+
+// helpers shared by the Graphviz, Mermaid and PlantUML exports
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Syntax {
+    Dot,
+    Mermaid,
+    PlantUml,
+}
+
+// the identifier every export gives a state
+pub fn node_name(idx: StateId) -> String {
+    format!("s{}", idx)
+}
+
+// escapes a label for use inside double quotes, or after the `:` of a transition, so the
+// label renders as written
+pub fn escape_label(label: &str, syntax: Syntax) -> String {
+    let mut out = String::with_capacity(label.len());
+    for c in label.chars() {
+        match (syntax, c) {
+            (Syntax::Dot, '\\') | (Syntax::PlantUml, '\\') => out.push_str("\\\\"),
+            (Syntax::Dot, '"') => out.push_str("\\\""),
+            (Syntax::Mermaid, '"') => out.push_str("#quot;"),
+            (Syntax::Mermaid, '#') => out.push_str("#35;"),
+            (Syntax::Mermaid, '\n') => out.push_str("<br/>"),
+            (Syntax::PlantUml, '"') => out.push_str("&#34;"),
+            (_, '\n') => out.push_str("\\n"),
+            (_, c) => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABEL: &str = "say \"hi\\there\"\n#1";
+
+    #[test]
+    fn dot_uses_backslash_escapes() {
+        assert_eq!(
+            escape_label(LABEL, Syntax::Dot),
+            "say \\\"hi\\\\there\\\"\\n#1"
+        );
+    }
+
+    #[test]
+    fn mermaid_uses_entity_codes() {
+        assert_eq!(
+            escape_label(LABEL, Syntax::Mermaid),
+            "say #quot;hi\\there#quot;<br/>#35;1"
+        );
+    }
+
+    #[test]
+    fn plantuml_escapes_backslashes_and_quotes() {
+        assert_eq!(
+            escape_label(LABEL, Syntax::PlantUml),
+            "say &#34;hi\\\\there&#34;\\n#1"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::core::Core;
+use ast::location::Location;
+use serde_json;
+
+// reads a serialized Core, as produced by other tools. It is only compiled if its derived
+// fields are exactly what `to_core` would have produced.
+pub fn from_json(source: &str) -> Result<Core, Errors> {
+    let core: Core = serde_json::from_str(source).map_err(|err| {
+        vec![ConversionError {
+            message: err.to_string(),
+            fatal: true,
+            source: "ast/core/from_json".to_string(),
+            loc: Location::default(),
+        }]
+    })?;
+    let errors = core.verify();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(core)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    fn chart() -> Core {
+        Statechart::builder()
+            .state("a", |s| s.on(0, "b").atomic("a1").atomic("a2"))
+            .atomic("b")
+            .build_core()
+            .unwrap()
+    }
+
+    #[test]
+    fn converted_cores_are_read_back() {
+        let core = chart();
+        let read = from_json(&serde_json::to_string(&core).unwrap()).unwrap();
+        assert_eq!(read.transitions[0].exits, core.transitions[0].exits);
+    }
+
+    #[test]
+    fn derived_fields_must_match() {
+        let mut core = chart();
+        core.transitions[0].exits.pop();
+        let errors = from_json(&serde_json::to_string(&core).unwrap()).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(
+            errors[0].message.starts_with("Transition 0 exits"),
+            "{}",
+            errors[0].message
+        );
+    }
+
+    #[test]
+    fn endpoints_are_checked_before_anything_is_derived() {
+        let mut core = chart();
+        core.states[1].parent = 9;
+        core.transitions[0].targets = vec![7];
+        let errors = from_json(&serde_json::to_string(&core).unwrap()).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "State 1 has a missing parent 9",
+                "Transition 0 refers to missing state 7"
+            ]
+        );
+        assert!(errors.iter().all(|err| err.fatal));
+
+        let mut core = chart();
+        core.states[2].parent = 3;
+        core.states[3].parent = 2;
+        let errors = from_json(&serde_json::to_string(&core).unwrap()).unwrap_err();
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+    }
+}
@@ -0,0 +1,124 @@
+use ast::core::diagram::{escape_label, node_name, Syntax};
+use ast::core::{Core, State, StateId, StateType};
+use std::fmt::Write;
+
+const ACTIVE_COLOR: &str = "#ffe082";
+
+#[derive(Clone, Debug, Default)]
+pub struct DotOptions {
+    pub configuration: Vec<StateId>,
+}
+
+pub fn to_dot(core: &Core, options: &DotOptions) -> String {
+    let mut out = String::new();
+    writeln!(out, "digraph statechart {{").unwrap();
+    writeln!(out, "  compound=true;").unwrap();
+    writeln!(out, "  node [fontname=\"Helvetica\"];").unwrap();
+    writeln!(out, "  edge [fontname=\"Helvetica\"];").unwrap();
+
+    if let Some(root) = core.states.first() {
+        for child in &root.children {
+            write_state(&mut out, core, &core.states[*child], options, 1);
+        }
+    }
+
+    for transition in &core.transitions {
+        let source = node_name(transition.source);
+        let label = escape(&core.transition_label(transition));
+        let ltail = cluster_attr("ltail", core, transition.source);
+
+        if transition.targets.is_empty() {
+            writeln!(
+                out,
+                "  {} -> {} [label=\"{}\", style=dashed{}];",
+                source, source, label, ltail
+            )
+            .unwrap();
+            continue;
+        }
+
+        for target in &transition.targets {
+            writeln!(
+                out,
+                "  {} -> {} [label=\"{}\"{}{}];",
+                source,
+                node_name(*target),
+                label,
+                ltail,
+                cluster_attr("lhead", core, *target)
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+fn write_state(out: &mut String, core: &Core, state: &State, options: &DotOptions, depth: usize) {
+    let indent = "  ".repeat(depth);
+    let name = node_name(state.idx);
+    let label = escape(&core.state_name(state.idx));
+    let is_active = options.configuration.contains(&state.idx);
+
+    if is_cluster(state) {
+        let parent = &core.states[state.parent];
+        let mut style = vec!["rounded"];
+        if parent.t == StateType::Parallel {
+            style.push("dashed");
+        }
+        if is_active {
+            style.push("filled");
+        }
+
+        writeln!(out, "{}subgraph cluster_{} {{", indent, state.idx).unwrap();
+        writeln!(out, "{}  label=\"{}\";", indent, label).unwrap();
+        writeln!(out, "{}  style=\"{}\";", indent, style.join(",")).unwrap();
+        if is_active {
+            writeln!(out, "{}  fillcolor=\"{}\";", indent, ACTIVE_COLOR).unwrap();
+        }
+        writeln!(
+            out,
+            "{}  {} [shape=point, style=invis, width=0];",
+            indent, name
+        )
+        .unwrap();
+        for child in &state.children {
+            write_state(out, core, &core.states[*child], options, depth + 1);
+        }
+        writeln!(out, "{}}}", indent).unwrap();
+        return;
+    }
+
+    let attrs = match state.t {
+        StateType::Initial => "shape=point, width=0.15".to_string(),
+        StateType::Final => "shape=doublecircle, label=\"\", width=0.2".to_string(),
+        StateType::HistoryShallow => "shape=circle, label=\"H\"".to_string(),
+        StateType::HistoryDeep => "shape=circle, label=\"H*\"".to_string(),
+        _ if is_active => format!(
+            "shape=box, style=\"rounded,filled\", fillcolor=\"{}\", label=\"{}\"",
+            ACTIVE_COLOR, label
+        ),
+        _ => format!("shape=box, style=rounded, label=\"{}\"", label),
+    };
+    writeln!(out, "{}{} [{}];", indent, name, attrs).unwrap();
+}
+
+fn is_cluster(state: &State) -> bool {
+    match state.t {
+        StateType::Compound | StateType::Parallel => !state.children.is_empty(),
+        _ => false,
+    }
+}
+
+fn cluster_attr(attr: &str, core: &Core, idx: StateId) -> String {
+    if is_cluster(&core.states[idx]) {
+        format!(", {}=cluster_{}", attr, idx)
+    } else {
+        String::new()
+    }
+}
+
+fn escape(label: &str) -> String {
+    escape_label(label, Syntax::Dot)
+}
@@ -1,5 +1,6 @@
 use ast::conversion_error::Errors;
 use ast::core;
+use ast::core::Executable;
 use ast::location::Location;
 use ast::microstep::*;
 
@@ -12,9 +13,14 @@ impl Into<Result<Microstep, Errors>> for core::Core {
         } = self;
         let configuration_size = states.len();
         let errors = vec![];
+        let datamodel = states
+            .iter()
+            .flat_map(|state| state.datamodel.iter().cloned())
+            .collect();
 
         let microstep = Microstep {
             configuration_size,
+            datamodel,
             init: gen_init(&states, &transitions, loc),
             next: gen_select_transitions(&states, &transitions, loc),
             loc,
@@ -120,10 +126,10 @@ fn gen_select_transitions(
         &gen_bool(false, loc),
         &states,
     ));
-    body.append(&mut gen_empty_configuration(
+    body.append(&mut gen_empty_transitions(
         AVAILABLE_TRANS_PREFIX,
         &gen_bool(true, loc),
-        &states,
+        &transitions,
     ));
     body.push(gen_var(is_stable_ident.clone(), &gen_bool(true, loc), loc));
     body.append(&mut gen_transition_select(
@@ -144,6 +150,7 @@ fn gen_select_transitions(
             // TODO
             // invocations: SimpleExpression::
             history: SimpleExpression::Identifier(history_ident.clone()),
+            transitions: gen_construct_transitions(&transitions, loc).to_simple(),
             is_stable: SimpleExpression::Identifier(is_stable_ident.clone()),
             loc,
         }),
@@ -184,6 +191,7 @@ fn gen_establish_entryset(
             configuration: gen_construct(ENTRY_PREFIX, states).to_simple(),
             initialized: gen_construct(INITIALIZED_PREFIX, states).to_simple(),
             history: gen_construct(HISTORY_PREFIX, states).to_simple(),
+            transitions: gen_construct_transitions(transitions, loc).to_simple(),
             // TODO
             // invocations: SimpleExpression::
             is_stable: gen_bool(false, loc).to_simple(),
@@ -225,18 +233,17 @@ fn gen_entryset_entry_ancestors(states: &[core::State]) -> Vec<Statement> {
             0 => (),
             _ => {
                 statements.push(gen_assign(
-                    id,
+                    id.clone(),
                     &Expression::LogicalExpression(LogicalExpression {
                         operator: LogicalOperator::Or,
-                        arguments: state
-                            .children
-                            .iter()
-                            .map(|d| {
+                        arguments: Some(Expression::Identifier(id))
+                            .into_iter()
+                            .chain(state.children.iter().map(|d| {
                                 Expression::Identifier(Identifier {
                                     name: format!("{}{}", ENTRY_PREFIX, d),
                                     loc,
                                 })
-                            })
+                            }))
                             .collect(),
                         loc,
                     }),
@@ -390,10 +397,10 @@ fn gen_entryset_exit_states(states: &[core::State]) -> Vec<Statement> {
         let loc = state.loc;
         let idx = state.idx;
 
-        for id in &state.on_exit {
-            statements.push(Statement::ExecuteStatement(ExecuteStatement {
-                id: *id,
-                guard: Some(gen_and(
+        for executable in &state.on_exit {
+            statements.push(gen_executable(
+                executable,
+                Some(gen_and(
                     &Expression::Identifier(Identifier {
                         name: format!("{}{}", CONFIGURATION_PREFIX, idx),
                         loc,
@@ -404,8 +411,7 @@ fn gen_entryset_exit_states(states: &[core::State]) -> Vec<Statement> {
                     }),
                     loc,
                 )),
-                loc,
-            }));
+            ));
         }
     }
     statements
@@ -416,15 +422,14 @@ fn gen_entryset_take_transitions(transitions: &[core::Transition]) -> Vec<Statem
     for transition in transitions {
         let loc = transition.loc;
         let idx = transition.idx;
-        for id in &transition.on_transition {
-            statements.push(Statement::ExecuteStatement(ExecuteStatement {
-                id: *id,
-                guard: Some(Expression::Identifier(Identifier {
+        for executable in &transition.on_transition {
+            statements.push(gen_executable(
+                executable,
+                Some(Expression::Identifier(Identifier {
                     name: format!("{}{}", TRANS_PREFIX, idx),
                     loc,
                 })),
-                loc,
-            }));
+            ));
         }
     }
     statements
@@ -444,7 +449,7 @@ fn gen_entryset_enter_states(states: &[core::State]) -> Vec<Statement> {
             loc,
         });
 
-        if state.on_init.len() + state.on_enter.len() > 0 {
+        if state.datamodel.len() + state.on_init.len() + state.on_enter.len() > 0 {
             statements.push(gen_var(
                 guard_ident.clone(),
                 &gen_and(
@@ -467,16 +472,32 @@ fn gen_entryset_enter_states(states: &[core::State]) -> Vec<Statement> {
             loc,
         };
 
-        for id in &state.on_init {
-            statements.push(Statement::ExecuteStatement(ExecuteStatement {
-                id: *id,
-                guard: Some(gen_and(
+        for variable in &state.datamodel {
+            if let Some(ref init) = variable.init {
+                statements.push(Statement::DataAssignmentStatement(
+                    DataAssignmentStatement {
+                        location: variable.id.clone(),
+                        expr: init.clone(),
+                        guard: Some(gen_and(
+                            &Expression::Identifier(guard_ident.clone()),
+                            &gen_not(&Expression::Identifier(initital_ident.clone()), loc),
+                            loc,
+                        )),
+                        loc: variable.loc,
+                    },
+                ));
+            }
+        }
+
+        for executable in &state.on_init {
+            statements.push(gen_executable(
+                executable,
+                Some(gen_and(
                     &Expression::Identifier(guard_ident.clone()),
                     &gen_not(&Expression::Identifier(initital_ident.clone()), loc),
                     loc,
                 )),
-                loc,
-            }));
+            ));
         }
 
         statements.push(gen_assign(
@@ -485,17 +506,91 @@ fn gen_entryset_enter_states(states: &[core::State]) -> Vec<Statement> {
             loc,
         ));
 
-        for id in &state.on_enter {
-            statements.push(Statement::ExecuteStatement(ExecuteStatement {
-                id: *id,
-                guard: Some(Expression::Identifier(guard_ident.clone())),
-                loc,
-            }));
+        for executable in &state.on_enter {
+            statements.push(gen_executable(
+                executable,
+                Some(Expression::Identifier(guard_ident.clone())),
+            ));
         }
     }
     statements
 }
 
+fn gen_executable(executable: &Executable, guard: Option<Expression>) -> Statement {
+    match executable {
+        Executable::Script(node) => Statement::ExecuteStatement(ExecuteStatement {
+            id: node.id,
+            guard,
+            loc: node.loc,
+        }),
+        Executable::Raise(node) => Statement::RaiseStatement(RaiseStatement {
+            event: node.event,
+            guard,
+            loc: node.loc,
+        }),
+        Executable::Send(node) => Statement::SendStatement(SendStatement {
+            event: node.event,
+            target: node.target.clone(),
+            id: node.id.clone(),
+            delay: node.delay,
+            guard,
+            loc: node.loc,
+        }),
+        Executable::Assign(node) => Statement::DataAssignmentStatement(DataAssignmentStatement {
+            location: node.location.clone(),
+            expr: node.expr.clone(),
+            guard,
+            loc: node.loc,
+        }),
+        Executable::Log(node) => Statement::LogStatement(LogStatement {
+            label: node.label.clone(),
+            expr: node.expr.clone(),
+            guard,
+            loc: node.loc,
+        }),
+        Executable::If(node) => Statement::IfStatement(IfStatement {
+            test: gen_condition(&node.condition),
+            consequent: gen_executables(&node.children),
+            alternate: gen_else_if(&node.else_if, &node.otherwise),
+            guard,
+            loc: node.loc,
+        }),
+        Executable::Foreach(node) => Statement::ForeachStatement(ForeachStatement {
+            array: node.array,
+            item: node.item.clone(),
+            index: node.index.clone(),
+            body: gen_executables(&node.children),
+            guard,
+            loc: node.loc,
+        }),
+        Executable::Cancel(node) => Statement::CancelStatement(CancelStatement {
+            id: node.id.clone(),
+            guard,
+            loc: node.loc,
+        }),
+    }
+}
+
+fn gen_executables(executables: &[Executable]) -> Vec<Statement> {
+    executables
+        .iter()
+        .map(|executable| gen_executable(executable, None))
+        .collect()
+}
+
+fn gen_else_if(else_if: &[core::ElseIf], otherwise: &[Executable]) -> Vec<Statement> {
+    match else_if.split_first() {
+        Some((node, rest)) => vec![Statement::IfStatement(IfStatement {
+            test: gen_condition(&node.condition),
+            consequent: gen_executables(&node.children),
+            alternate: gen_else_if(rest, otherwise),
+            guard: None,
+            loc: node.loc,
+        })],
+        None => gen_executables(otherwise),
+    }
+}
+
 fn gen_destruct(configuration: &Identifier, states: &[core::State]) -> Statement {
     let left = (0..states.len())
         .map(|index| {
@@ -531,6 +626,24 @@ fn gen_construct(prefix: &'static str, states: &[core::State]) -> Expression {
     })
 }
 
+// history and initial transitions are never selected so they have no t<idx> in next
+fn gen_construct_transitions(transitions: &[core::Transition], loc: Location) -> Expression {
+    let arguments = transitions
+        .iter()
+        .map(|transition| match transition.t {
+            core::TransitionType::History | core::TransitionType::Initial => {
+                gen_bool(false, transition.loc)
+            }
+            _ => Expression::Identifier(Identifier {
+                name: format!("{}{}", TRANS_PREFIX, transition.idx),
+                loc: transition.loc,
+            }),
+        })
+        .collect();
+
+    Expression::ConfigurationCreateExpression(ConfigurationCreateExpression { arguments, loc })
+}
+
 fn gen_empty_configuration(
     name: &'static str,
     init: &Expression,
@@ -696,16 +809,40 @@ fn gen_is_transition_applicable(
 }
 
 fn gen_is_transition_enabled(transition: &core::Transition) -> Option<Expression> {
-    let loc = transition.loc;
     match transition.condition {
-        Some(id) => Some(Expression::ConditionExpression(ConditionExpression {
-            id,
-            loc,
-        })),
+        Some(ref condition) => Some(gen_condition(condition)),
         None => None,
     }
 }
 
+fn gen_condition(condition: &core::Condition) -> Expression {
+    match condition {
+        core::Condition::Predicate(node) => Expression::ConditionExpression(ConditionExpression {
+            id: node.id,
+            loc: node.loc,
+        }),
+        core::Condition::In(node) => Expression::Identifier(Identifier {
+            name: format!("{}{}", CONFIGURATION_PREFIX, node.state),
+            loc: node.loc,
+        }),
+        core::Condition::And(node) => Expression::LogicalExpression(LogicalExpression {
+            operator: LogicalOperator::And,
+            arguments: node.children.iter().map(gen_condition).collect(),
+            loc: node.loc,
+        }),
+        core::Condition::Or(node) => Expression::LogicalExpression(LogicalExpression {
+            operator: LogicalOperator::Or,
+            arguments: node.children.iter().map(gen_condition).collect(),
+            loc: node.loc,
+        }),
+        core::Condition::Not(node) => gen_not(&gen_condition(&node.condition), node.loc),
+        core::Condition::Expression(node) => Expression::DatamodelExpression(DatamodelExpression {
+            expr: node.expr.clone(),
+            loc: node.loc,
+        }),
+    }
+}
+
 fn gen_union(
     guard: &Expression,
     prefix: &'static str,
@@ -793,3 +930,54 @@ fn gen_merge(
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+    use runtime::interpreter::{Host, Interpreter};
+
+    struct NoConditions;
+
+    impl Host for NoConditions {
+        fn condition(&mut self, _id: usize) -> bool {
+            false
+        }
+    }
+
+    fn active_ids(core: &core::Core, event: usize) -> Vec<String> {
+        let microstep: Result<Microstep, _> = core.clone().into();
+        let microstep = microstep.unwrap();
+        let mut interpreter = Interpreter::new(&microstep);
+        interpreter.start(&mut NoConditions).unwrap();
+        interpreter.dispatch(event, &mut NoConditions).unwrap();
+        interpreter
+            .active_states()
+            .into_iter()
+            .filter_map(|state| core.states[state].id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn transitions_past_the_state_count_are_available() {
+        // 3 states and 4 transitions, a3 used to be undeclared
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b").on(1, "b").on(2, "b").on(3, "b"))
+            .atomic("b")
+            .build_core()
+            .unwrap();
+        assert_eq!(core.states.len(), 3);
+        assert_eq!(active_ids(&core, 3), vec!["b"]);
+    }
+
+    #[test]
+    fn targeted_compound_states_are_entered() {
+        // the transition sets e<p>, which its children used to overwrite with false
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "p"))
+            .state("p", |s| s.atomic("p1"))
+            .build_core()
+            .unwrap();
+        assert_eq!(active_ids(&core, 0), vec!["p", "p1"]);
+    }
+}
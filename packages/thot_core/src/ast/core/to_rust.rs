@@ -0,0 +1,241 @@
+use ast::conversion_error::Errors;
+use ast::core::visit::{self, Visit};
+use ast::core::{Condition, ConditonId, Core, EventId, Executable, ExecutableId, StateType};
+use ast::microstep::Microstep;
+use serde_json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// names for the generated module and its variants. Ids of the chart without a name here
+// are named after their id, such as `E3`, `G1` and `A4`.
+#[derive(Clone, Debug, Default)]
+pub struct RustOptions {
+    pub module: String,
+    pub visibility: String,
+    pub events: Vec<(String, EventId)>,
+    pub guards: Vec<(String, ConditonId)>,
+    pub actions: Vec<(String, ExecutableId)>,
+}
+
+// emits a module with typed `State`, `Event`, `Guard` and `Action` enums, an `Actions` trait
+// for the host and a `Machine` that runs the compiled microstep with the thot_core runtime
+pub fn to_rust(core: &Core, options: &RustOptions) -> Result<String, Errors> {
+    let microstep: Result<Microstep, Errors> = core.clone().into();
+    let microstep = microstep?;
+
+    let mut ids = Ids::default();
+    ids.visit_core(core);
+
+    let events = variants(&options.events, core.events(), "E");
+    let guards = variants(&options.guards, ids.conditions, "G");
+    let actions = variants(&options.actions, ids.scripts, "A");
+    let mut states = vec![];
+    for state in &core.states {
+        let id = match (state.t, &state.id) {
+            (StateType::Initial, _) | (_, None) => continue,
+            (_, Some(id)) => id,
+        };
+        let mut name = camel_case(id);
+        if name.is_empty() || name == "Self" || states.iter().any(|(other, _, _)| *other == name) {
+            name = format!("S{}", state.idx);
+        }
+        states.push((name, id.clone(), state.idx));
+    }
+
+    let mut out = String::new();
+    writeln!(out, "// generated by thot, do not edit").unwrap();
+    writeln!(out, "#[allow(dead_code)]").unwrap();
+    writeln!(out, "{} mod {} {{", options.visibility, options.module).unwrap();
+    writeln!(
+        out,
+        "use ::thot_core::ast::microstep::Microstep;
+use ::thot_core::runtime::interpreter::{{self, Interpreter, RuntimeError}};
+use ::std::sync::OnceLock;
+"
+    )
+    .unwrap();
+
+    write_enum(&mut out, "State", &states);
+    writeln!(out, "impl State {{").unwrap();
+    writeln!(out, "pub fn id(self) -> &'static str {{ match self {{").unwrap();
+    for (name, id, _) in &states {
+        writeln!(out, "State::{} => {:?},", name, id).unwrap();
+    }
+    writeln!(out, "}} }}").unwrap();
+    write_ids(&mut out, "State", "idx", &states);
+    writeln!(out, "}}\n").unwrap();
+
+    for (kind, variants) in &[("Event", &events), ("Guard", &guards), ("Action", &actions)] {
+        let variants: Vec<_> = variants
+            .iter()
+            .map(|(name, id)| (name.clone(), String::new(), *id))
+            .collect();
+        write_enum(&mut out, kind, &variants);
+        writeln!(out, "impl {} {{", kind).unwrap();
+        write_ids(&mut out, kind, "id", &variants);
+        writeln!(out, "}}\n").unwrap();
+    }
+
+    // without guards there is nothing for the host to decide
+    let guard = if guards.is_empty() {
+        "fn guard(&mut self, _guard: Guard) -> bool { false }"
+    } else {
+        "fn guard(&mut self, guard: Guard) -> bool;"
+    };
+    writeln!(
+        out,
+        "pub trait Actions {{
+{}
+fn action(&mut self, _action: Action) {{}}
+}}
+
+struct Host<'a, A: 'a + ?Sized>(&'a mut A);
+
+impl<'a, A: Actions + ?Sized> interpreter::Host for Host<'a, A> {{
+fn condition(&mut self, id: usize) -> bool {{
+match Guard::from_id(id) {{ Some(guard) => self.0.guard(guard), None => false }}
+}}
+fn execute(&mut self, id: usize) {{
+if let Some(action) = Action::from_id(id) {{ self.0.action(action) }}
+}}
+}}
+
+const MICROSTEP: &str = {:?};
+
+fn microstep() -> &'static Microstep {{
+static CELL: OnceLock<Microstep> = OnceLock::new();
+CELL.get_or_init(|| Microstep::from_json(MICROSTEP).unwrap())
+}}
+
+#[derive(Clone, Debug)]
+pub struct Machine {{
+interpreter: Interpreter<'static>,
+}}
+
+impl Machine {{
+pub fn new() -> Machine {{
+Machine {{ interpreter: Interpreter::new(microstep()) }}
+}}
+pub fn start<A: Actions + ?Sized>(&mut self, actions: &mut A) -> Result<(), RuntimeError> {{
+self.interpreter.start(&mut Host(actions)).map(|_| ())
+}}
+pub fn send<A: Actions + ?Sized>(&mut self, event: Event, actions: &mut A) -> Result<(), RuntimeError> {{
+self.interpreter.dispatch(event.id(), &mut Host(actions)).map(|_| ())
+}}
+pub fn is_active(&self, state: State) -> bool {{
+self.interpreter.is_active(state.idx())
+}}
+pub fn active_states(&self) -> Vec<State> {{
+self.interpreter.active_states().into_iter().filter_map(State::from_idx).collect()
+}}
+}}
+
+impl Default for Machine {{
+fn default() -> Machine {{ Machine::new() }}
+}}
+}}",
+        guard,
+        serde_json::to_string(&microstep).unwrap()
+    )
+    .unwrap();
+
+    Ok(out)
+}
+
+fn write_enum(out: &mut String, kind: &str, variants: &[(String, String, usize)]) {
+    writeln!(out, "#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]").unwrap();
+    writeln!(out, "pub enum {} {{", kind).unwrap();
+    for (name, _, _) in variants {
+        writeln!(out, "{},", name).unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+}
+
+// the constant list of variants and the conversions to and from their ids
+fn write_ids(out: &mut String, kind: &str, id: &str, variants: &[(String, String, usize)]) {
+    let all: Vec<_> = variants
+        .iter()
+        .map(|(name, _, _)| format!("{}::{}", kind, name))
+        .collect();
+    writeln!(out, "pub const ALL: &[{}] = &[{}];", kind, all.join(", ")).unwrap();
+    writeln!(out, "pub fn {}(self) -> usize {{ match self {{", id).unwrap();
+    for (name, _, value) in variants {
+        writeln!(out, "{}::{} => {},", kind, name, value).unwrap();
+    }
+    writeln!(out, "}} }}").unwrap();
+    writeln!(
+        out,
+        "pub fn from_{}({}: usize) -> Option<{}> {{ match {} {{",
+        id, id, kind, id
+    )
+    .unwrap();
+    for (name, _, value) in variants {
+        writeln!(out, "{} => Some({}::{}),", value, kind, name).unwrap();
+    }
+    writeln!(out, "_ => None, }} }}").unwrap();
+}
+
+// the named ids followed by any others the chart uses. Names that do not make a distinct
+// variant, such as `self` or a second `Timer`, fall back to the prefix and id.
+fn variants<I>(named: &[(String, usize)], used: I, prefix: &str) -> Vec<(String, usize)>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let mut result: BTreeMap<usize, String> = BTreeMap::new();
+    for id in used {
+        result.insert(id, format!("{}{}", prefix, id));
+    }
+    for (name, id) in named {
+        let name = camel_case(name);
+        if name.is_empty() || name == "Self" || result.values().any(|other| *other == name) {
+            continue;
+        }
+        result.insert(*id, name);
+    }
+    result.into_iter().map(|(id, name)| (name, id)).collect()
+}
+
+// the host conditions and scripts the chart uses
+#[derive(Default)]
+struct Ids {
+    conditions: BTreeSet<ConditonId>,
+    scripts: BTreeSet<ExecutableId>,
+}
+
+impl<'ast> Visit<'ast> for Ids {
+    fn visit_condition(&mut self, node: &'ast Condition) {
+        if let Condition::Predicate(node) = node {
+            self.conditions.insert(node.id);
+        }
+        visit::visit_condition(self, node);
+    }
+
+    fn visit_executable(&mut self, node: &'ast Executable) {
+        if let Executable::Script(node) = node {
+            self.scripts.insert(node.id);
+        }
+        visit::visit_executable(self, node);
+    }
+}
+
+// turns a name such as `waiting-for_input` or `POWER_OUTAGE` into `WaitingForInput` or
+// `PowerOutage`, keeping the case of mixed case words like `isReady`
+pub fn camel_case(id: &str) -> String {
+    let mut name = String::new();
+    for word in id.split(|c: char| !c.is_alphanumeric()) {
+        let is_upper = !word.chars().any(char::is_lowercase);
+        for (idx, c) in word.chars().enumerate() {
+            if idx == 0 {
+                name.extend(c.to_uppercase());
+            } else if is_upper {
+                name.extend(c.to_lowercase());
+            } else {
+                name.push(c);
+            }
+        }
+    }
+    if name.starts_with(|c: char| c.is_numeric()) {
+        name.insert(0, 'S');
+    }
+    name
+}
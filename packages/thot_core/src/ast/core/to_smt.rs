@@ -0,0 +1,310 @@
+use ast::conversion_error::Errors;
+use ast::core::to_smv::replace_states;
+use ast::core::Core;
+use ast::microstep::to_formula::{translate_init, translate_next, variables, Formula, Inputs};
+use ast::microstep::Microstep;
+use std::fmt::Write;
+
+#[derive(Clone, Debug)]
+pub struct SmtOptions {
+    pub steps: usize,
+    pub queries: Vec<String>,
+}
+
+impl Default for SmtOptions {
+    fn default() -> SmtOptions {
+        SmtOptions {
+            steps: 1,
+            queries: vec![],
+        }
+    }
+}
+
+// encodes `init` followed by `steps` unrolled microsteps as SMT-LIB2. Variables are
+// suffixed with the step they belong to, so `c3.0` is state 3 in the initial configuration.
+// Every state id also gets a `|state:<id>.reached|` literal for `check-sat-assuming`, so
+// `(check-sat-assuming (|state:a.reached| |state:b.reached|))` asks whether both are entered
+// within the bound and `(get-unsat-core)` names the conflicting ones when they are not. The
+// `state:` prefix keeps ids like `c1` or `init` apart from the generated names.
+pub fn to_smt(core: &Core, options: &SmtOptions) -> Result<String, Errors> {
+    let microstep: Result<Microstep, Errors> = core.clone().into();
+    let microstep = microstep?;
+    let size = microstep.configuration_size;
+    let variables = variables(size);
+
+    let mut init_inputs = Inputs::default();
+    let init = translate_init(&microstep, &mut init_inputs).map_err(|err| vec![err])?;
+    let mut inputs = Inputs::default();
+    let next = translate_next(&microstep, &mut inputs).map_err(|err| vec![err])?;
+
+    let mut queries = vec![];
+    for query in &options.queries {
+        let reached: Vec<_> = (0..=options.steps)
+            .map(|step| replace_states(core, query, |idx| format!("c{}.{}", idx, step)))
+            .collect::<Result<_, _>>()
+            .map_err(|err| vec![err])?;
+        queries.push(reached);
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "; thot microstep relation unrolled for {} steps",
+        options.steps
+    )
+    .unwrap();
+    // both options are only allowed before set-logic
+    writeln!(out, "(set-option :produce-models true)").unwrap();
+    writeln!(out, "(set-option :produce-unsat-cores true)").unwrap();
+    writeln!(out, "(set-logic QF_UF)").unwrap();
+
+    writeln!(out, "\n; state variables").unwrap();
+    for step in 0..=options.steps {
+        for name in &variables {
+            writeln!(out, "(declare-const {}.{} Bool)", name, step).unwrap();
+        }
+    }
+
+    writeln!(out, "\n; active states by id").unwrap();
+    let ids: Vec<_> = core
+        .states
+        .iter()
+        .filter_map(|state| match state.id {
+            Some(ref id) if !id.contains(['|', '\\']) => Some((state.idx, id)),
+            _ => None,
+        })
+        .collect();
+    for (idx, id) in &ids {
+        for step in 0..=options.steps {
+            writeln!(
+                out,
+                "(define-fun |state:{}.{}| () Bool c{}.{})",
+                id, step, idx, step
+            )
+            .unwrap();
+        }
+    }
+
+    writeln!(out, "\n; states reached within {} steps", options.steps).unwrap();
+    for (_, id) in &ids {
+        let active: Vec<_> = (0..=options.steps)
+            .map(|step| format!("|state:{}.{}|", id, step))
+            .collect();
+        writeln!(out, "(declare-const |state:{}.reached| Bool)", id).unwrap();
+        writeln!(
+            out,
+            "(assert (! (=> |state:{}.reached| (or {})) :named |state:{}.reached.def|))",
+            id,
+            active.join(" "),
+            id
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "\n; init").unwrap();
+    for name in input_names(&init_inputs) {
+        writeln!(out, "(declare-const init_{} Bool)", name).unwrap();
+    }
+    for (name, formula) in &init.defines {
+        writeln!(
+            out,
+            "(define-fun {} () Bool {})",
+            name,
+            render(formula, None)
+        )
+        .unwrap();
+    }
+    let values: Vec<_> = variables
+        .iter()
+        .zip(init.values(size))
+        .map(|(name, formula)| format!("(= {}.0 {})", name, render(&formula, None)))
+        .collect();
+    writeln!(out, "(assert (! (and {}) :named init))", values.join(" ")).unwrap();
+
+    for step in 0..options.steps {
+        writeln!(out, "\n; step {}", step).unwrap();
+        for event in &inputs.events {
+            writeln!(out, "(declare-const e{}.{} Bool)", event, step).unwrap();
+        }
+        for name in input_names(&inputs) {
+            writeln!(out, "(declare-const {}.{} Bool)", name, step).unwrap();
+        }
+
+        let events: Vec<_> = inputs
+            .events
+            .iter()
+            .map(|event| format!("e{}.{}", event, step))
+            .collect();
+        let has_event = match events.len() {
+            0 => "false".to_string(),
+            1 => events[0].clone(),
+            _ => format!("(or {})", events.join(" ")),
+        };
+        writeln!(out, "(define-fun has_event.{} () Bool {})", step, has_event).unwrap();
+        for (i, a) in events.iter().enumerate() {
+            for b in &events[i + 1..] {
+                writeln!(out, "(assert (not (and {} {})))", a, b).unwrap();
+            }
+        }
+        // an external event is only taken once the previous macrostep has stabilized
+        writeln!(
+            out,
+            "(assert (=> (not stable.{}) (not has_event.{})))",
+            step, step
+        )
+        .unwrap();
+
+        for (name, formula) in &next.defines {
+            writeln!(
+                out,
+                "(define-fun {}.{} () Bool {})",
+                name,
+                step,
+                render(formula, Some(step))
+            )
+            .unwrap();
+        }
+        let values: Vec<_> = variables
+            .iter()
+            .zip(next.values(size))
+            .map(|(name, formula)| {
+                format!("(= {}.{} {})", name, step + 1, render(&formula, Some(step)))
+            })
+            .collect();
+        writeln!(
+            out,
+            "(assert (! (and {}) :named step.{}))",
+            values.join(" "),
+            step
+        )
+        .unwrap();
+    }
+
+    if !queries.is_empty() {
+        writeln!(
+            out,
+            "\n; queries, each reached within {} steps",
+            options.steps
+        )
+        .unwrap();
+        for (idx, reached) in queries.iter().enumerate() {
+            writeln!(
+                out,
+                "(assert (! (or {}) :named query.{}))",
+                reached.join(" "),
+                idx
+            )
+            .unwrap();
+        }
+        writeln!(out, "(check-sat)").unwrap();
+        writeln!(out, "(get-model)").unwrap();
+    }
+
+    Ok(out)
+}
+
+fn input_names(inputs: &Inputs) -> Vec<String> {
+    inputs
+        .conditions
+        .iter()
+        .map(|id| format!("cond{}", id))
+        .chain((0..inputs.data).map(|idx| format!("data{}", idx)))
+        .collect()
+}
+
+// renders a formula of the initial step (`None`) or of the given step
+fn render(formula: &Formula, step: Option<usize>) -> String {
+    let name = |name: String| match step {
+        Some(step) => format!("{}.{}", name, step),
+        None => format!("init_{}", name),
+    };
+    match formula {
+        Formula::Const(value) => value.to_string(),
+        Formula::Var(var) => match step {
+            Some(step) => format!("{}.{}", var, step),
+            None => var.clone(),
+        },
+        Formula::Condition(id) => name(format!("cond{}", id)),
+        Formula::Data(idx) => name(format!("data{}", idx)),
+        Formula::Event(id) => name(format!("e{}", id)),
+        Formula::HasEvent => name("has_event".to_string()),
+        Formula::Not(argument) => format!("(not {})", render(argument, step)),
+        Formula::And(arguments) => apply("and", arguments, step),
+        Formula::Or(arguments) => apply("or", arguments, step),
+        Formula::Xor(arguments) => apply("xor", arguments, step),
+        Formula::Ite(test, consequent, alternate) => format!(
+            "(ite {} {} {})",
+            render(test, step),
+            render(consequent, step),
+            render(alternate, step)
+        ),
+    }
+}
+
+fn apply(operator: &str, arguments: &[Formula], step: Option<usize>) -> String {
+    let arguments: Vec<_> = arguments
+        .iter()
+        .map(|argument| render(argument, step))
+        .collect();
+    format!("({} {})", operator, arguments.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+    use std::collections::HashSet;
+
+    #[test]
+    fn states_have_named_reached_assertions() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b"))
+            .atomic("b")
+            .build_core()
+            .unwrap();
+        let options = SmtOptions {
+            steps: 2,
+            ..Default::default()
+        };
+        let out = to_smt(&core, &options).unwrap();
+
+        assert!(out.contains("(set-option :produce-unsat-cores true)"));
+        assert!(out.contains("(declare-const |state:b.reached| Bool)"));
+        assert!(out.contains(
+            "(assert (! (=> |state:b.reached| (or |state:b.0| |state:b.1| |state:b.2|)) \
+             :named |state:b.reached.def|))"
+        ));
+    }
+
+    #[test]
+    fn state_ids_do_not_collide_with_generated_names() {
+        let core = Statechart::builder()
+            .state("stable", |s| {
+                s.on(0, "c1").transition(|t| t.target("c0").when(0))
+            })
+            .atomic("c1")
+            .atomic("c0")
+            .atomic("init")
+            .build_core()
+            .unwrap();
+        let out = to_smt(&core, &SmtOptions::default()).unwrap();
+
+        // `|x|` and `x` are the same symbol
+        let mut names = HashSet::new();
+        for line in out.lines() {
+            let mut words = line.split_whitespace();
+            let declared = match words.next() {
+                Some("(declare-const") | Some("(define-fun") => words.next(),
+                _ => None,
+            };
+            let named = line
+                .split(":named ")
+                .nth(1)
+                .map(|rest| rest.trim_end_matches(')'));
+            for name in declared.into_iter().chain(named) {
+                assert!(names.insert(name.trim_matches('|').to_string()), "{}", name);
+            }
+        }
+        assert!(names.contains("state:stable.0") && names.contains("stable.0"));
+    }
+}
@@ -0,0 +1,277 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::core::{Core, StateId};
+use ast::location::Location;
+use ast::microstep::to_formula::{translate_init, translate_next, variables, Formula, Inputs};
+use ast::microstep::Microstep;
+use std::fmt::Write;
+
+const SOURCE: &str = "ast/core/to_smv";
+
+#[derive(Clone, Debug, Default)]
+pub struct SmvOptions {
+    pub ctl: Vec<String>,
+    pub ltl: Vec<String>,
+}
+
+// emits a NuSMV module whose transition relation is one microstep. Conditions, events and
+// datamodel expressions become free inputs, so the model over-approximates the statechart.
+pub fn to_smv(core: &Core, options: &SmvOptions) -> Result<String, Errors> {
+    let microstep: Result<Microstep, Errors> = core.clone().into();
+    let microstep = microstep?;
+    let size = microstep.configuration_size;
+
+    let mut init_inputs = Inputs::default();
+    let init = translate_init(&microstep, &mut init_inputs).map_err(|err| vec![err])?;
+    let mut inputs = Inputs::default();
+    let next = translate_next(&microstep, &mut inputs).map_err(|err| vec![err])?;
+
+    let mut properties = vec![];
+    for (kind, list) in &[("CTLSPEC", &options.ctl), ("LTLSPEC", &options.ltl)] {
+        for property in list.iter() {
+            let property = replace_states(core, property, |idx| format!("c{}", idx))
+                .map_err(|err| vec![err])?;
+            properties.push(format!("{} {};", kind, property));
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "MODULE main").unwrap();
+
+    writeln!(out, "VAR").unwrap();
+    for name in variables(size) {
+        match name[1..].parse::<usize>() {
+            Ok(idx) if name.starts_with('c') => {
+                writeln!(out, "  {} : boolean; -- {}", name, core.state_name(idx)).unwrap()
+            }
+            _ => writeln!(out, "  {} : boolean;", name).unwrap(),
+        }
+    }
+
+    // INIT may not reference input variables, so inputs of the initial step are frozen
+    let frozen = input_names(&init_inputs, "init_");
+    if !frozen.is_empty() {
+        writeln!(out, "FROZENVAR").unwrap();
+        for name in frozen {
+            writeln!(out, "  {} : boolean;", name).unwrap();
+        }
+    }
+
+    writeln!(out, "IVAR").unwrap();
+    let events: Vec<_> = Some("none".to_string())
+        .into_iter()
+        .chain(inputs.events.iter().map(|event| format!("e{}", event)))
+        .collect();
+    writeln!(out, "  event : {{{}}};", events.join(", ")).unwrap();
+    for name in input_names(&inputs, "") {
+        writeln!(out, "  {} : boolean;", name).unwrap();
+    }
+
+    writeln!(out, "DEFINE").unwrap();
+    for (name, formula) in &init.defines {
+        writeln!(out, "  {} := {};", name, render(formula, true)).unwrap();
+    }
+    for (name, formula) in &next.defines {
+        writeln!(out, "  {} := {};", name, render(formula, false)).unwrap();
+    }
+    let variables = variables(size);
+    for (name, formula) in variables.iter().zip(init.values(size)) {
+        writeln!(out, "  {}_init := {};", name, render(&formula, true)).unwrap();
+    }
+    for (name, formula) in variables.iter().zip(next.values(size)) {
+        writeln!(out, "  {}_next := {};", name, render(&formula, false)).unwrap();
+    }
+
+    let init: Vec<_> = variables
+        .iter()
+        .map(|name| format!("{} = {}_init", name, name))
+        .collect();
+    writeln!(out, "INIT").unwrap();
+    writeln!(out, "  {}", init.join("\n  & ")).unwrap();
+
+    // an external event is only taken once the previous macrostep has stabilized
+    let next: Vec<_> = variables
+        .iter()
+        .map(|name| format!("next({}) = {}_next", name, name))
+        .collect();
+    writeln!(out, "TRANS").unwrap();
+    writeln!(out, "  (!stable -> event = none)").unwrap();
+    writeln!(out, "  & {}", next.join("\n  & ")).unwrap();
+
+    for property in properties {
+        writeln!(out, "{}", property).unwrap();
+    }
+
+    Ok(out)
+}
+
+fn input_names(inputs: &Inputs, prefix: &str) -> Vec<String> {
+    inputs
+        .conditions
+        .iter()
+        .map(|id| format!("{}cond{}", prefix, id))
+        .chain((0..inputs.data).map(|idx| format!("{}data{}", prefix, idx)))
+        .collect()
+}
+
+// replaces `in(<state id>)` with the variable of that state
+pub fn replace_states<F>(core: &Core, property: &str, f: F) -> Result<String, ConversionError>
+where
+    F: Fn(StateId) -> String,
+{
+    let mut out = String::new();
+    let mut rest = property;
+    while let Some(start) = rest.find("in(") {
+        let is_word = rest[..start]
+            .chars()
+            .last()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        out.push_str(&rest[..start]);
+        if is_word {
+            out.push_str("in(");
+            rest = &rest[start + 3..];
+            continue;
+        }
+
+        let end = match rest[start..].find(')') {
+            Some(end) => start + end,
+            None => return Err(error(format!("Unclosed in( in property: {:?}", property))),
+        };
+        let id = rest[start + 3..end].trim();
+        match core
+            .states
+            .iter()
+            .find(|state| state.id.as_deref() == Some(id))
+        {
+            Some(state) => out.push_str(&f(state.idx)),
+            None => return Err(error(format!("Unknown state in property: {:?}", id))),
+        }
+        rest = &rest[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn error(message: String) -> ConversionError {
+    ConversionError {
+        message,
+        fatal: true,
+        source: SOURCE.to_string(),
+        loc: Location::default(),
+    }
+}
+
+fn render(formula: &Formula, is_init: bool) -> String {
+    let input = |name: String| {
+        if is_init {
+            format!("init_{}", name)
+        } else {
+            name
+        }
+    };
+    match formula {
+        Formula::Const(true) => "TRUE".to_string(),
+        Formula::Const(false) => "FALSE".to_string(),
+        Formula::Var(name) => name.clone(),
+        Formula::Condition(id) => input(format!("cond{}", id)),
+        Formula::Data(idx) => input(format!("data{}", idx)),
+        Formula::Event(id) => format!("event = e{}", id),
+        Formula::HasEvent => "event != none".to_string(),
+        Formula::Not(argument) if is_name(argument) => format!("!{}", render(argument, is_init)),
+        Formula::Not(argument) => format!("!({})", render(argument, is_init)),
+        Formula::And(arguments) => join(arguments, " & ", is_init),
+        Formula::Or(arguments) => join(arguments, " | ", is_init),
+        Formula::Xor(arguments) => join(arguments, " xor ", is_init),
+        Formula::Ite(..) => {
+            let mut arms = vec![];
+            let mut formula = formula;
+            while let Formula::Ite(test, consequent, alternate) = formula {
+                arms.push(format!(
+                    "{} : {};",
+                    render(test, is_init),
+                    render(consequent, is_init)
+                ));
+                formula = alternate;
+            }
+            arms.push(format!("TRUE : {};", render(formula, is_init)));
+            format!("case {} esac", arms.join(" "))
+        }
+    }
+}
+
+fn join(arguments: &[Formula], operator: &str, is_init: bool) -> String {
+    let arguments: Vec<_> = arguments
+        .iter()
+        .map(|argument| match argument {
+            argument if is_name(argument) => render(argument, is_init),
+            Formula::Not(_) | Formula::And(_) | Formula::Or(_) | Formula::Xor(_) => {
+                render(argument, is_init)
+            }
+            argument => format!("({})", render(argument, is_init)),
+        })
+        .collect();
+    format!("({})", arguments.join(operator))
+}
+
+fn is_name(formula: &Formula) -> bool {
+    matches!(
+        formula,
+        Formula::Const(_) | Formula::Var(_) | Formula::Condition(_) | Formula::Data(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    fn chart() -> Core {
+        Statechart::builder()
+            .state("a", |s| s.on(0, "b"))
+            .atomic("b")
+            .build_core()
+            .unwrap()
+    }
+
+    #[test]
+    fn states_are_replaced_by_their_variables() {
+        let core = chart();
+        let var = |idx| format!("c{}", idx);
+        assert_eq!(
+            replace_states(&core, "AG (in(a) -> EF in( b ))", var).unwrap(),
+            "AG (c1 -> EF c2)"
+        );
+        // only a whole `in(` starts a state
+        assert_eq!(
+            replace_states(&core, "within(a) | join(b) | !in(b)", var).unwrap(),
+            "within(a) | join(b) | !c2"
+        );
+    }
+
+    #[test]
+    fn unknown_states_are_errors() {
+        let core = chart();
+        let var = |idx| format!("c{}", idx);
+        let err = replace_states(&core, "AG !in(nope)", var).unwrap_err();
+        assert_eq!(err.message, "Unknown state in property: \"nope\"");
+        assert!(replace_states(&core, "AG in(a", var).is_err());
+
+        let options = SmvOptions {
+            ctl: vec!["AG !in(nope)".to_string()],
+            ..Default::default()
+        };
+        assert!(to_smv(&core, &options).is_err());
+    }
+
+    #[test]
+    fn properties_follow_the_model() {
+        let options = SmvOptions {
+            ctl: vec!["EF in(b)".to_string()],
+            ltl: vec!["G (in(b) -> G in(b))".to_string()],
+        };
+        let out = to_smv(&chart(), &options).unwrap();
+        assert!(out.starts_with("MODULE main\n"), "{}", out);
+        assert!(out.contains("  c2 : boolean; -- b\n"), "{}", out);
+        assert!(out.contains("CTLSPEC EF c2;\n"), "{}", out);
+        assert!(out.contains("LTLSPEC G (c2 -> G c2);\n"), "{}", out);
+    }
+}
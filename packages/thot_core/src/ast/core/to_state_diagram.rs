@@ -0,0 +1,202 @@
+use ast::core::diagram::{escape_label, node_name, Syntax};
+use ast::core::{Core, State, StateId, StateType, Transition};
+use std::fmt::Write;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Dialect {
+    Mermaid,
+    PlantUml,
+}
+
+pub fn to_mermaid(core: &Core) -> String {
+    to_state_diagram(core, Dialect::Mermaid)
+}
+
+pub fn to_plantuml(core: &Core) -> String {
+    to_state_diagram(core, Dialect::PlantUml)
+}
+
+pub fn to_state_diagram(core: &Core, dialect: Dialect) -> String {
+    let mut out = String::new();
+    match dialect {
+        Dialect::Mermaid => writeln!(out, "stateDiagram-v2").unwrap(),
+        Dialect::PlantUml => writeln!(out, "@startuml").unwrap(),
+    }
+
+    if let Some(root) = core.states.first() {
+        write_body(&mut out, core, root, dialect, 1);
+    }
+
+    for transition in &core.transitions {
+        if is_scoped(core, transition) {
+            continue;
+        }
+        write_transition(&mut out, core, transition, dialect, 1);
+    }
+
+    if dialect == Dialect::PlantUml {
+        writeln!(out, "@enduml").unwrap();
+    }
+    out
+}
+
+fn write_body(out: &mut String, core: &Core, state: &State, dialect: Dialect, depth: usize) {
+    let indent = "    ".repeat(depth);
+
+    for (i, child) in state.children.iter().enumerate() {
+        if state.t == StateType::Parallel && i > 0 {
+            writeln!(out, "{}--", indent).unwrap();
+        }
+        write_state(out, core, &core.states[*child], dialect, depth);
+    }
+
+    // the initial marker points at the explicit initial transitions or the first child
+    match state
+        .children
+        .iter()
+        .find(|c| core.states[**c].t == StateType::Initial)
+    {
+        Some(initial) => {
+            for transition in &core.states[*initial].transitions {
+                for target in &core.transitions[*transition].targets {
+                    writeln!(
+                        out,
+                        "{}[*] --> {}",
+                        indent,
+                        reference(core, *target, dialect)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        None => {
+            if state.t != StateType::Parallel {
+                if let Some(initial) = state.initial.first() {
+                    writeln!(
+                        out,
+                        "{}[*] --> {}",
+                        indent,
+                        reference(core, *initial, dialect)
+                    )
+                    .unwrap();
+                }
+            }
+        }
+    }
+
+    // transitions into final states use the [*] marker of their enclosing scope
+    for child in &state.children {
+        if core.states[*child].t != StateType::Final {
+            continue;
+        }
+        for transition in &core.transitions {
+            if transition.targets.contains(child) {
+                writeln!(
+                    out,
+                    "{}{} --> [*]{}",
+                    indent,
+                    reference(core, transition.source, dialect),
+                    label(core, transition, dialect)
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+fn write_state(out: &mut String, core: &Core, state: &State, dialect: Dialect, depth: usize) {
+    let indent = "    ".repeat(depth);
+    let name = node_name(state.idx);
+    let label = escape(&core.state_name(state.idx), dialect);
+
+    match state.t {
+        StateType::Initial | StateType::Final => (),
+        StateType::HistoryShallow | StateType::HistoryDeep => {
+            if dialect == Dialect::Mermaid {
+                let label = if state.t == StateType::HistoryDeep {
+                    "H*"
+                } else {
+                    "H"
+                };
+                writeln!(out, "{}state \"{}\" as {}", indent, label, name).unwrap();
+            }
+        }
+        _ if state.children.is_empty() => {
+            writeln!(out, "{}state \"{}\" as {}", indent, label, name).unwrap();
+        }
+        _ => {
+            match dialect {
+                Dialect::Mermaid => {
+                    writeln!(out, "{}state \"{}\" as {}", indent, label, name).unwrap();
+                    writeln!(out, "{}state {} {{", indent, name).unwrap();
+                }
+                Dialect::PlantUml => {
+                    writeln!(out, "{}state \"{}\" as {} {{", indent, label, name).unwrap();
+                }
+            }
+            write_body(out, core, state, dialect, depth + 1);
+            writeln!(out, "{}}}", indent).unwrap();
+        }
+    }
+}
+
+fn write_transition(
+    out: &mut String,
+    core: &Core,
+    transition: &Transition,
+    dialect: Dialect,
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    let source = reference(core, transition.source, dialect);
+    let label = label(core, transition, dialect);
+
+    if transition.targets.is_empty() {
+        writeln!(out, "{}{} --> {}{}", indent, source, source, label).unwrap();
+    }
+
+    for target in &transition.targets {
+        if core.states[*target].t == StateType::Final {
+            continue;
+        }
+        writeln!(
+            out,
+            "{}{} --> {}{}",
+            indent,
+            source,
+            reference(core, *target, dialect),
+            label
+        )
+        .unwrap();
+    }
+}
+
+fn is_scoped(core: &Core, transition: &Transition) -> bool {
+    core.states[transition.source].t == StateType::Initial
+}
+
+fn reference(core: &Core, idx: StateId, dialect: Dialect) -> String {
+    let state = &core.states[idx];
+    match (dialect, state.t) {
+        (Dialect::PlantUml, StateType::HistoryShallow) => format!("{}[H]", node_name(state.parent)),
+        (Dialect::PlantUml, StateType::HistoryDeep) => format!("{}[H*]", node_name(state.parent)),
+        _ => node_name(idx),
+    }
+}
+
+fn label(core: &Core, transition: &Transition, dialect: Dialect) -> String {
+    let label = core.transition_label(transition);
+    if label.is_empty() {
+        label
+    } else {
+        format!(" : {}", escape(&label, dialect))
+    }
+}
+
+fn escape(label: &str, dialect: Dialect) -> String {
+    let syntax = match dialect {
+        Dialect::Mermaid => Syntax::Mermaid,
+        Dialect::PlantUml => Syntax::PlantUml,
+    };
+    escape_label(label, syntax)
+}
@@ -0,0 +1,101 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::core::{Core, StateId};
+use ast::location::Location;
+
+const SOURCE: &str = "ast/core/validate";
+
+// checks the fields every derived one is computed from: indices match positions, parents
+// and transition endpoints are in range and the parents form a tree. Whether the derived
+// fields agree with them is left to `Core::verify`.
+pub fn validate(core: &Core) -> Result<(), Errors> {
+    let mut errors = vec![];
+    if core.states.is_empty() {
+        errors.push(error("Core has no root state".to_string(), core.loc));
+        return Err(errors);
+    }
+    let len = core.states.len();
+
+    for (idx, state) in core.states.iter().enumerate() {
+        if state.idx != idx {
+            errors.push(error(
+                format!("State at position {} has idx {}", idx, state.idx),
+                state.loc,
+            ));
+        }
+        if state.parent >= len {
+            errors.push(error(
+                format!("State {} has a missing parent {}", idx, state.parent),
+                state.loc,
+            ));
+        }
+    }
+    for (idx, transition) in core.transitions.iter().enumerate() {
+        if transition.idx != idx {
+            errors.push(error(
+                format!("Transition at position {} has idx {}", idx, transition.idx),
+                transition.loc,
+            ));
+        }
+        for state in Some(&transition.source)
+            .into_iter()
+            .chain(&transition.targets)
+        {
+            if *state >= len {
+                errors.push(error(
+                    format!("Transition {} refers to missing state {}", idx, state),
+                    transition.loc,
+                ));
+            }
+        }
+    }
+    // the parents can only be followed once they are in range
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let root = &core.states[0];
+    if root.parent != 0 {
+        errors.push(error(
+            "The root state may not have a parent".to_string(),
+            root.loc,
+        ));
+    }
+    for state in core.states.iter().skip(1) {
+        if parents(core, state.idx).is_none() {
+            errors.push(error(
+                format!("The parents of state {} do not lead to the root", state.idx),
+                state.loc,
+            ));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// the ancestors of `idx` from the root down, or None if the parents form a cycle
+pub fn parents(core: &Core, idx: StateId) -> Option<Vec<StateId>> {
+    let mut chain = vec![];
+    let mut state = idx;
+    while state != 0 {
+        state = core.states[state].parent;
+        if state == idx || chain.len() >= core.states.len() {
+            return None;
+        }
+        chain.push(state);
+    }
+    chain.reverse();
+    Some(chain)
+}
+
+fn error(message: String, loc: Location) -> ConversionError {
+    ConversionError {
+        message,
+        fatal: true,
+        source: SOURCE.to_string(),
+        loc,
+    }
+}
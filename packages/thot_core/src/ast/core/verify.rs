@@ -0,0 +1,201 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::core::validate::{parents, validate};
+use ast::core::{Core, State, StateId, StateType, Transition};
+use ast::location::Location;
+use ast::statechart::to_core::StateTree;
+
+const SOURCE: &str = "ast/core/verify";
+
+impl Core {
+    // recomputes every derived field from the parents, state types and transition targets
+    // and reports each field that differs. Whether a state is atomic or compound is derived
+    // from its children too.
+    pub fn verify(&self) -> Errors {
+        self.clone().repair()
+    }
+
+    // like `verify`, overwriting the fields that differ. Nothing is changed if the parents
+    // or transition endpoints themselves are broken, see `validate`.
+    pub fn repair(&mut self) -> Errors {
+        if let Err(errors) = validate(self) {
+            return errors;
+        }
+
+        let mut errors = vec![];
+        let states = derive_states(self);
+        for (state, derived) in self.states.iter_mut().zip(states) {
+            let name = match state.id {
+                Some(ref id) => id.clone(),
+                None => format!("#{}", state.idx),
+            };
+            if state.t != derived.t {
+                errors.push(ConversionError {
+                    message: format!(
+                        "State {} type is {:?} but should be {:?}",
+                        name, state.t, derived.t
+                    ),
+                    fatal: false,
+                    source: SOURCE.to_string(),
+                    loc: state.loc,
+                });
+                state.t = derived.t;
+            }
+            let fields = [
+                ("children", &mut state.children, derived.children),
+                ("ancestors", &mut state.ancestors, derived.ancestors),
+                ("descendants", &mut state.descendants, derived.descendants),
+                ("initial", &mut state.initial, derived.initial),
+                ("transitions", &mut state.transitions, derived.transitions),
+            ];
+            for (field, value, expected) in fields {
+                if *value != expected {
+                    errors.push(mismatch(
+                        format!("State {} {}", name, field),
+                        value,
+                        &expected,
+                        state.loc,
+                    ));
+                    *value = expected;
+                }
+            }
+        }
+
+        let tree = StateTree::new(&self.states);
+        for transition in self.transitions.iter_mut() {
+            let exits = tree.exit_set(transition);
+            if transition.exits != exits {
+                errors.push(mismatch(
+                    format!("Transition {} exits", transition.idx),
+                    &transition.exits,
+                    &exits,
+                    transition.loc,
+                ));
+                transition.exits = exits;
+            }
+        }
+
+        // conflicts depend on the exits of both transitions, so they come last
+        let transitions: Vec<Transition> = self.transitions.clone();
+        let conflicts = tree.conflicts(&transitions);
+        for transition in self.transitions.iter_mut() {
+            let conflicts = conflicts.get(transition);
+            if transition.conflicts != conflicts {
+                errors.push(mismatch(
+                    format!("Transition {} conflicts", transition.idx),
+                    &transition.conflicts,
+                    &conflicts,
+                    transition.loc,
+                ));
+                transition.conflicts = conflicts;
+            }
+        }
+
+        errors
+    }
+}
+
+// the tree fields as `to_core` fills them in: children and transitions in document order,
+// descendants in the order their states close
+fn derive_states(core: &Core) -> Vec<State> {
+    let mut states: Vec<State> = core
+        .states
+        .iter()
+        .map(|state| State {
+            idx: state.idx,
+            t: state.t,
+            parent: state.parent,
+            ..Default::default()
+        })
+        .collect();
+
+    for idx in 1..states.len() {
+        let parent = states[idx].parent;
+        states[parent].children.push(idx);
+        states[idx].ancestors = parents(core, idx).unwrap();
+    }
+    for state in states.iter_mut() {
+        state.t = match state.t {
+            StateType::Atomic | StateType::Compound if state.children.is_empty() => {
+                StateType::Atomic
+            }
+            StateType::Atomic | StateType::Compound => StateType::Compound,
+            t => t,
+        };
+    }
+    for transition in &core.transitions {
+        states[transition.source].transitions.push(transition.idx);
+    }
+
+    let mut order = vec![];
+    post_order(&states, 0, &mut order);
+    for idx in order {
+        for ancestor in states[idx].ancestors.clone() {
+            states[ancestor].descendants.push(idx);
+        }
+    }
+
+    for state in states.iter_mut() {
+        let initial = &core.states[state.idx].initial;
+        state.initial = match state.t {
+            StateType::Parallel | StateType::HistoryShallow => state.children.clone(),
+            // the `initial` attribute may pick any one child
+            StateType::Compound if initial.len() == 1 && state.children.contains(&initial[0]) => {
+                initial.clone()
+            }
+            StateType::Compound => state.children.iter().take(1).cloned().collect(),
+            StateType::HistoryDeep => state.descendants.clone(),
+            _ => vec![],
+        };
+    }
+    states
+}
+
+fn post_order(states: &[State], idx: StateId, order: &mut Vec<StateId>) {
+    for child in &states[idx].children {
+        post_order(states, *child, order);
+    }
+    order.push(idx);
+}
+
+fn mismatch(field: String, value: &[usize], expected: &[usize], loc: Location) -> ConversionError {
+    ConversionError {
+        message: format!("{} is {:?} but should be {:?}", field, value, expected),
+        fatal: false,
+        source: SOURCE.to_string(),
+        loc,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    #[test]
+    fn atomic_and_compound_are_repaired() {
+        let mut core = Statechart::builder()
+            .state("a", |s| s.atomic("a1").on(0, "b"))
+            .atomic("b")
+            .build_core()
+            .unwrap();
+        let (a, b) = (1, 3);
+        assert_eq!(core.states[a].t, StateType::Compound);
+        assert_eq!(core.states[b].t, StateType::Atomic);
+        core.states[a].t = StateType::Atomic;
+        core.states[b].t = StateType::Compound;
+
+        let errors = core.verify();
+        let messages: Vec<_> = errors.iter().map(|err| err.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "State a type is Atomic but should be Compound",
+                "State b type is Compound but should be Atomic",
+            ]
+        );
+        assert_eq!(core.repair().len(), 2);
+        assert_eq!(core.states[a].t, StateType::Compound);
+        assert_eq!(core.states[b].t, StateType::Atomic);
+        assert!(core.verify().is_empty());
+    }
+}
@@ -0,0 +1,399 @@
+use ast::core::*;
+use ast::datamodel::{BinaryExpression, Expression, UnaryExpression, Variable};
+use ast::executable::{Assign, Log};
+
+// Each method of `Visit`, `VisitMut` and `Fold` defaults to the function of the same name
+// in this module, which recurses into the children of the node. Overriding a method and
+// calling that function from it keeps the recursion going.
+
+pub trait Visit<'ast> {
+    fn visit_core(&mut self, node: &'ast Core) {
+        visit_core(self, node)
+    }
+    fn visit_state(&mut self, node: &'ast State) {
+        visit_state(self, node)
+    }
+    fn visit_transition(&mut self, node: &'ast Transition) {
+        visit_transition(self, node)
+    }
+    fn visit_condition(&mut self, node: &'ast Condition) {
+        visit_condition(self, node)
+    }
+    fn visit_executable(&mut self, node: &'ast Executable) {
+        visit_executable(self, node)
+    }
+    fn visit_variable(&mut self, node: &'ast Variable) {
+        visit_variable(self, node)
+    }
+    fn visit_expression(&mut self, node: &'ast Expression) {
+        visit_expression(self, node)
+    }
+}
+
+pub fn visit_core<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Core) {
+    for state in &node.states {
+        v.visit_state(state);
+    }
+    for transition in &node.transitions {
+        v.visit_transition(transition);
+    }
+}
+
+pub fn visit_state<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast State) {
+    for variable in &node.datamodel {
+        v.visit_variable(variable);
+    }
+    for executable in node
+        .on_init
+        .iter()
+        .chain(&node.on_enter)
+        .chain(&node.on_exit)
+    {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_transition<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Transition) {
+    if let Some(ref condition) = node.condition {
+        v.visit_condition(condition);
+    }
+    for executable in &node.on_transition {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_condition<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Condition) {
+    match node {
+        Condition::Predicate(_) | Condition::In(_) => {}
+        Condition::And(node) | Condition::Or(node) => {
+            for child in &node.children {
+                v.visit_condition(child);
+            }
+        }
+        Condition::Not(node) => v.visit_condition(&node.condition),
+        Condition::Expression(node) => v.visit_expression(&node.expr),
+    }
+}
+
+pub fn visit_executable<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Executable) {
+    match node {
+        Executable::Assign(node) => v.visit_expression(&node.expr),
+        Executable::Log(node) => {
+            if let Some(ref expr) = node.expr {
+                v.visit_expression(expr);
+            }
+        }
+        Executable::If(node) => {
+            v.visit_condition(&node.condition);
+            for child in &node.children {
+                v.visit_executable(child);
+            }
+            for branch in &node.else_if {
+                v.visit_condition(&branch.condition);
+                for child in &branch.children {
+                    v.visit_executable(child);
+                }
+            }
+            for child in &node.otherwise {
+                v.visit_executable(child);
+            }
+        }
+        Executable::Foreach(node) => {
+            for child in &node.children {
+                v.visit_executable(child);
+            }
+        }
+        Executable::Script(_) | Executable::Raise(_) | Executable::Send(_) => {}
+        Executable::Cancel(_) => {}
+    }
+}
+
+pub fn visit_variable<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Variable) {
+    if let Some(ref init) = node.init {
+        v.visit_expression(init);
+    }
+}
+
+pub fn visit_expression<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Expression) {
+    match node {
+        Expression::Unary(node) => v.visit_expression(&node.argument),
+        Expression::Binary(node) => {
+            v.visit_expression(&node.left);
+            v.visit_expression(&node.right);
+        }
+        _ => {}
+    }
+}
+
+pub trait VisitMut {
+    fn visit_core_mut(&mut self, node: &mut Core) {
+        visit_core_mut(self, node)
+    }
+    fn visit_state_mut(&mut self, node: &mut State) {
+        visit_state_mut(self, node)
+    }
+    fn visit_transition_mut(&mut self, node: &mut Transition) {
+        visit_transition_mut(self, node)
+    }
+    fn visit_condition_mut(&mut self, node: &mut Condition) {
+        visit_condition_mut(self, node)
+    }
+    fn visit_executable_mut(&mut self, node: &mut Executable) {
+        visit_executable_mut(self, node)
+    }
+    fn visit_variable_mut(&mut self, node: &mut Variable) {
+        visit_variable_mut(self, node)
+    }
+    fn visit_expression_mut(&mut self, node: &mut Expression) {
+        visit_expression_mut(self, node)
+    }
+}
+
+pub fn visit_core_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Core) {
+    for state in &mut node.states {
+        v.visit_state_mut(state);
+    }
+    for transition in &mut node.transitions {
+        v.visit_transition_mut(transition);
+    }
+}
+
+pub fn visit_state_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut State) {
+    for variable in &mut node.datamodel {
+        v.visit_variable_mut(variable);
+    }
+    for executable in node
+        .on_init
+        .iter_mut()
+        .chain(&mut node.on_enter)
+        .chain(&mut node.on_exit)
+    {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_transition_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Transition) {
+    if let Some(ref mut condition) = node.condition {
+        v.visit_condition_mut(condition);
+    }
+    for executable in &mut node.on_transition {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_condition_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Condition) {
+    match node {
+        Condition::Predicate(_) | Condition::In(_) => {}
+        Condition::And(node) | Condition::Or(node) => {
+            for child in &mut node.children {
+                v.visit_condition_mut(child);
+            }
+        }
+        Condition::Not(node) => v.visit_condition_mut(&mut node.condition),
+        Condition::Expression(node) => v.visit_expression_mut(&mut node.expr),
+    }
+}
+
+pub fn visit_executable_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Executable) {
+    match node {
+        Executable::Assign(node) => v.visit_expression_mut(&mut node.expr),
+        Executable::Log(node) => {
+            if let Some(ref mut expr) = node.expr {
+                v.visit_expression_mut(expr);
+            }
+        }
+        Executable::If(node) => {
+            v.visit_condition_mut(&mut node.condition);
+            for child in &mut node.children {
+                v.visit_executable_mut(child);
+            }
+            for branch in &mut node.else_if {
+                v.visit_condition_mut(&mut branch.condition);
+                for child in &mut branch.children {
+                    v.visit_executable_mut(child);
+                }
+            }
+            for child in &mut node.otherwise {
+                v.visit_executable_mut(child);
+            }
+        }
+        Executable::Foreach(node) => {
+            for child in &mut node.children {
+                v.visit_executable_mut(child);
+            }
+        }
+        Executable::Script(_) | Executable::Raise(_) | Executable::Send(_) => {}
+        Executable::Cancel(_) => {}
+    }
+}
+
+pub fn visit_variable_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Variable) {
+    if let Some(ref mut init) = node.init {
+        v.visit_expression_mut(init);
+    }
+}
+
+pub fn visit_expression_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Expression) {
+    match node {
+        Expression::Unary(node) => v.visit_expression_mut(&mut node.argument),
+        Expression::Binary(node) => {
+            v.visit_expression_mut(&mut node.left);
+            v.visit_expression_mut(&mut node.right);
+        }
+        _ => {}
+    }
+}
+
+pub trait Fold {
+    fn fold_core(&mut self, node: Core) -> Core {
+        fold_core(self, node)
+    }
+    fn fold_state(&mut self, node: State) -> State {
+        fold_state(self, node)
+    }
+    fn fold_transition(&mut self, node: Transition) -> Transition {
+        fold_transition(self, node)
+    }
+    fn fold_condition(&mut self, node: Condition) -> Condition {
+        fold_condition(self, node)
+    }
+    fn fold_executable(&mut self, node: Executable) -> Executable {
+        fold_executable(self, node)
+    }
+    fn fold_variable(&mut self, node: Variable) -> Variable {
+        fold_variable(self, node)
+    }
+    fn fold_expression(&mut self, node: Expression) -> Expression {
+        fold_expression(self, node)
+    }
+}
+
+fn fold_executables<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Executable>) -> Vec<Executable> {
+    nodes
+        .into_iter()
+        .map(|node| f.fold_executable(node))
+        .collect()
+}
+
+pub fn fold_core<F: Fold + ?Sized>(f: &mut F, node: Core) -> Core {
+    Core {
+        states: node
+            .states
+            .into_iter()
+            .map(|state| f.fold_state(state))
+            .collect(),
+        transitions: node
+            .transitions
+            .into_iter()
+            .map(|transition| f.fold_transition(transition))
+            .collect(),
+        ..node
+    }
+}
+
+pub fn fold_state<F: Fold + ?Sized>(f: &mut F, node: State) -> State {
+    State {
+        datamodel: node
+            .datamodel
+            .into_iter()
+            .map(|variable| f.fold_variable(variable))
+            .collect(),
+        on_init: fold_executables(f, node.on_init),
+        on_enter: fold_executables(f, node.on_enter),
+        on_exit: fold_executables(f, node.on_exit),
+        ..node
+    }
+}
+
+pub fn fold_transition<F: Fold + ?Sized>(f: &mut F, node: Transition) -> Transition {
+    Transition {
+        condition: node.condition.map(|condition| f.fold_condition(condition)),
+        on_transition: fold_executables(f, node.on_transition),
+        ..node
+    }
+}
+
+pub fn fold_condition<F: Fold + ?Sized>(f: &mut F, node: Condition) -> Condition {
+    match node {
+        Condition::And(node) => Condition::And(Conditions {
+            children: node
+                .children
+                .into_iter()
+                .map(|child| f.fold_condition(child))
+                .collect(),
+            ..node
+        }),
+        Condition::Or(node) => Condition::Or(Conditions {
+            children: node
+                .children
+                .into_iter()
+                .map(|child| f.fold_condition(child))
+                .collect(),
+            ..node
+        }),
+        Condition::Not(node) => Condition::Not(Not {
+            condition: Box::new(f.fold_condition(*node.condition)),
+            ..node
+        }),
+        Condition::Expression(node) => Condition::Expression(ExpressionCondition {
+            expr: f.fold_expression(node.expr),
+            ..node
+        }),
+        node => node,
+    }
+}
+
+pub fn fold_executable<F: Fold + ?Sized>(f: &mut F, node: Executable) -> Executable {
+    match node {
+        Executable::Assign(node) => Executable::Assign(Assign {
+            expr: f.fold_expression(node.expr),
+            ..node
+        }),
+        Executable::Log(node) => Executable::Log(Log {
+            expr: node.expr.map(|expr| f.fold_expression(expr)),
+            ..node
+        }),
+        Executable::If(node) => Executable::If(If {
+            condition: f.fold_condition(node.condition),
+            children: fold_executables(f, node.children),
+            else_if: node
+                .else_if
+                .into_iter()
+                .map(|branch| ElseIf {
+                    condition: f.fold_condition(branch.condition),
+                    children: fold_executables(f, branch.children),
+                    ..branch
+                })
+                .collect(),
+            otherwise: fold_executables(f, node.otherwise),
+            ..node
+        }),
+        Executable::Foreach(node) => Executable::Foreach(Foreach {
+            children: fold_executables(f, node.children),
+            ..node
+        }),
+        node => node,
+    }
+}
+
+pub fn fold_variable<F: Fold + ?Sized>(f: &mut F, node: Variable) -> Variable {
+    Variable {
+        init: node.init.map(|init| f.fold_expression(init)),
+        ..node
+    }
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(f: &mut F, node: Expression) -> Expression {
+    match node {
+        Expression::Unary(node) => Expression::Unary(UnaryExpression {
+            argument: Box::new(f.fold_expression(*node.argument)),
+            ..node
+        }),
+        Expression::Binary(node) => Expression::Binary(BinaryExpression {
+            left: Box::new(f.fold_expression(*node.left)),
+            right: Box::new(f.fold_expression(*node.right)),
+            ..node
+        }),
+        node => node,
+    }
+}
@@ -0,0 +1,508 @@
+use ast::location::Location;
+use std::collections::HashMap;
+use std::fmt;
+
+pub type VariableId = String;
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Type {
+    Integer,
+    Boolean,
+    String,
+    Enum(Vec<String>),
+}
+
+impl Type {
+    pub fn default_value(&self) -> Value {
+        match self {
+            Type::Integer => Value::Integer(0),
+            Type::Boolean => Value::Boolean(false),
+            Type::String => Value::String(String::new()),
+            Type::Enum(variants) => Value::Enum(variants.first().cloned().unwrap_or_default()),
+        }
+    }
+
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Enum(variants), Type::Enum(others)) => {
+                others.iter().all(|variant| variants.contains(variant))
+            }
+            _ => self == other,
+        }
+    }
+
+    pub fn accepts_value(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Integer, Value::Integer(_))
+            | (Type::Boolean, Value::Boolean(_))
+            | (Type::String, Value::String(_)) => true,
+            (Type::Enum(variants), Value::Enum(variant)) => variants.contains(variant),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Value {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+    Enum(String),
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Variable {
+    pub id: VariableId,
+
+    #[serde(rename = "type")]
+    pub t: Type,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub init: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Expression {
+    Integer(IntegerLiteral),
+    Boolean(BooleanLiteral),
+    String(StringLiteral),
+    Enum(EnumLiteral),
+    Variable(VariableReference),
+    Unary(UnaryExpression),
+    Binary(BinaryExpression),
+}
+
+impl Expression {
+    pub fn loc(&self) -> Location {
+        match self {
+            Expression::Integer(node) => node.loc,
+            Expression::Boolean(node) => node.loc,
+            Expression::String(node) => node.loc,
+            Expression::Enum(node) => node.loc,
+            Expression::Variable(node) => node.loc,
+            Expression::Unary(node) => node.loc,
+            Expression::Binary(node) => node.loc,
+        }
+    }
+
+    pub fn type_of(&self, types: &HashMap<VariableId, Type>) -> Result<Type, EvalError> {
+        match self {
+            Expression::Integer(_) => Ok(Type::Integer),
+            Expression::Boolean(_) => Ok(Type::Boolean),
+            Expression::String(_) => Ok(Type::String),
+            Expression::Enum(node) => Ok(Type::Enum(vec![node.value.clone()])),
+            Expression::Variable(node) => types.get(&node.id).cloned().ok_or_else(|| {
+                EvalError::new(format!("Unknown variable: {:?}", node.id), node.loc)
+            }),
+            Expression::Unary(node) => {
+                let argument = node.argument.type_of(types)?;
+                let expected = match node.operator {
+                    UnaryOperator::Not => Type::Boolean,
+                    UnaryOperator::Negate => Type::Integer,
+                };
+                if argument != expected {
+                    return Err(EvalError::new(
+                        format!("Expected {:?} operand for {:?}", expected, node.operator),
+                        node.loc,
+                    ));
+                }
+                Ok(expected)
+            }
+            Expression::Binary(node) => {
+                let left = node.left.type_of(types)?;
+                let right = node.right.type_of(types)?;
+                let operands = match node.operator {
+                    BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div
+                    | BinaryOperator::Rem
+                    | BinaryOperator::Lt
+                    | BinaryOperator::Le
+                    | BinaryOperator::Gt
+                    | BinaryOperator::Ge => left == Type::Integer && right == Type::Integer,
+                    BinaryOperator::And | BinaryOperator::Or => {
+                        left == Type::Boolean && right == Type::Boolean
+                    }
+                    BinaryOperator::Eq | BinaryOperator::Ne => {
+                        left.accepts(&right) || right.accepts(&left)
+                    }
+                };
+                if !operands {
+                    return Err(EvalError::new(
+                        format!(
+                            "Mismatched operands for {:?}: {:?} and {:?}",
+                            node.operator, left, right
+                        ),
+                        node.loc,
+                    ));
+                }
+                Ok(match node.operator {
+                    BinaryOperator::Add
+                    | BinaryOperator::Sub
+                    | BinaryOperator::Mul
+                    | BinaryOperator::Div
+                    | BinaryOperator::Rem => Type::Integer,
+                    _ => Type::Boolean,
+                })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expression::Integer(node) => write!(f, "{}", node.value),
+            Expression::Boolean(node) => write!(f, "{}", node.value),
+            Expression::String(node) => write!(f, "{}", quote(&node.value, '"')),
+            Expression::Enum(node) => write!(f, "{}", quote(&node.value, '\'')),
+            Expression::Variable(node) => write!(f, "{}", node.id),
+            Expression::Unary(node) => {
+                let operator = match node.operator {
+                    UnaryOperator::Not => "!",
+                    UnaryOperator::Negate => "-",
+                };
+                match node.argument.as_ref() {
+                    Expression::Binary(_) => write!(f, "{}({})", operator, node.argument),
+                    argument => write!(f, "{}{}", operator, argument),
+                }
+            }
+            Expression::Binary(node) => {
+                let operator = match node.operator {
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Sub => "-",
+                    BinaryOperator::Mul => "*",
+                    BinaryOperator::Div => "/",
+                    BinaryOperator::Rem => "%",
+                    BinaryOperator::Eq => "==",
+                    BinaryOperator::Ne => "!=",
+                    BinaryOperator::Lt => "<",
+                    BinaryOperator::Le => "<=",
+                    BinaryOperator::Gt => ">",
+                    BinaryOperator::Ge => ">=",
+                    BinaryOperator::And => "&&",
+                    BinaryOperator::Or => "||",
+                };
+                for (i, operand) in [&node.left, &node.right].iter().enumerate() {
+                    if i == 1 {
+                        write!(f, " {} ", operator)?;
+                    }
+                    match operand.as_ref() {
+                        Expression::Binary(_) => write!(f, "({})", operand)?,
+                        operand => write!(f, "{}", operand)?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// wraps a literal in `delimiter`, escaping backslashes, the delimiter, newlines, tabs and
+// carriage returns with a backslash. `unescape` reverses it.
+pub fn quote(value: &str, delimiter: char) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push(delimiter);
+    for c in value.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            c if c == delimiter => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push(delimiter);
+    out
+}
+
+// the character a backslash escape in a quoted literal stands for
+pub fn unescape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        other => other,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct IntegerLiteral {
+    pub value: i64,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BooleanLiteral {
+    pub value: bool,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct StringLiteral {
+    pub value: String,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumLiteral {
+    pub value: String,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VariableReference {
+    pub id: VariableId,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnaryOperator {
+    Not,
+    Negate,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnaryExpression {
+    pub operator: UnaryOperator,
+
+    pub argument: Box<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BinaryOperator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct BinaryExpression {
+    pub operator: BinaryOperator,
+
+    pub left: Box<Expression>,
+
+    pub right: Box<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EvalError {
+    pub message: String,
+    pub loc: Location,
+}
+
+impl EvalError {
+    fn new(message: String, loc: Location) -> EvalError {
+        EvalError { message, loc }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Datamodel {
+    types: HashMap<VariableId, Type>,
+    values: HashMap<VariableId, Value>,
+}
+
+impl Datamodel {
+    pub fn new(variables: &[Variable]) -> Datamodel {
+        let mut datamodel = Datamodel::default();
+        for variable in variables {
+            datamodel
+                .values
+                .insert(variable.id.clone(), variable.t.default_value());
+            datamodel
+                .types
+                .insert(variable.id.clone(), variable.t.clone());
+        }
+        datamodel
+    }
+
+    pub fn types(&self) -> &HashMap<VariableId, Type> {
+        &self.types
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Value> {
+        self.values.get(id)
+    }
+
+    pub fn set(&mut self, id: &str, value: Value, loc: Location) -> Result<(), EvalError> {
+        match self.types.get(id) {
+            Some(t) if t.accepts_value(&value) => {
+                self.values.insert(id.to_string(), value);
+                Ok(())
+            }
+            Some(t) => Err(EvalError::new(
+                format!("Cannot assign {:?} to {:?} of type {:?}", value, id, t),
+                loc,
+            )),
+            None => Err(EvalError::new(format!("Unknown variable: {:?}", id), loc)),
+        }
+    }
+
+    pub fn assign(&mut self, id: &str, expr: &Expression) -> Result<(), EvalError> {
+        let value = self.eval(expr)?;
+        self.set(id, value, expr.loc())
+    }
+
+    pub fn eval(&self, expr: &Expression) -> Result<Value, EvalError> {
+        match expr {
+            Expression::Integer(node) => Ok(Value::Integer(node.value)),
+            Expression::Boolean(node) => Ok(Value::Boolean(node.value)),
+            Expression::String(node) => Ok(Value::String(node.value.clone())),
+            Expression::Enum(node) => Ok(Value::Enum(node.value.clone())),
+            Expression::Variable(node) => self.values.get(&node.id).cloned().ok_or_else(|| {
+                EvalError::new(format!("Unknown variable: {:?}", node.id), node.loc)
+            }),
+            Expression::Unary(node) => match (node.operator, self.eval(&node.argument)?) {
+                (UnaryOperator::Not, Value::Boolean(value)) => Ok(Value::Boolean(!value)),
+                (UnaryOperator::Negate, Value::Integer(value)) => {
+                    Ok(Value::Integer(value.wrapping_neg()))
+                }
+                (operator, value) => Err(EvalError::new(
+                    format!("Invalid operand for {:?}: {:?}", operator, value),
+                    node.loc,
+                )),
+            },
+            Expression::Binary(node) => self.eval_binary(node),
+        }
+    }
+
+    pub fn eval_bool(&self, expr: &Expression) -> Result<bool, EvalError> {
+        match self.eval(expr)? {
+            Value::Boolean(value) => Ok(value),
+            value => Err(EvalError::new(
+                format!("Expected a boolean, found {:?}", value),
+                expr.loc(),
+            )),
+        }
+    }
+
+    fn eval_binary(&self, node: &BinaryExpression) -> Result<Value, EvalError> {
+        let loc = node.loc;
+
+        // short-circuit the logical operators before evaluating the right side
+        match node.operator {
+            BinaryOperator::And => {
+                return Ok(Value::Boolean(
+                    self.eval_bool(&node.left)? && self.eval_bool(&node.right)?,
+                ))
+            }
+            BinaryOperator::Or => {
+                return Ok(Value::Boolean(
+                    self.eval_bool(&node.left)? || self.eval_bool(&node.right)?,
+                ))
+            }
+            _ => (),
+        }
+
+        let left = self.eval(&node.left)?;
+        let right = self.eval(&node.right)?;
+
+        match (node.operator, left, right) {
+            (BinaryOperator::Eq, left, right) => Ok(Value::Boolean(left == right)),
+            (BinaryOperator::Ne, left, right) => Ok(Value::Boolean(left != right)),
+            (operator, Value::Integer(left), Value::Integer(right)) => match operator {
+                BinaryOperator::Add => Ok(Value::Integer(left.wrapping_add(right))),
+                BinaryOperator::Sub => Ok(Value::Integer(left.wrapping_sub(right))),
+                BinaryOperator::Mul => Ok(Value::Integer(left.wrapping_mul(right))),
+                BinaryOperator::Div | BinaryOperator::Rem if right == 0 => {
+                    Err(EvalError::new("Division by zero".to_string(), loc))
+                }
+                BinaryOperator::Div => Ok(Value::Integer(left.wrapping_div(right))),
+                BinaryOperator::Rem => Ok(Value::Integer(left.wrapping_rem(right))),
+                BinaryOperator::Lt => Ok(Value::Boolean(left < right)),
+                BinaryOperator::Le => Ok(Value::Boolean(left <= right)),
+                BinaryOperator::Gt => Ok(Value::Boolean(left > right)),
+                BinaryOperator::Ge => Ok(Value::Boolean(left >= right)),
+                _ => unreachable!(),
+            },
+            (operator, left, right) => Err(EvalError::new(
+                format!(
+                    "Invalid operands for {:?}: {:?} and {:?}",
+                    operator, left, right
+                ),
+                loc,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integer(value: i64) -> Expression {
+        Expression::Integer(IntegerLiteral {
+            value,
+            loc: Location::default(),
+        })
+    }
+
+    fn negate(argument: Expression) -> Expression {
+        Expression::Unary(UnaryExpression {
+            operator: UnaryOperator::Negate,
+            argument: Box::new(argument),
+            loc: Location::default(),
+        })
+    }
+
+    #[test]
+    fn negate_wraps_like_the_binary_operators() {
+        let datamodel = Datamodel::new(&[]);
+        assert_eq!(
+            datamodel.eval(&negate(integer(5))).unwrap(),
+            Value::Integer(-5)
+        );
+        assert_eq!(
+            datamodel.eval(&negate(integer(i64::MIN))).unwrap(),
+            Value::Integer(i64::MIN)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use ast::datamodel::{Expression, VariableId};
+use ast::location::Location;
+use ast::statechart::Condition;
+pub use ast::statechart::{ConditonId, EventId, ExecutableId};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+// serialized tagged by `type`. Charts written before executables were structured list bare
+// script ids, which still deserialize as `Script`. `C` is the condition of `if` and `elseif`,
+// which names states by id in a statechart and by index in a Core.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", remote = "Self")]
+pub enum Executable<C = Condition> {
+    Script(Script),
+    Raise(Raise),
+    Send(Send),
+    Assign(Assign),
+    Log(Log),
+    If(If<C>),
+    Foreach(Foreach<C>),
+    Cancel(Cancel),
+}
+
+impl<C: Serialize> Serialize for Executable<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Executable::serialize(self, serializer)
+    }
+}
+
+impl<'de, C: Deserialize<'de>> Deserialize<'de> for Executable<C> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Executable<C>, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if let Some(id) = value.as_u64() {
+            return Ok(Executable::Script(Script {
+                id: id as ExecutableId,
+                loc: Location::default(),
+            }));
+        }
+        Executable::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+impl<C> Executable<C> {
+    pub fn loc(&self) -> Location {
+        match self {
+            Executable::Script(node) => node.loc,
+            Executable::Raise(node) => node.loc,
+            Executable::Send(node) => node.loc,
+            Executable::Assign(node) => node.loc,
+            Executable::Log(node) => node.loc,
+            Executable::If(node) => node.loc,
+            Executable::Foreach(node) => node.loc,
+            Executable::Cancel(node) => node.loc,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    pub id: ExecutableId,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Raise {
+    pub event: EventId,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Send {
+    pub event: EventId,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(default)]
+    pub delay: usize,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Assign {
+    pub location: VariableId,
+
+    pub expr: Expression,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Log {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct If<C = Condition> {
+    pub condition: C,
+
+    #[serde(default = "Vec::new")]
+    pub children: Vec<Executable<C>>,
+
+    #[serde(default = "Vec::new")]
+    pub else_if: Vec<ElseIf<C>>,
+
+    #[serde(default = "Vec::new", rename = "else")]
+    pub otherwise: Vec<Executable<C>>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ElseIf<C = Condition> {
+    pub condition: C,
+
+    #[serde(default = "Vec::new")]
+    pub children: Vec<Executable<C>>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Foreach<C = Condition> {
+    // the host value iterated over, see `Host::foreach`
+    pub array: usize,
+
+    pub item: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+
+    #[serde(default = "Vec::new")]
+    pub children: Vec<Executable<C>>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cancel {
+    pub id: String,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn bare_ids_are_scripts() {
+        let executables: Vec<Executable> =
+            serde_json::from_str(r#"[3, {"type": "raise", "event": 1}]"#).unwrap();
+        match executables[0] {
+            Executable::Script(Script { id: 3, .. }) => {}
+            ref other => panic!("expected script 3, got {:?}", other),
+        }
+        match executables[1] {
+            Executable::Raise(Raise { event: 1, .. }) => {}
+            ref other => panic!("expected raise 1, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tagged_round_trip() {
+        let executable: Executable = Executable::Script(Script {
+            id: 7,
+            loc: Location::default(),
+        });
+        let json = serde_json::to_string(&executable).unwrap();
+        assert!(json.contains(r#""type":"script""#), "{}", json);
+        match serde_json::from_str::<Executable>(&json).unwrap() {
+            Executable::Script(Script { id: 7, .. }) => {}
+            other => panic!("expected script 7, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn malformed_executables_are_errors() {
+        let err = serde_json::from_str::<Executable>(r#"{"type": "raise"}"#).unwrap_err();
+        assert!(err.to_string().contains("event"), "{}", err);
+        assert!(serde_json::from_str::<Executable>("-1").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use ast::location::{Location, Point};
+use serde_json::{self, Value};
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+// source locations of parsed JSON values, keyed by their address in the parsed value
+#[derive(Clone, Debug, Default)]
+pub struct Locations(HashMap<usize, Location>);
+
+impl Locations {
+    // `source` must be the text `value` was parsed from
+    pub fn new(source: &str, value: &Value) -> Locations {
+        let mut scanner = Scanner {
+            chars: source.chars().peekable(),
+            point: Point::default(),
+            locations: Locations::default(),
+        };
+        scanner.whitespace();
+        let start = scanner.point;
+        scanner.value(Some(value), start);
+        scanner.locations
+    }
+
+    // the location of `node`, which must borrow from the parsed value
+    pub fn get<T>(&self, node: &T) -> Location {
+        self.0.get(&address(node)).cloned().unwrap_or_default()
+    }
+}
+
+fn address<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+// walks the source serde_json already accepted, matching each value to its position
+struct Scanner<'a> {
+    chars: Peekable<Chars<'a>>,
+    point: Point,
+    locations: Locations,
+}
+
+impl<'a> Scanner<'a> {
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.point.line += 1;
+            self.point.column = 0;
+        } else {
+            self.point.column += 1;
+        }
+        Some(c)
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.chars.peek(), Some(c) if c.is_whitespace()) {
+            self.bump();
+        }
+    }
+
+    fn string(&mut self) -> String {
+        let mut raw: String = self.bump().into_iter().collect();
+        while let Some(c) = self.bump() {
+            raw.push(c);
+            match c {
+                '\\' => raw.extend(self.bump()),
+                '"' => break,
+                _ => (),
+            }
+        }
+        serde_json::from_str(&raw).unwrap_or_default()
+    }
+
+    // object members are located from their key
+    fn value(&mut self, value: Option<&Value>, start: Point) {
+        match self.chars.peek() {
+            Some('{') => {
+                self.bump();
+                loop {
+                    self.whitespace();
+                    if self.chars.peek() != Some(&'"') {
+                        self.bump();
+                        break;
+                    }
+                    let key_start = self.point;
+                    let key = self.string();
+                    self.whitespace();
+                    self.bump();
+                    self.whitespace();
+                    let member = value.and_then(|value| value.get(key.as_str()));
+                    self.value(member, key_start);
+                    self.whitespace();
+                    if self.chars.peek() == Some(&',') {
+                        self.bump();
+                    }
+                }
+            }
+            Some('[') => {
+                self.bump();
+                for idx in 0.. {
+                    self.whitespace();
+                    if matches!(self.chars.peek(), Some(']') | None) {
+                        self.bump();
+                        break;
+                    }
+                    let item_start = self.point;
+                    self.value(value.and_then(|value| value.get(idx)), item_start);
+                    self.whitespace();
+                    if self.chars.peek() == Some(&',') {
+                        self.bump();
+                    }
+                }
+            }
+            Some('"') => {
+                self.string();
+            }
+            _ => {
+                while matches!(self.chars.peek(), Some(c) if !c.is_whitespace() && !",]}".contains(*c))
+                {
+                    self.bump();
+                }
+            }
+        }
+
+        if let Some(value) = value {
+            let loc = Location {
+                start,
+                end: self.point,
+                source: None,
+            };
+            self.locations.0.insert(address(value), loc);
+            if let Some(config) = value.as_object() {
+                self.locations.0.insert(address(config), loc);
+            }
+        }
+    }
+}
@@ -1,11 +1,23 @@
+use ast::datamodel::{self, Variable};
 use ast::location::Location;
+use serde_json;
 type ExecutableId = usize;
 type InvocationId = usize;
+type EventId = usize;
+type ValueId = usize;
+
+#[path = "microstep/to_formula.rs"]
+pub mod to_formula;
+
+#[path = "microstep/visit.rs"]
+pub mod visit;
 
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct Microstep {
     pub configuration_size: usize,
+    #[serde(default)]
+    pub datamodel: Vec<Variable>,
     pub init: Function,
     pub next: Function,
     pub render: Function,
@@ -13,6 +25,13 @@ pub struct Microstep {
     pub loc: Location,
 }
 
+impl Microstep {
+    // loads a compiled microstep, such as the one embedded in generated Rust code
+    pub fn from_json(source: &str) -> Result<Microstep, serde_json::Error> {
+        serde_json::from_str(source)
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(tag = "type")]
 pub enum Statement {
@@ -22,6 +41,13 @@ pub enum Statement {
     InvocationsDestructureDeclaration(InvocationsDestructureDeclaration),
     ReturnStatement(ReturnStatement),
     ExecuteStatement(ExecuteStatement),
+    RaiseStatement(RaiseStatement),
+    SendStatement(SendStatement),
+    DataAssignmentStatement(DataAssignmentStatement),
+    LogStatement(LogStatement),
+    IfStatement(IfStatement),
+    ForeachStatement(ForeachStatement),
+    CancelStatement(CancelStatement),
     InvocationOpenStatement(InvocationOpenStatement),
     InvocationCloseStatement(InvocationCloseStatement),
 }
@@ -39,6 +65,7 @@ pub enum Expression {
     InvocationsCreateExpression(InvocationsCreateExpression),
     ConditionExpression(ConditionExpression),
     EventExpression(EventExpression),
+    DatamodelExpression(DatamodelExpression),
     MicrostepResult(MicrostepResult),
     RenderExpression, // TODO
 }
@@ -87,6 +114,27 @@ pub enum SimpleExpression {
     InvocationsCreateExpression(InvocationsCreateExpression),
 }
 
+impl SimpleExpression {
+    pub fn to_expression(&self) -> Expression {
+        match self {
+            SimpleExpression::Identifier(v) => Expression::Identifier(v.clone()),
+            SimpleExpression::NullLiteral => Expression::NullLiteral,
+            SimpleExpression::StringLiteral(v) => Expression::StringLiteral(v.clone()),
+            SimpleExpression::BooleanLiteral(v) => Expression::BooleanLiteral(*v),
+            SimpleExpression::IntegerLiteral(v) => Expression::IntegerLiteral(*v),
+            SimpleExpression::LogicalExpression(v) => Expression::LogicalExpression(v.clone()),
+            SimpleExpression::ConditionExpression(v) => Expression::ConditionExpression(v.clone()),
+            SimpleExpression::EventExpression(v) => Expression::EventExpression(v.clone()),
+            SimpleExpression::ConfigurationCreateExpression(v) => {
+                Expression::ConfigurationCreateExpression(v.clone())
+            }
+            SimpleExpression::InvocationsCreateExpression(v) => {
+                Expression::InvocationsCreateExpression(v.clone())
+            }
+        }
+    }
+}
+
 impl Default for SimpleExpression {
     fn default() -> SimpleExpression {
         SimpleExpression::NullLiteral
@@ -165,6 +213,14 @@ pub struct EventExpression {
     pub loc: Location,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DatamodelExpression {
+    pub expr: datamodel::Expression,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct MicrostepResult {
     #[serde(default)]
@@ -176,6 +232,10 @@ pub struct MicrostepResult {
     #[serde(default)]
     pub history: SimpleExpression,
 
+    // the transitions taken by the microstep, indexed like the core transitions
+    #[serde(default)]
+    pub transitions: SimpleExpression,
+
     // #[serde(default)]
     // pub invocations: SimpleExpression,
     #[serde(default)]
@@ -294,6 +354,113 @@ pub struct ExecuteStatement {
     pub loc: Location,
 }
 
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct RaiseStatement {
+    pub event: EventId,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct SendStatement {
+    pub event: EventId,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    #[serde(default)]
+    pub delay: usize,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DataAssignmentStatement {
+    pub location: String,
+
+    pub expr: datamodel::Expression,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct LogStatement {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expr: Option<datamodel::Expression>,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct IfStatement {
+    #[serde(default)]
+    pub test: Expression,
+
+    #[serde(default)]
+    pub consequent: Vec<Statement>,
+
+    #[serde(default)]
+    pub alternate: Vec<Statement>,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ForeachStatement {
+    pub array: ValueId,
+
+    pub item: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+
+    #[serde(default)]
+    pub body: Vec<Statement>,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CancelStatement {
+    pub id: String,
+
+    #[serde(default)]
+    pub guard: Option<Expression>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct InvocationOpenStatement {
     pub id: InvocationId,
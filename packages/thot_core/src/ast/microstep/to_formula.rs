@@ -0,0 +1,481 @@
+use ast::conversion_error::ConversionError;
+use ast::location::Location;
+use ast::microstep::*;
+use std::collections::{BTreeSet, HashMap};
+
+const SOURCE: &str = "ast/microstep/to_formula";
+
+// a boolean formula over the state variables, the definitions introduced while
+// translating and the free inputs of a step
+#[derive(Clone, Debug, PartialEq)]
+pub enum Formula {
+    Const(bool),
+    Var(String),
+    Condition(usize),
+    Data(usize),
+    Event(usize),
+    HasEvent,
+    Not(Box<Formula>),
+    And(Vec<Formula>),
+    Or(Vec<Formula>),
+    Xor(Vec<Formula>),
+    Ite(Box<Formula>, Box<Formula>, Box<Formula>),
+}
+
+impl Formula {
+    pub fn var(name: &str) -> Formula {
+        Formula::Var(name.to_string())
+    }
+
+    pub fn and(arguments: Vec<Formula>) -> Formula {
+        Formula::fold(arguments, true, Formula::And)
+    }
+
+    pub fn or(arguments: Vec<Formula>) -> Formula {
+        Formula::fold(arguments, false, Formula::Or)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(argument: Formula) -> Formula {
+        match argument {
+            Formula::Const(value) => Formula::Const(!value),
+            Formula::Not(argument) => *argument,
+            argument => Formula::Not(Box::new(argument)),
+        }
+    }
+
+    pub fn ite(test: Formula, consequent: Formula, alternate: Formula) -> Formula {
+        match test {
+            Formula::Const(true) => consequent,
+            Formula::Const(false) => alternate,
+            _ if consequent == alternate => consequent,
+            test => Formula::Ite(Box::new(test), Box::new(consequent), Box::new(alternate)),
+        }
+    }
+
+    pub fn is_atom(&self) -> bool {
+        match self {
+            Formula::Not(argument) => argument.is_atom(),
+            Formula::And(_) | Formula::Or(_) | Formula::Xor(_) | Formula::Ite(..) => false,
+            _ => true,
+        }
+    }
+
+    // drops the identity and short-circuits on the absorbing constant
+    fn fold(arguments: Vec<Formula>, identity: bool, f: fn(Vec<Formula>) -> Formula) -> Formula {
+        let mut folded = vec![];
+        for argument in arguments {
+            match argument {
+                Formula::Const(value) if value == identity => {}
+                Formula::Const(value) => return Formula::Const(value),
+                argument => folded.push(argument),
+            }
+        }
+        match folded.len() {
+            0 => Formula::Const(identity),
+            1 => folded.remove(0),
+            _ => f(folded),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Sym {
+    Null,
+    Bool(Formula),
+    Configuration(Vec<Formula>),
+    Result {
+        configuration: Vec<Formula>,
+        initialized: Vec<Formula>,
+        history: Vec<Formula>,
+        is_stable: Formula,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Inputs {
+    pub events: BTreeSet<usize>,
+    pub conditions: BTreeSet<usize>,
+    pub data: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Translation {
+    pub defines: Vec<(String, Formula)>,
+    // guarded results in the order they are returned
+    pub results: Vec<(Formula, Vec<Formula>)>,
+}
+
+impl Translation {
+    // the value of every state variable after the step, see `variables`
+    pub fn values(&self, size: usize) -> Vec<Formula> {
+        variables(size)
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| {
+                self.results
+                    .iter()
+                    .rev()
+                    .fold(Formula::var(name), |value, (condition, values)| {
+                        Formula::ite(condition.clone(), values[idx].clone(), value)
+                    })
+            })
+            .collect()
+    }
+}
+
+// the configuration, initialized and history bits followed by the stable flag
+pub fn variables(size: usize) -> Vec<String> {
+    let mut variables = vec![];
+    for prefix in &["c", "i", "h"] {
+        variables.extend((0..size).map(|idx| format!("{}{}", prefix, idx)));
+    }
+    variables.push("stable".to_string());
+    variables
+}
+
+pub fn translate_init(
+    microstep: &Microstep,
+    inputs: &mut Inputs,
+) -> Result<Translation, ConversionError> {
+    let size = microstep.configuration_size;
+    Translator::new("init", true, size, inputs).function(&microstep.init, vec![])
+}
+
+pub fn translate_next(
+    microstep: &Microstep,
+    inputs: &mut Inputs,
+) -> Result<Translation, ConversionError> {
+    let size = microstep.configuration_size;
+    let params = ["c", "i", "h"]
+        .iter()
+        .map(|prefix| {
+            Sym::Configuration(
+                (0..size)
+                    .map(|idx| Formula::Var(format!("{}{}", prefix, idx)))
+                    .collect(),
+            )
+        })
+        .chain(Some(Sym::Bool(Formula::HasEvent)))
+        .collect();
+    Translator::new("step", false, size, inputs).function(&microstep.next, params)
+}
+
+struct Translator<'a> {
+    prefix: &'a str,
+    is_init: bool,
+    inputs: &'a mut Inputs,
+    size: usize,
+    translation: Translation,
+    counts: HashMap<String, usize>,
+}
+
+impl<'a> Translator<'a> {
+    fn new(prefix: &'a str, is_init: bool, size: usize, inputs: &'a mut Inputs) -> Translator<'a> {
+        Translator {
+            prefix,
+            is_init,
+            inputs,
+            size,
+            translation: Translation::default(),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn function(
+        mut self,
+        function: &Function,
+        args: Vec<Sym>,
+    ) -> Result<Translation, ConversionError> {
+        let mut env = HashMap::new();
+        for (param, arg) in function.params.iter().zip(args) {
+            match param {
+                Expression::Identifier(id) => {
+                    env.insert(id.name.clone(), arg);
+                }
+                _ => return Err(error("Invalid parameter", function.loc)),
+            }
+        }
+        self.block(&function.body, &mut env, &Formula::Const(true))?;
+        Ok(self.translation)
+    }
+
+    // returns true once the block has returned unconditionally
+    fn block(
+        &mut self,
+        statements: &[Statement],
+        env: &mut HashMap<String, Sym>,
+        pc: &Formula,
+    ) -> Result<bool, ConversionError> {
+        for statement in statements {
+            if self.statement(statement, env, pc)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn statement(
+        &mut self,
+        statement: &Statement,
+        env: &mut HashMap<String, Sym>,
+        pc: &Formula,
+    ) -> Result<bool, ConversionError> {
+        match statement {
+            Statement::VariableDeclaration(node) => {
+                let VariableDeclarationId::Identifier(ref id) = node.id;
+                let value = self.eval(&node.init, env)?;
+                self.bind(&id.name, value, env);
+            }
+            Statement::AssignmentStatement(node) => {
+                let AssignmentStatementLeft::Identifier(ref id) = node.left;
+                let value = self.eval(&node.right, env)?;
+                self.bind(&id.name, value, env);
+            }
+            Statement::ConfigurationDestructureDeclaration(
+                ConfigurationDestructureDeclaration { left, right, loc },
+            )
+            | Statement::InvocationsDestructureDeclaration(InvocationsDestructureDeclaration {
+                left,
+                right,
+                loc,
+            }) => {
+                let values = match self.eval(right, env)? {
+                    Sym::Configuration(values) => values,
+                    _ => return Err(error("Expected a configuration", *loc)),
+                };
+                for (i, id) in left.iter().enumerate() {
+                    match id {
+                        Expression::Identifier(id) => {
+                            let value = values.get(i).cloned().unwrap_or(Formula::Const(false));
+                            self.bind(&id.name, Sym::Bool(value), env);
+                        }
+                        _ => return Err(error("Invalid destructure target", *loc)),
+                    }
+                }
+            }
+            Statement::ReturnStatement(node) => {
+                let condition = Formula::and(vec![pc.clone(), self.guard(&node.guard, env)?]);
+                let values = match self.eval(&node.argument, env)? {
+                    Sym::Result {
+                        configuration,
+                        initialized,
+                        history,
+                        is_stable,
+                    } => {
+                        let mut values = vec![];
+                        for mut list in [configuration, initialized, history] {
+                            list.resize(self.size, Formula::Const(false));
+                            values.extend(list);
+                        }
+                        values.push(is_stable);
+                        values
+                    }
+                    _ => return Err(error("Expected a microstep result", node.loc)),
+                };
+                let is_done = condition == Formula::Const(true);
+                self.translation.results.push((condition, values));
+                return Ok(is_done);
+            }
+            Statement::IfStatement(node) => {
+                let guard = Formula::and(vec![pc.clone(), self.guard(&node.guard, env)?]);
+                let test = self.eval_bool(&node.test, env)?;
+                let consequent_pc = Formula::and(vec![guard.clone(), test.clone()]);
+                let alternate_pc = Formula::and(vec![guard, Formula::not(test.clone())]);
+
+                let mut consequent = env.clone();
+                self.block(&node.consequent, &mut consequent, &consequent_pc)?;
+                let mut alternate = env.clone();
+                self.block(&node.alternate, &mut alternate, &alternate_pc)?;
+                self.merge(env, &consequent_pc, consequent, &alternate_pc, alternate);
+            }
+            Statement::ForeachStatement(node) => {
+                let guard = Formula::and(vec![pc.clone(), self.guard(&node.guard, env)?]);
+                let skipped = env.clone();
+                let mut body = env.clone();
+                self.block(&node.body, &mut body, &guard)?;
+                self.merge(env, &guard, body, &Formula::not(guard.clone()), skipped);
+            }
+            // side effects on the host and the datamodel are abstracted away
+            Statement::ExecuteStatement(_)
+            | Statement::RaiseStatement(_)
+            | Statement::SendStatement(_)
+            | Statement::DataAssignmentStatement(_)
+            | Statement::LogStatement(_)
+            | Statement::CancelStatement(_)
+            | Statement::InvocationOpenStatement(_)
+            | Statement::InvocationCloseStatement(_) => {}
+        }
+        Ok(false)
+    }
+
+    fn merge(
+        &mut self,
+        env: &mut HashMap<String, Sym>,
+        first_pc: &Formula,
+        first: HashMap<String, Sym>,
+        second_pc: &Formula,
+        mut second: HashMap<String, Sym>,
+    ) {
+        let mut keys: Vec<_> = first.keys().chain(second.keys()).cloned().collect();
+        keys.sort();
+        keys.dedup();
+        for key in keys {
+            let a = first.get(&key).cloned().unwrap_or(Sym::Null);
+            let b = second.remove(&key).unwrap_or(Sym::Null);
+            if a == b {
+                env.insert(key, a);
+                continue;
+            }
+            let old = match env.get(&key) {
+                Some(Sym::Bool(old)) => old.clone(),
+                _ => Formula::Const(false),
+            };
+            let value = match (a, b) {
+                (Sym::Bool(a), Sym::Bool(b)) => {
+                    Formula::ite(first_pc.clone(), a, Formula::ite(second_pc.clone(), b, old))
+                }
+                (Sym::Bool(a), Sym::Null) => Formula::ite(first_pc.clone(), a, old),
+                (Sym::Null, Sym::Bool(b)) => Formula::ite(second_pc.clone(), b, old),
+                (a, _) => {
+                    env.insert(key, a);
+                    continue;
+                }
+            };
+            self.bind(&key, Sym::Bool(value), env);
+        }
+    }
+
+    // introduces a definition for every compound value so formulas stay linear in size
+    fn bind(&mut self, name: &str, value: Sym, env: &mut HashMap<String, Sym>) {
+        let value = match value {
+            Sym::Bool(formula) if !formula.is_atom() => {
+                let count = self.counts.entry(name.to_string()).or_insert(0);
+                let define = match *count {
+                    0 => format!("{}_{}", self.prefix, name),
+                    count => format!("{}_{}_{}", self.prefix, name, count),
+                };
+                *count += 1;
+                self.translation.defines.push((define.clone(), formula));
+                Sym::Bool(Formula::Var(define))
+            }
+            value => value,
+        };
+        env.insert(name.to_string(), value);
+    }
+
+    fn guard(
+        &mut self,
+        guard: &Option<Expression>,
+        env: &HashMap<String, Sym>,
+    ) -> Result<Formula, ConversionError> {
+        match guard {
+            Some(guard) => self.eval_bool(guard, env),
+            None => Ok(Formula::Const(true)),
+        }
+    }
+
+    fn eval_bool(
+        &mut self,
+        expr: &Expression,
+        env: &HashMap<String, Sym>,
+    ) -> Result<Formula, ConversionError> {
+        match self.eval(expr, env)? {
+            Sym::Bool(value) => Ok(value),
+            _ => Err(error("Expected a boolean", Location::default())),
+        }
+    }
+
+    fn eval_list(
+        &mut self,
+        expr: &SimpleExpression,
+        env: &HashMap<String, Sym>,
+    ) -> Result<Vec<Formula>, ConversionError> {
+        match self.eval(&expr.to_expression(), env)? {
+            Sym::Configuration(values) => Ok(values),
+            _ => Err(error("Expected a configuration", Location::default())),
+        }
+    }
+
+    fn eval_arguments(
+        &mut self,
+        arguments: &[Expression],
+        env: &HashMap<String, Sym>,
+    ) -> Result<Vec<Formula>, ConversionError> {
+        arguments
+            .iter()
+            .map(|argument| self.eval_bool(argument, env))
+            .collect()
+    }
+
+    fn eval(
+        &mut self,
+        expr: &Expression,
+        env: &HashMap<String, Sym>,
+    ) -> Result<Sym, ConversionError> {
+        Ok(match expr {
+            Expression::Identifier(id) => match env.get(&id.name) {
+                Some(value) => value.clone(),
+                None => return Err(error(&format!("Unknown identifier: {:?}", id.name), id.loc)),
+            },
+            Expression::NullLiteral | Expression::RenderExpression => Sym::Null,
+            Expression::StringLiteral(node) => {
+                return Err(error("Unsupported string literal", node.loc))
+            }
+            Expression::IntegerLiteral(node) => {
+                return Err(error("Unsupported integer literal", node.loc))
+            }
+            Expression::BooleanLiteral(node) => Sym::Bool(Formula::Const(node.value)),
+            Expression::LogicalExpression(node) => {
+                let arguments = self.eval_arguments(&node.arguments, env)?;
+                Sym::Bool(match node.operator {
+                    LogicalOperator::And => Formula::and(arguments),
+                    LogicalOperator::Or => Formula::or(arguments),
+                    LogicalOperator::Xor if arguments.is_empty() => Formula::Const(false),
+                    LogicalOperator::Xor => Formula::Xor(arguments),
+                    LogicalOperator::Not => match arguments.into_iter().next() {
+                        Some(argument) => Formula::not(argument),
+                        None => return Err(error("Missing operand for Not", node.loc)),
+                    },
+                })
+            }
+            Expression::ConfigurationCreateExpression(node) => {
+                Sym::Configuration(self.eval_arguments(&node.arguments, env)?)
+            }
+            Expression::InvocationsCreateExpression(node) => {
+                Sym::Configuration(self.eval_arguments(&node.arguments, env)?)
+            }
+            Expression::ConditionExpression(node) => {
+                self.inputs.conditions.insert(node.id);
+                Sym::Bool(Formula::Condition(node.id))
+            }
+            Expression::EventExpression(node) => {
+                if self.is_init {
+                    Sym::Bool(Formula::Const(false))
+                } else {
+                    self.inputs.events.insert(node.id);
+                    Sym::Bool(Formula::Event(node.id))
+                }
+            }
+            Expression::DatamodelExpression(_) => {
+                self.inputs.data += 1;
+                Sym::Bool(Formula::Data(self.inputs.data - 1))
+            }
+            Expression::MicrostepResult(node) => Sym::Result {
+                configuration: self.eval_list(&node.configuration, env)?,
+                initialized: self.eval_list(&node.initialized, env)?,
+                history: self.eval_list(&node.history, env)?,
+                is_stable: self.eval_bool(&node.is_stable.to_expression(), env)?,
+            },
+        })
+    }
+}
+
+fn error(message: &str, loc: Location) -> ConversionError {
+    ConversionError {
+        message: message.to_string(),
+        fatal: true,
+        source: SOURCE.to_string(),
+        loc,
+    }
+}
@@ -0,0 +1,600 @@
+use ast::datamodel::{self, BinaryExpression, UnaryExpression, Variable};
+use ast::microstep::*;
+
+// Each method of `Visit`, `VisitMut` and `Fold` defaults to the function of the same name
+// in this module, which recurses into the children of the node. Overriding a method and
+// calling that function from it keeps the recursion going. Every guard is visited after
+// the rest of its statement.
+
+pub trait Visit<'ast> {
+    fn visit_microstep(&mut self, node: &'ast Microstep) {
+        visit_microstep(self, node)
+    }
+    fn visit_function(&mut self, node: &'ast Function) {
+        visit_function(self, node)
+    }
+    fn visit_statement(&mut self, node: &'ast Statement) {
+        visit_statement(self, node)
+    }
+    fn visit_expression(&mut self, node: &'ast Expression) {
+        visit_expression(self, node)
+    }
+    fn visit_simple_expression(&mut self, node: &'ast SimpleExpression) {
+        visit_simple_expression(self, node)
+    }
+    fn visit_variable(&mut self, node: &'ast Variable) {
+        visit_variable(self, node)
+    }
+    fn visit_datamodel_expression(&mut self, node: &'ast datamodel::Expression) {
+        visit_datamodel_expression(self, node)
+    }
+}
+
+pub fn visit_microstep<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Microstep) {
+    for variable in &node.datamodel {
+        v.visit_variable(variable);
+    }
+    v.visit_function(&node.init);
+    v.visit_function(&node.next);
+    v.visit_function(&node.render);
+}
+
+pub fn visit_function<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Function) {
+    for param in &node.params {
+        v.visit_expression(param);
+    }
+    for statement in &node.body {
+        v.visit_statement(statement);
+    }
+}
+
+pub fn visit_statement<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Statement) {
+    let guard = match node {
+        Statement::VariableDeclaration(node) => {
+            v.visit_expression(&node.init);
+            &None
+        }
+        Statement::AssignmentStatement(node) => {
+            v.visit_expression(&node.right);
+            &None
+        }
+        Statement::ConfigurationDestructureDeclaration(node) => {
+            for left in &node.left {
+                v.visit_expression(left);
+            }
+            v.visit_expression(&node.right);
+            &None
+        }
+        Statement::InvocationsDestructureDeclaration(node) => {
+            for left in &node.left {
+                v.visit_expression(left);
+            }
+            v.visit_expression(&node.right);
+            &None
+        }
+        Statement::ReturnStatement(node) => {
+            v.visit_expression(&node.argument);
+            &node.guard
+        }
+        Statement::ExecuteStatement(node) => &node.guard,
+        Statement::RaiseStatement(node) => &node.guard,
+        Statement::SendStatement(node) => &node.guard,
+        Statement::DataAssignmentStatement(node) => {
+            v.visit_datamodel_expression(&node.expr);
+            &node.guard
+        }
+        Statement::LogStatement(node) => {
+            if let Some(ref expr) = node.expr {
+                v.visit_datamodel_expression(expr);
+            }
+            &node.guard
+        }
+        Statement::IfStatement(node) => {
+            v.visit_expression(&node.test);
+            for statement in node.consequent.iter().chain(&node.alternate) {
+                v.visit_statement(statement);
+            }
+            &node.guard
+        }
+        Statement::ForeachStatement(node) => {
+            for statement in &node.body {
+                v.visit_statement(statement);
+            }
+            &node.guard
+        }
+        Statement::CancelStatement(node) => &node.guard,
+        Statement::InvocationOpenStatement(node) => &node.guard,
+        Statement::InvocationCloseStatement(node) => &node.guard,
+    };
+    if let Some(guard) = guard {
+        v.visit_expression(guard);
+    }
+}
+
+pub fn visit_expression<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Expression) {
+    match node {
+        Expression::LogicalExpression(node) => {
+            for argument in &node.arguments {
+                v.visit_expression(argument);
+            }
+        }
+        Expression::ConfigurationCreateExpression(node) => {
+            for argument in &node.arguments {
+                v.visit_expression(argument);
+            }
+        }
+        Expression::InvocationsCreateExpression(node) => {
+            for argument in &node.arguments {
+                v.visit_expression(argument);
+            }
+        }
+        Expression::DatamodelExpression(node) => v.visit_datamodel_expression(&node.expr),
+        Expression::MicrostepResult(node) => {
+            v.visit_simple_expression(&node.configuration);
+            v.visit_simple_expression(&node.initialized);
+            v.visit_simple_expression(&node.history);
+            v.visit_simple_expression(&node.transitions);
+            v.visit_simple_expression(&node.is_stable);
+        }
+        _ => {}
+    }
+}
+
+pub fn visit_simple_expression<'ast, V: Visit<'ast> + ?Sized>(
+    v: &mut V,
+    node: &'ast SimpleExpression,
+) {
+    match node {
+        SimpleExpression::LogicalExpression(node) => {
+            for argument in &node.arguments {
+                v.visit_expression(argument);
+            }
+        }
+        SimpleExpression::ConfigurationCreateExpression(node) => {
+            for argument in &node.arguments {
+                v.visit_expression(argument);
+            }
+        }
+        SimpleExpression::InvocationsCreateExpression(node) => {
+            for argument in &node.arguments {
+                v.visit_expression(argument);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn visit_variable<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Variable) {
+    if let Some(ref init) = node.init {
+        v.visit_datamodel_expression(init);
+    }
+}
+
+pub fn visit_datamodel_expression<'ast, V: Visit<'ast> + ?Sized>(
+    v: &mut V,
+    node: &'ast datamodel::Expression,
+) {
+    match node {
+        datamodel::Expression::Unary(node) => v.visit_datamodel_expression(&node.argument),
+        datamodel::Expression::Binary(node) => {
+            v.visit_datamodel_expression(&node.left);
+            v.visit_datamodel_expression(&node.right);
+        }
+        _ => {}
+    }
+}
+
+pub trait VisitMut {
+    fn visit_microstep_mut(&mut self, node: &mut Microstep) {
+        visit_microstep_mut(self, node)
+    }
+    fn visit_function_mut(&mut self, node: &mut Function) {
+        visit_function_mut(self, node)
+    }
+    fn visit_statement_mut(&mut self, node: &mut Statement) {
+        visit_statement_mut(self, node)
+    }
+    fn visit_expression_mut(&mut self, node: &mut Expression) {
+        visit_expression_mut(self, node)
+    }
+    fn visit_simple_expression_mut(&mut self, node: &mut SimpleExpression) {
+        visit_simple_expression_mut(self, node)
+    }
+    fn visit_variable_mut(&mut self, node: &mut Variable) {
+        visit_variable_mut(self, node)
+    }
+    fn visit_datamodel_expression_mut(&mut self, node: &mut datamodel::Expression) {
+        visit_datamodel_expression_mut(self, node)
+    }
+}
+
+pub fn visit_microstep_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Microstep) {
+    for variable in &mut node.datamodel {
+        v.visit_variable_mut(variable);
+    }
+    v.visit_function_mut(&mut node.init);
+    v.visit_function_mut(&mut node.next);
+    v.visit_function_mut(&mut node.render);
+}
+
+pub fn visit_function_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Function) {
+    for param in &mut node.params {
+        v.visit_expression_mut(param);
+    }
+    for statement in &mut node.body {
+        v.visit_statement_mut(statement);
+    }
+}
+
+pub fn visit_statement_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Statement) {
+    let guard = match node {
+        Statement::VariableDeclaration(node) => {
+            v.visit_expression_mut(&mut node.init);
+            None
+        }
+        Statement::AssignmentStatement(node) => {
+            v.visit_expression_mut(&mut node.right);
+            None
+        }
+        Statement::ConfigurationDestructureDeclaration(node) => {
+            for left in &mut node.left {
+                v.visit_expression_mut(left);
+            }
+            v.visit_expression_mut(&mut node.right);
+            None
+        }
+        Statement::InvocationsDestructureDeclaration(node) => {
+            for left in &mut node.left {
+                v.visit_expression_mut(left);
+            }
+            v.visit_expression_mut(&mut node.right);
+            None
+        }
+        Statement::ReturnStatement(node) => {
+            v.visit_expression_mut(&mut node.argument);
+            node.guard.as_mut()
+        }
+        Statement::ExecuteStatement(node) => node.guard.as_mut(),
+        Statement::RaiseStatement(node) => node.guard.as_mut(),
+        Statement::SendStatement(node) => node.guard.as_mut(),
+        Statement::DataAssignmentStatement(node) => {
+            v.visit_datamodel_expression_mut(&mut node.expr);
+            node.guard.as_mut()
+        }
+        Statement::LogStatement(node) => {
+            if let Some(ref mut expr) = node.expr {
+                v.visit_datamodel_expression_mut(expr);
+            }
+            node.guard.as_mut()
+        }
+        Statement::IfStatement(node) => {
+            v.visit_expression_mut(&mut node.test);
+            for statement in node.consequent.iter_mut().chain(&mut node.alternate) {
+                v.visit_statement_mut(statement);
+            }
+            node.guard.as_mut()
+        }
+        Statement::ForeachStatement(node) => {
+            for statement in &mut node.body {
+                v.visit_statement_mut(statement);
+            }
+            node.guard.as_mut()
+        }
+        Statement::CancelStatement(node) => node.guard.as_mut(),
+        Statement::InvocationOpenStatement(node) => node.guard.as_mut(),
+        Statement::InvocationCloseStatement(node) => node.guard.as_mut(),
+    };
+    if let Some(guard) = guard {
+        v.visit_expression_mut(guard);
+    }
+}
+
+pub fn visit_expression_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Expression) {
+    match node {
+        Expression::LogicalExpression(node) => {
+            for argument in &mut node.arguments {
+                v.visit_expression_mut(argument);
+            }
+        }
+        Expression::ConfigurationCreateExpression(node) => {
+            for argument in &mut node.arguments {
+                v.visit_expression_mut(argument);
+            }
+        }
+        Expression::InvocationsCreateExpression(node) => {
+            for argument in &mut node.arguments {
+                v.visit_expression_mut(argument);
+            }
+        }
+        Expression::DatamodelExpression(node) => v.visit_datamodel_expression_mut(&mut node.expr),
+        Expression::MicrostepResult(node) => {
+            v.visit_simple_expression_mut(&mut node.configuration);
+            v.visit_simple_expression_mut(&mut node.initialized);
+            v.visit_simple_expression_mut(&mut node.history);
+            v.visit_simple_expression_mut(&mut node.transitions);
+            v.visit_simple_expression_mut(&mut node.is_stable);
+        }
+        _ => {}
+    }
+}
+
+pub fn visit_simple_expression_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut SimpleExpression) {
+    match node {
+        SimpleExpression::LogicalExpression(node) => {
+            for argument in &mut node.arguments {
+                v.visit_expression_mut(argument);
+            }
+        }
+        SimpleExpression::ConfigurationCreateExpression(node) => {
+            for argument in &mut node.arguments {
+                v.visit_expression_mut(argument);
+            }
+        }
+        SimpleExpression::InvocationsCreateExpression(node) => {
+            for argument in &mut node.arguments {
+                v.visit_expression_mut(argument);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn visit_variable_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Variable) {
+    if let Some(ref mut init) = node.init {
+        v.visit_datamodel_expression_mut(init);
+    }
+}
+
+pub fn visit_datamodel_expression_mut<V: VisitMut + ?Sized>(
+    v: &mut V,
+    node: &mut datamodel::Expression,
+) {
+    match node {
+        datamodel::Expression::Unary(node) => v.visit_datamodel_expression_mut(&mut node.argument),
+        datamodel::Expression::Binary(node) => {
+            v.visit_datamodel_expression_mut(&mut node.left);
+            v.visit_datamodel_expression_mut(&mut node.right);
+        }
+        _ => {}
+    }
+}
+
+pub trait Fold {
+    fn fold_microstep(&mut self, node: Microstep) -> Microstep {
+        fold_microstep(self, node)
+    }
+    fn fold_function(&mut self, node: Function) -> Function {
+        fold_function(self, node)
+    }
+    fn fold_statement(&mut self, node: Statement) -> Statement {
+        fold_statement(self, node)
+    }
+    fn fold_expression(&mut self, node: Expression) -> Expression {
+        fold_expression(self, node)
+    }
+    fn fold_simple_expression(&mut self, node: SimpleExpression) -> SimpleExpression {
+        fold_simple_expression(self, node)
+    }
+    fn fold_variable(&mut self, node: Variable) -> Variable {
+        fold_variable(self, node)
+    }
+    fn fold_datamodel_expression(&mut self, node: datamodel::Expression) -> datamodel::Expression {
+        fold_datamodel_expression(self, node)
+    }
+}
+
+fn fold_statements<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Statement>) -> Vec<Statement> {
+    nodes
+        .into_iter()
+        .map(|node| f.fold_statement(node))
+        .collect()
+}
+
+fn fold_expressions<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Expression>) -> Vec<Expression> {
+    nodes
+        .into_iter()
+        .map(|node| f.fold_expression(node))
+        .collect()
+}
+
+fn fold_guard<F: Fold + ?Sized>(f: &mut F, guard: Option<Expression>) -> Option<Expression> {
+    guard.map(|guard| f.fold_expression(guard))
+}
+
+pub fn fold_microstep<F: Fold + ?Sized>(f: &mut F, node: Microstep) -> Microstep {
+    Microstep {
+        datamodel: node
+            .datamodel
+            .into_iter()
+            .map(|variable| f.fold_variable(variable))
+            .collect(),
+        init: f.fold_function(node.init),
+        next: f.fold_function(node.next),
+        render: f.fold_function(node.render),
+        ..node
+    }
+}
+
+pub fn fold_function<F: Fold + ?Sized>(f: &mut F, node: Function) -> Function {
+    Function {
+        params: fold_expressions(f, node.params),
+        body: fold_statements(f, node.body),
+        ..node
+    }
+}
+
+pub fn fold_statement<F: Fold + ?Sized>(f: &mut F, node: Statement) -> Statement {
+    match node {
+        Statement::VariableDeclaration(node) => {
+            Statement::VariableDeclaration(VariableDeclaration {
+                init: f.fold_expression(node.init),
+                ..node
+            })
+        }
+        Statement::AssignmentStatement(node) => {
+            Statement::AssignmentStatement(AssignmentStatement {
+                right: f.fold_expression(node.right),
+                ..node
+            })
+        }
+        Statement::ConfigurationDestructureDeclaration(node) => {
+            Statement::ConfigurationDestructureDeclaration(ConfigurationDestructureDeclaration {
+                left: fold_expressions(f, node.left),
+                right: f.fold_expression(node.right),
+                ..node
+            })
+        }
+        Statement::InvocationsDestructureDeclaration(node) => {
+            Statement::InvocationsDestructureDeclaration(InvocationsDestructureDeclaration {
+                left: fold_expressions(f, node.left),
+                right: f.fold_expression(node.right),
+                ..node
+            })
+        }
+        Statement::ReturnStatement(node) => Statement::ReturnStatement(ReturnStatement {
+            argument: f.fold_expression(node.argument),
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::ExecuteStatement(node) => Statement::ExecuteStatement(ExecuteStatement {
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::RaiseStatement(node) => Statement::RaiseStatement(RaiseStatement {
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::SendStatement(node) => Statement::SendStatement(SendStatement {
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::DataAssignmentStatement(node) => {
+            Statement::DataAssignmentStatement(DataAssignmentStatement {
+                expr: f.fold_datamodel_expression(node.expr),
+                guard: fold_guard(f, node.guard),
+                ..node
+            })
+        }
+        Statement::LogStatement(node) => Statement::LogStatement(LogStatement {
+            expr: node.expr.map(|expr| f.fold_datamodel_expression(expr)),
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::IfStatement(node) => Statement::IfStatement(IfStatement {
+            test: f.fold_expression(node.test),
+            consequent: fold_statements(f, node.consequent),
+            alternate: fold_statements(f, node.alternate),
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::ForeachStatement(node) => Statement::ForeachStatement(ForeachStatement {
+            body: fold_statements(f, node.body),
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::CancelStatement(node) => Statement::CancelStatement(CancelStatement {
+            guard: fold_guard(f, node.guard),
+            ..node
+        }),
+        Statement::InvocationOpenStatement(node) => {
+            Statement::InvocationOpenStatement(InvocationOpenStatement {
+                guard: fold_guard(f, node.guard),
+                ..node
+            })
+        }
+        Statement::InvocationCloseStatement(node) => {
+            Statement::InvocationCloseStatement(InvocationCloseStatement {
+                guard: fold_guard(f, node.guard),
+                ..node
+            })
+        }
+    }
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(f: &mut F, node: Expression) -> Expression {
+    match node {
+        Expression::LogicalExpression(node) => Expression::LogicalExpression(LogicalExpression {
+            arguments: fold_expressions(f, node.arguments),
+            ..node
+        }),
+        Expression::ConfigurationCreateExpression(node) => {
+            Expression::ConfigurationCreateExpression(ConfigurationCreateExpression {
+                arguments: fold_expressions(f, node.arguments),
+                ..node
+            })
+        }
+        Expression::InvocationsCreateExpression(node) => {
+            Expression::InvocationsCreateExpression(InvocationsCreateExpression {
+                arguments: fold_expressions(f, node.arguments),
+                ..node
+            })
+        }
+        Expression::DatamodelExpression(node) => {
+            Expression::DatamodelExpression(DatamodelExpression {
+                expr: f.fold_datamodel_expression(node.expr),
+                ..node
+            })
+        }
+        Expression::MicrostepResult(node) => Expression::MicrostepResult(MicrostepResult {
+            configuration: f.fold_simple_expression(node.configuration),
+            initialized: f.fold_simple_expression(node.initialized),
+            history: f.fold_simple_expression(node.history),
+            transitions: f.fold_simple_expression(node.transitions),
+            is_stable: f.fold_simple_expression(node.is_stable),
+            ..node
+        }),
+        node => node,
+    }
+}
+
+pub fn fold_simple_expression<F: Fold + ?Sized>(
+    f: &mut F,
+    node: SimpleExpression,
+) -> SimpleExpression {
+    match node {
+        SimpleExpression::LogicalExpression(node) => {
+            SimpleExpression::LogicalExpression(LogicalExpression {
+                arguments: fold_expressions(f, node.arguments),
+                ..node
+            })
+        }
+        SimpleExpression::ConfigurationCreateExpression(node) => {
+            SimpleExpression::ConfigurationCreateExpression(ConfigurationCreateExpression {
+                arguments: fold_expressions(f, node.arguments),
+                ..node
+            })
+        }
+        SimpleExpression::InvocationsCreateExpression(node) => {
+            SimpleExpression::InvocationsCreateExpression(InvocationsCreateExpression {
+                arguments: fold_expressions(f, node.arguments),
+                ..node
+            })
+        }
+        node => node,
+    }
+}
+
+pub fn fold_variable<F: Fold + ?Sized>(f: &mut F, node: Variable) -> Variable {
+    Variable {
+        init: node.init.map(|init| f.fold_datamodel_expression(init)),
+        ..node
+    }
+}
+
+pub fn fold_datamodel_expression<F: Fold + ?Sized>(
+    f: &mut F,
+    node: datamodel::Expression,
+) -> datamodel::Expression {
+    match node {
+        datamodel::Expression::Unary(node) => datamodel::Expression::Unary(UnaryExpression {
+            argument: Box::new(f.fold_datamodel_expression(*node.argument)),
+            ..node
+        }),
+        datamodel::Expression::Binary(node) => datamodel::Expression::Binary(BinaryExpression {
+            left: Box::new(f.fold_datamodel_expression(*node.left)),
+            right: Box::new(f.fold_datamodel_expression(*node.right)),
+            ..node
+        }),
+        node => node,
+    }
+}
@@ -1,100 +1,86 @@
+use ast::datamodel::{self, Variable};
+use ast::executable::Executable;
 use ast::location::Location;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 
 #[path = "statechart/to_core.rs"]
 pub mod to_core;
 
-pub type ExecutableId = usize;
-pub type InvocationId = usize;
-pub type ConditonId = usize;
-pub type EventId = usize;
+#[path = "statechart/to_scxml.rs"]
+pub mod to_scxml;
 
-#[derive(Clone, Debug)]
-pub enum IteratorEvent {
-    Enter(Box<Node>),
-    Exit(Box<Node>),
-}
+#[path = "statechart/from_scxml.rs"]
+pub mod from_scxml;
 
-#[derive(Clone, Debug)]
-pub struct ChildIterator {
-    node: Option<Box<NodeIterator>>,
-    children: Vec<Node>,
-    i: usize,
-}
+#[path = "statechart/to_xstate.rs"]
+pub mod to_xstate;
 
-impl Iterator for ChildIterator {
-    type Item = IteratorEvent;
+#[path = "statechart/from_xstate.rs"]
+pub mod from_xstate;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if let Some(ref mut iter) = self.node {
-            let next = iter.next();
-            if next.is_none() {
-                self.i += 1;
-            } else {
-                return next;
-            }
-        }
+#[path = "statechart/builder.rs"]
+pub mod builder;
 
-        if self.i >= self.children.len() {
-            return None;
-        }
-
-        let child = Box::new(self.children[self.i].clone());
-
-        let child_itr = Box::new(NodeIterator {
-            node: child,
-            children: None,
-            is_finished: false,
-        });
+#[path = "statechart/visit.rs"]
+pub mod visit;
 
-        self.node = Some(child_itr);
+pub type ExecutableId = usize;
+pub type InvocationId = usize;
+pub type ConditonId = usize;
+pub type EventId = usize;
 
-        self.next()
-    }
+#[derive(Clone, Copy, Debug)]
+pub enum IteratorEvent<'a> {
+    Enter(&'a Node),
+    Exit(&'a Node),
 }
 
+// walks a node and its descendants in document order, entering each node before its
+// children and exiting it after them
 #[derive(Clone, Debug)]
-pub struct NodeIterator {
-    node: Box<Node>,
-    children: Option<Box<ChildIterator>>,
-    is_finished: bool,
+pub struct NodeIterator<'a> {
+    root: Option<&'a Node>,
+    // the nodes entered but not yet exited, with the index of their next child
+    stack: Vec<(&'a Node, usize)>,
 }
 
-impl Iterator for NodeIterator {
-    type Item = IteratorEvent;
+impl<'a> Iterator for NodeIterator<'a> {
+    type Item = IteratorEvent<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.is_finished {
-            return None;
+        if let Some(root) = self.root.take() {
+            self.stack.push((root, 0));
+            return Some(IteratorEvent::Enter(root));
         }
 
-        if let Some(ref mut iter) = self.children {
-            let next = iter.next();
-            if next.is_none() {
-                self.is_finished = true;
-                return Some(IteratorEvent::Exit(self.node.clone()));
+        let (node, i) = self.stack.last_mut()?;
+        let node: &'a Node = node;
+        match child_nodes(node).get(*i) {
+            Some(child) => {
+                *i += 1;
+                self.stack.push((child, 0));
+                Some(IteratorEvent::Enter(child))
+            }
+            None => {
+                self.stack.pop();
+                Some(IteratorEvent::Exit(node))
             }
-
-            return next;
         }
+    }
+}
 
-        let children = match self.node.as_ref() {
-            Node::Statechart(node) => node.children.clone(),
-            Node::State(node) => node.children.clone(),
-            Node::Parallel(node) => node.children.clone(),
-            Node::Initial(node) => node.children.clone(),
-            Node::Final(node) => node.children.clone(),
-            _ => vec![],
-        };
-
-        let child_iterator = Box::new(ChildIterator {
-            node: None,
-            children,
-            i: 0,
-        });
-
-        self.children = Some(child_iterator);
-
-        Some(IteratorEvent::Enter(self.node.clone()))
+// the children the iterator descends into. The children of history states and
+// transitions are left to whoever handles those nodes.
+fn child_nodes(node: &Node) -> &[Node] {
+    match node {
+        Node::Statechart(node) => &node.children,
+        Node::State(node) => &node.children,
+        Node::Parallel(node) => &node.children,
+        Node::Initial(node) => &node.children,
+        Node::Final(node) => &node.children,
+        _ => &[],
     }
 }
 
@@ -116,11 +102,10 @@ pub enum Node {
 }
 
 impl Node {
-    pub fn iter(self) -> NodeIterator {
+    pub fn iter(&self) -> NodeIterator<'_> {
         NodeIterator {
-            node: Box::from(self),
-            children: None,
-            is_finished: false,
+            root: Some(self),
+            stack: vec![],
         }
     }
 }
@@ -146,6 +131,9 @@ pub struct Statechart {
     #[serde(default)]
     pub binding: Binding,
 
+    #[serde(default)]
+    pub datamodel: Vec<Variable>,
+
     #[serde(default)]
     pub children: Vec<Node>,
 
@@ -162,6 +150,9 @@ pub struct State {
     #[serde(default)]
     pub initial: Vec<String>,
 
+    #[serde(default)]
+    pub datamodel: Vec<Variable>,
+
     #[serde(default)]
     pub children: Vec<Node>,
 
@@ -207,10 +198,88 @@ pub struct Transition {
     pub t: TransitionType,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub condition: Option<ConditonId>,
+    pub condition: Option<Condition>,
 
     #[serde(default)]
-    pub children: Vec<ExecutableId>,
+    pub children: Vec<Executable>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+// serialized tagged by `type`. Charts written before conditions were structured name a
+// bare condition id, which still deserializes as `Predicate`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case", remote = "Self")]
+pub enum Condition {
+    Predicate(Predicate),
+    In(In),
+    And(Conditions),
+    Or(Conditions),
+    Not(Not),
+    Expression(ExpressionCondition),
+}
+
+impl Serialize for Condition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Condition::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Condition, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if let Some(id) = value.as_u64() {
+            return Ok(Condition::Predicate(Predicate {
+                id: id as ConditonId,
+                loc: Location::default(),
+            }));
+        }
+        Condition::deserialize(value).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Predicate {
+    pub id: ConditonId,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct In {
+    pub state: String,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    #[serde(default)]
+    pub children: Vec<Condition>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Not {
+    pub condition: Box<Condition>,
+
+    #[serde(default)]
+    pub loc: Location,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExpressionCondition {
+    pub expr: datamodel::Expression,
 
     #[serde(default)]
     pub loc: Location,
@@ -223,10 +292,10 @@ pub struct OnEvent {
     pub event: Option<EventId>,
 
     #[serde(default, skip_serializing_if = "Option::is_none")]
-    pub condition: Option<ConditonId>,
+    pub condition: Option<Condition>,
 
     #[serde(default)]
-    pub children: Vec<ExecutableId>,
+    pub children: Vec<Executable>,
 
     #[serde(default)]
     pub loc: Location,
@@ -259,7 +328,7 @@ pub struct Final {
 #[serde(deny_unknown_fields)]
 pub struct OnInit {
     #[serde(default)]
-    pub children: Vec<ExecutableId>,
+    pub children: Vec<Executable>,
 
     #[serde(default)]
     pub loc: Location,
@@ -269,7 +338,7 @@ pub struct OnInit {
 #[serde(deny_unknown_fields)]
 pub struct OnEntry {
     #[serde(default)]
-    pub children: Vec<ExecutableId>,
+    pub children: Vec<Executable>,
 
     #[serde(default)]
     pub loc: Location,
@@ -279,7 +348,7 @@ pub struct OnEntry {
 #[serde(deny_unknown_fields)]
 pub struct OnExit {
     #[serde(default)]
-    pub children: Vec<ExecutableId>,
+    pub children: Vec<Executable>,
 
     #[serde(default)]
     pub loc: Location,
@@ -321,3 +390,38 @@ pub struct Invoke {
     #[serde(default)]
     pub loc: Location,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+
+    #[test]
+    fn bare_condition_ids_are_predicates() {
+        let statechart: Statechart = serde_json::from_str(
+            r#"{"children": [
+                {"type": "state", "id": "a", "children": [
+                    {"type": "transition", "event": 0, "targets": ["b"], "condition": 2}
+                ]},
+                {"type": "state", "id": "b"}
+            ]}"#,
+        )
+        .unwrap();
+        let transition = match statechart.children[0] {
+            Node::State(ref state) => match state.children[0] {
+                Node::Transition(ref transition) => transition.clone(),
+                ref other => panic!("expected a transition, got {:?}", other),
+            },
+            ref other => panic!("expected a state, got {:?}", other),
+        };
+        match transition.condition {
+            Some(Condition::Predicate(Predicate { id: 2, .. })) => {}
+            ref other => panic!("expected predicate 2, got {:?}", other),
+        }
+        let json = serde_json::to_string(&transition).unwrap();
+        assert!(json.contains(r#""type":"predicate""#), "{}", json);
+
+        let err = serde_json::from_str::<Condition>("-1").unwrap_err();
+        assert!(err.to_string().contains("type"), "{}", err);
+    }
+}
@@ -0,0 +1,323 @@
+use ast::conversion_error::Errors;
+use ast::core::Core;
+use ast::datamodel::Variable;
+use ast::executable::Executable;
+use ast::statechart::*;
+
+impl Statechart {
+    // Statechart::builder()
+    //     .state("idle", |s| s.on(1, "running"))
+    //     .parallel("running", |p| p.state("a", |s| s).state("b", |s| s.on(2, "idle")))
+    //     .build_core()
+    pub fn builder() -> StatechartBuilder {
+        StatechartBuilder {
+            statechart: Statechart::default(),
+        }
+    }
+}
+
+// methods for the builders whose nodes contain states
+macro_rules! child_states {
+    () => {
+        pub fn state<F>(mut self, id: &str, f: F) -> Self
+        where
+            F: FnOnce(StateBuilder) -> StateBuilder,
+        {
+            let state = f(StateBuilder::new(id)).state;
+            self.children().push(Node::State(state));
+            self
+        }
+
+        pub fn atomic(self, id: &str) -> Self {
+            self.state(id, |s| s)
+        }
+
+        pub fn parallel<F>(mut self, id: &str, f: F) -> Self
+        where
+            F: FnOnce(ParallelBuilder) -> ParallelBuilder,
+        {
+            let parallel = f(ParallelBuilder::new(id)).parallel;
+            self.children().push(Node::Parallel(parallel));
+            self
+        }
+
+        pub fn final_state(mut self, id: &str) -> Self {
+            self.children().push(Node::Final(Final {
+                id: Some(id.to_string()),
+                ..Default::default()
+            }));
+            self
+        }
+    };
+}
+
+// methods for the builders whose nodes can have transitions and actions
+macro_rules! transitions {
+    () => {
+        pub fn on(self, event: EventId, target: &str) -> Self {
+            self.transition(|t| t.event(event).target(target))
+        }
+
+        // an eventless transition
+        pub fn always(self, target: &str) -> Self {
+            self.transition(|t| t.target(target))
+        }
+
+        pub fn transition<F>(mut self, f: F) -> Self
+        where
+            F: FnOnce(TransitionBuilder) -> TransitionBuilder,
+        {
+            let transition = f(TransitionBuilder::default()).transition;
+            self.children().push(Node::Transition(transition));
+            self
+        }
+
+        pub fn on_entry(mut self, executable: Executable) -> Self {
+            self.children().push(Node::OnEntry(OnEntry {
+                children: vec![executable],
+                ..Default::default()
+            }));
+            self
+        }
+
+        pub fn on_exit(mut self, executable: Executable) -> Self {
+            self.children().push(Node::OnExit(OnExit {
+                children: vec![executable],
+                ..Default::default()
+            }));
+            self
+        }
+
+        pub fn history(mut self, id: &str, t: HistoryType) -> Self {
+            self.children().push(Node::History(History {
+                id: Some(id.to_string()),
+                t,
+                ..Default::default()
+            }));
+            self
+        }
+    };
+}
+
+#[derive(Clone, Debug)]
+pub struct StatechartBuilder {
+    statechart: Statechart,
+}
+
+impl StatechartBuilder {
+    child_states!();
+
+    fn children(&mut self) -> &mut Vec<Node> {
+        &mut self.statechart.children
+    }
+
+    // the chart starts in the first child state unless it names another child here
+    pub fn initial(mut self, id: &str) -> Self {
+        self.statechart.initital.push(id.to_string());
+        self
+    }
+
+    pub fn binding(mut self, binding: Binding) -> Self {
+        self.statechart.binding = binding;
+        self
+    }
+
+    pub fn data(mut self, variable: Variable) -> Self {
+        self.statechart.datamodel.push(variable);
+        self
+    }
+
+    pub fn build(self) -> Statechart {
+        self.statechart
+    }
+
+    // converts the chart, which reports missing targets and invalid datamodel use
+    pub fn build_core(self) -> Result<Core, Errors> {
+        self.statechart.into()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StateBuilder {
+    state: State,
+}
+
+impl StateBuilder {
+    fn new(id: &str) -> StateBuilder {
+        StateBuilder {
+            state: State {
+                id: Some(id.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    child_states!();
+    transitions!();
+
+    fn children(&mut self) -> &mut Vec<Node> {
+        &mut self.state.children
+    }
+
+    // the child state entered with this state, instead of the first one
+    pub fn initial(mut self, id: &str) -> Self {
+        self.state.initial.push(id.to_string());
+        self
+    }
+
+    pub fn data(mut self, variable: Variable) -> Self {
+        self.state.datamodel.push(variable);
+        self
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ParallelBuilder {
+    parallel: Parallel,
+}
+
+impl ParallelBuilder {
+    fn new(id: &str) -> ParallelBuilder {
+        ParallelBuilder {
+            parallel: Parallel {
+                id: Some(id.to_string()),
+                ..Default::default()
+            },
+        }
+    }
+
+    child_states!();
+    transitions!();
+
+    fn children(&mut self) -> &mut Vec<Node> {
+        &mut self.parallel.children
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TransitionBuilder {
+    transition: Transition,
+}
+
+impl TransitionBuilder {
+    pub fn event(mut self, event: EventId) -> Self {
+        self.transition.event = Some(event);
+        self
+    }
+
+    pub fn target(mut self, id: &str) -> Self {
+        self.transition.targets.push(id.to_string());
+        self
+    }
+
+    pub fn internal(mut self) -> Self {
+        self.transition.t = TransitionType::Internal;
+        self
+    }
+
+    pub fn condition(mut self, condition: Condition) -> Self {
+        self.transition.condition = Some(condition);
+        self
+    }
+
+    // guards the transition with the host condition `id`
+    pub fn when(self, id: ConditonId) -> Self {
+        self.condition(Condition::Predicate(Predicate {
+            id,
+            ..Default::default()
+        }))
+    }
+
+    pub fn action(mut self, executable: Executable) -> Self {
+        self.transition.children.push(executable);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::core::{StateType, TransitionType};
+    use ast::microstep::Microstep;
+    use runtime::interpreter::{Host, Interpreter};
+
+    struct NoConditions;
+
+    impl Host for NoConditions {
+        fn condition(&mut self, _id: usize) -> bool {
+            false
+        }
+    }
+
+    fn idx(core: &Core, id: &str) -> usize {
+        core.states
+            .iter()
+            .position(|state| state.id.as_deref() == Some(id))
+            .unwrap()
+    }
+
+    fn start(core: &Core) -> Vec<String> {
+        let microstep: Result<Microstep, Errors> = core.clone().into();
+        let microstep = microstep.unwrap();
+        let mut interpreter = Interpreter::new(&microstep);
+        interpreter.start(&mut NoConditions).unwrap();
+        interpreter
+            .active_states()
+            .into_iter()
+            .filter_map(|state| core.states[state].id.clone())
+            .collect()
+    }
+
+    #[test]
+    fn builds_the_core_of_the_chart() {
+        let core = Statechart::builder()
+            .state("idle", |s| s.on(1, "running"))
+            .parallel("running", |p| {
+                p.state("a", |s| s.history("h", HistoryType::Deep).atomic("a1"))
+                    .state("b", |s| {
+                        s.transition(|t| t.event(2).target("b1").internal())
+                            .atomic("b1")
+                    })
+            })
+            .build_core()
+            .unwrap();
+
+        let running = &core.states[idx(&core, "running")];
+        assert_eq!(running.t, StateType::Parallel);
+        assert_eq!(running.initial, vec![idx(&core, "a"), idx(&core, "b")]);
+        assert_eq!(core.states[idx(&core, "h")].t, StateType::HistoryDeep);
+
+        let transition = &core.transitions[core.states[idx(&core, "idle")].transitions[0]];
+        assert_eq!(transition.event, Some(1));
+        assert_eq!(transition.targets, vec![idx(&core, "running")]);
+        let internal = &core.transitions[core.states[idx(&core, "b")].transitions[0]];
+        assert_eq!(internal.t, TransitionType::Internal);
+
+        assert_eq!(start(&core), vec!["idle"]);
+    }
+
+    #[test]
+    fn initial_picks_the_child_entered_first() {
+        let core = Statechart::builder()
+            .initial("b")
+            .atomic("a")
+            .state("b", |s| s.initial("b2").atomic("b1").atomic("b2"))
+            .build_core()
+            .unwrap();
+
+        assert_eq!(core.states[0].initial, vec![idx(&core, "b")]);
+        assert_eq!(core.states[idx(&core, "b")].initial, vec![idx(&core, "b2")]);
+        assert_eq!(start(&core), vec!["b", "b2"]);
+        assert!(core.verify().is_empty());
+    }
+
+    #[test]
+    fn initial_must_name_a_child() {
+        let errors = Statechart::builder()
+            .initial("a1")
+            .state("a", |s| s.atomic("a1"))
+            .build_core()
+            .unwrap_err();
+        assert!(errors[0].message.contains("not a child"), "{:?}", errors);
+    }
+}
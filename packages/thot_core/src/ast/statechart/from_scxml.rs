@@ -0,0 +1,1057 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::datamodel::{self, Type, Variable};
+use ast::executable::{self, Executable};
+use ast::location::{Location, Point};
+use ast::statechart::*;
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+const SOURCE: &str = "statechart/ast/statechart/from_scxml";
+
+pub fn from_scxml(source: &str) -> Result<Statechart, Errors> {
+    let root = parse_xml(source)?;
+    let mut parser = Parser { errors: vec![] };
+
+    if root.name != "scxml" {
+        parser.error(format!("Expected <scxml>, found <{}>", root.name), root.loc);
+    }
+
+    let statechart = Statechart {
+        initital: parser.list(&root, "initial"),
+        binding: match root.attr("binding") {
+            Some("early") => Binding::Early,
+            _ => Binding::Late,
+        },
+        datamodel: parser.datamodel(&root),
+        children: parser.nodes(&root),
+        loc: root.loc,
+    };
+
+    if parser.errors.is_empty() {
+        Ok(statechart)
+    } else {
+        Err(parser.errors)
+    }
+}
+
+pub fn parse_condition(source: &str, loc: Location) -> Result<Condition, ConversionError> {
+    match ExpressionParser::new(source, loc)?.parse()? {
+        Parsed::Condition(condition) => Ok(condition),
+        Parsed::Expression(expr) => Ok(Condition::Expression(ExpressionCondition { expr, loc })),
+    }
+}
+
+pub fn parse_expression(
+    source: &str,
+    loc: Location,
+) -> Result<datamodel::Expression, ConversionError> {
+    match ExpressionParser::new(source, loc)?.parse()? {
+        Parsed::Expression(expr) => Ok(expr),
+        Parsed::Condition(_) => Err(error(
+            format!("Expected a data expression: {:?}", source),
+            loc,
+        )),
+    }
+}
+
+pub fn parse_type(source: &str, loc: Location) -> Result<Type, ConversionError> {
+    let source = source.trim();
+    match source {
+        "integer" => Ok(Type::Integer),
+        "boolean" => Ok(Type::Boolean),
+        "string" => Ok(Type::String),
+        _ if source.starts_with("enum(") && source.ends_with(')') => Ok(Type::Enum(
+            source[5..source.len() - 1]
+                .split(',')
+                .map(|variant| variant.trim().to_string())
+                .filter(|variant| !variant.is_empty())
+                .collect(),
+        )),
+        _ => Err(error(format!("Invalid type: {:?}", source), loc)),
+    }
+}
+
+fn error(message: String, loc: Location) -> ConversionError {
+    ConversionError {
+        message,
+        fatal: true,
+        source: SOURCE.to_string(),
+        loc,
+    }
+}
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    loc: Location,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_xml(source: &str) -> Result<Element, Errors> {
+    let mut stack: Vec<Element> = vec![];
+    let mut reader = EventReader::from_str(source);
+
+    loop {
+        // the position of the event `next` just returned, or of the error
+        let event = reader.next();
+        let position = reader.position();
+        let point = Point {
+            line: position.row as usize + 1,
+            column: position.column as usize,
+        };
+        let event = event.map_err(|err| {
+            vec![error(
+                err.msg().to_string(),
+                Location {
+                    start: point,
+                    end: point,
+                    source: None,
+                },
+            )]
+        })?;
+
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(Element {
+                name: name.local_name,
+                attrs: attributes
+                    .into_iter()
+                    .filter(|attr| attr.name.prefix.is_none())
+                    .map(|attr| (attr.name.local_name, attr.value))
+                    .collect(),
+                children: vec![],
+                loc: Location {
+                    start: point,
+                    end: point,
+                    source: None,
+                },
+            }),
+            XmlEvent::EndElement { .. } => {
+                let mut element = stack.pop().unwrap();
+                element.loc.end = point;
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::EndDocument => {
+                return Err(vec![error(
+                    "Missing <scxml> element".to_string(),
+                    Location::default(),
+                )])
+            }
+            _ => (),
+        }
+    }
+}
+
+struct Parser {
+    errors: Errors,
+}
+
+impl Parser {
+    fn error(&mut self, message: String, loc: Location) {
+        self.errors.push(error(message, loc));
+    }
+
+    fn list(&mut self, element: &Element, name: &str) -> Vec<String> {
+        element
+            .attr(name)
+            .map(|value| value.split_whitespace().map(|s| s.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    fn id(&mut self, element: &Element) -> Option<String> {
+        element.attr("id").map(|id| id.to_string())
+    }
+
+    fn usize_attr(&mut self, element: &Element, name: &str) -> Option<usize> {
+        let value = element.attr(name)?;
+        match value.trim().parse() {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.error(
+                    format!("Invalid {} on <{}>: {:?}", name, element.name, value),
+                    element.loc,
+                );
+                None
+            }
+        }
+    }
+
+    fn required<'a>(&mut self, element: &'a Element, name: &str) -> Option<&'a str> {
+        let value = element.attr(name);
+        if value.is_none() {
+            self.error(
+                format!("Missing {} on <{}>", name, element.name),
+                element.loc,
+            );
+        }
+        value
+    }
+
+    // the `cond` of <if> and <elseif>, which is required
+    fn required_condition(&mut self, element: &Element) -> Condition {
+        let value = self.required(element, "cond").unwrap_or("#0");
+        match parse_condition(value, element.loc) {
+            Ok(condition) => condition,
+            Err(err) => {
+                self.errors.push(err);
+                Condition::Predicate(Predicate {
+                    id: 0,
+                    loc: element.loc,
+                })
+            }
+        }
+    }
+
+    fn condition(&mut self, element: &Element) -> Option<Condition> {
+        let value = element.attr("cond")?;
+        match parse_condition(value, element.loc) {
+            Ok(condition) => Some(condition),
+            Err(err) => {
+                self.errors.push(err);
+                None
+            }
+        }
+    }
+
+    fn expression(&mut self, element: &Element, value: &str) -> datamodel::Expression {
+        match parse_expression(value, element.loc) {
+            Ok(expr) => expr,
+            Err(err) => {
+                self.errors.push(err);
+                datamodel::Expression::Boolean(datamodel::BooleanLiteral {
+                    value: false,
+                    loc: element.loc,
+                })
+            }
+        }
+    }
+
+    fn datamodel(&mut self, element: &Element) -> Vec<Variable> {
+        let mut variables = vec![];
+        for datamodel in element.children.iter().filter(|c| c.name == "datamodel") {
+            for data in &datamodel.children {
+                if data.name != "data" {
+                    self.error(format!("Unexpected <{}>", data.name), data.loc);
+                    continue;
+                }
+                let id = self.required(data, "id").unwrap_or_default().to_string();
+                let t = match parse_type(data.attr("type").unwrap_or("string"), data.loc) {
+                    Ok(t) => t,
+                    Err(err) => {
+                        self.errors.push(err);
+                        Type::String
+                    }
+                };
+                let init = data.attr("expr").map(|expr| self.expression(data, expr));
+                variables.push(Variable {
+                    id,
+                    t,
+                    init,
+                    loc: data.loc,
+                });
+            }
+        }
+        variables
+    }
+
+    fn nodes(&mut self, element: &Element) -> Vec<Node> {
+        element
+            .children
+            .iter()
+            .filter(|child| child.name != "datamodel")
+            .filter_map(|child| self.node(child))
+            .collect()
+    }
+
+    fn node(&mut self, element: &Element) -> Option<Node> {
+        let loc = element.loc;
+        Some(match element.name.as_str() {
+            "scxml" => Node::Statechart(Statechart {
+                initital: self.list(element, "initial"),
+                datamodel: self.datamodel(element),
+                children: self.nodes(element),
+                loc,
+                ..Default::default()
+            }),
+            "state" => Node::State(State {
+                id: self.id(element),
+                initial: self.list(element, "initial"),
+                datamodel: self.datamodel(element),
+                children: self.nodes(element),
+                loc,
+            }),
+            "parallel" => Node::Parallel(Parallel {
+                id: self.id(element),
+                children: self.nodes(element),
+                loc,
+            }),
+            "transition" => Node::Transition(Transition {
+                event: self.usize_attr(element, "event"),
+                targets: self.list(element, "target"),
+                t: match element.attr("type") {
+                    Some("internal") => TransitionType::Internal,
+                    _ => TransitionType::External,
+                },
+                condition: self.condition(element),
+                children: self.executables(&element.children),
+                loc,
+            }),
+            "onevent" => Node::OnEvent(OnEvent {
+                event: self.usize_attr(element, "event"),
+                condition: self.condition(element),
+                children: self.executables(&element.children),
+                loc,
+            }),
+            "initial" => Node::Initial(Initial {
+                children: self.nodes(element),
+                loc,
+            }),
+            "final" => Node::Final(Final {
+                id: self.id(element),
+                children: self.nodes(element),
+                loc,
+            }),
+            "oninit" => Node::OnInit(OnInit {
+                children: self.executables(&element.children),
+                loc,
+            }),
+            "onentry" => Node::OnEntry(OnEntry {
+                children: self.executables(&element.children),
+                loc,
+            }),
+            "onexit" => Node::OnExit(OnExit {
+                children: self.executables(&element.children),
+                loc,
+            }),
+            "history" => Node::History(History {
+                id: self.id(element),
+                t: match element.attr("type") {
+                    Some("deep") => HistoryType::Deep,
+                    _ => HistoryType::Shallow,
+                },
+                children: self.nodes(element),
+                loc,
+            }),
+            "invoke" => Node::Invoke(Invoke {
+                id: self.usize_attr(element, "id").unwrap_or_default(),
+                loc,
+            }),
+            name => {
+                self.error(format!("Unexpected <{}>", name), loc);
+                return None;
+            }
+        })
+    }
+
+    fn executables(&mut self, elements: &[Element]) -> Vec<Executable> {
+        elements
+            .iter()
+            .filter_map(|element| self.executable(element))
+            .collect()
+    }
+
+    fn executable(&mut self, element: &Element) -> Option<Executable> {
+        let loc = element.loc;
+        Some(match element.name.as_str() {
+            "script" => Executable::Script(executable::Script {
+                id: self.usize_attr(element, "id").unwrap_or_default(),
+                loc,
+            }),
+            "raise" => Executable::Raise(executable::Raise {
+                event: self.usize_attr(element, "event").unwrap_or_default(),
+                loc,
+            }),
+            "send" => Executable::Send(executable::Send {
+                event: self.usize_attr(element, "event").unwrap_or_default(),
+                target: element.attr("target").map(|s| s.to_string()),
+                id: element.attr("id").map(|s| s.to_string()),
+                delay: self.delay(element),
+                loc,
+            }),
+            "assign" => {
+                let location = self.required(element, "location").unwrap_or_default();
+                let expr = self.required(element, "expr").unwrap_or("false");
+                Executable::Assign(executable::Assign {
+                    location: location.to_string(),
+                    expr: self.expression(element, expr),
+                    loc,
+                })
+            }
+            "log" => Executable::Log(executable::Log {
+                label: element.attr("label").map(|s| s.to_string()),
+                expr: element
+                    .attr("expr")
+                    .map(|expr| self.expression(element, expr)),
+                loc,
+            }),
+            "if" => self.if_executable(element),
+            "foreach" => Executable::Foreach(executable::Foreach {
+                array: self.usize_attr(element, "array").unwrap_or_default(),
+                item: self
+                    .required(element, "item")
+                    .unwrap_or_default()
+                    .to_string(),
+                index: element.attr("index").map(|s| s.to_string()),
+                children: self.executables(&element.children),
+                loc,
+            }),
+            "cancel" => Executable::Cancel(executable::Cancel {
+                id: self
+                    .required(element, "sendid")
+                    .unwrap_or_default()
+                    .to_string(),
+                loc,
+            }),
+            name => {
+                self.error(format!("Unexpected <{}>", name), loc);
+                return None;
+            }
+        })
+    }
+
+    fn if_executable(&mut self, element: &Element) -> Executable {
+        let mut node = executable::If {
+            condition: self.required_condition(element),
+            children: vec![],
+            else_if: vec![],
+            otherwise: vec![],
+            loc: element.loc,
+        };
+
+        // <elseif/> and <else/> partition the children of <if>
+        let mut is_else = false;
+        for child in &element.children {
+            match child.name.as_str() {
+                "elseif" => node.else_if.push(executable::ElseIf {
+                    condition: self.required_condition(child),
+                    children: vec![],
+                    loc: child.loc,
+                }),
+                "else" => is_else = true,
+                _ => {
+                    if let Some(executable) = self.executable(child) {
+                        match node.else_if.last_mut() {
+                            _ if is_else => node.otherwise.push(executable),
+                            Some(else_if) => else_if.children.push(executable),
+                            None => node.children.push(executable),
+                        }
+                    }
+                }
+            }
+        }
+
+        Executable::If(node)
+    }
+
+    fn delay(&mut self, element: &Element) -> usize {
+        let value = match element.attr("delay") {
+            Some(value) => value.trim(),
+            None => return 0,
+        };
+        let parsed = if let Some(millis) = value.strip_suffix("ms") {
+            millis.parse().ok()
+        } else if let Some(seconds) = value.strip_suffix('s') {
+            seconds.parse::<usize>().ok().map(|seconds| seconds * 1000)
+        } else {
+            value.parse().ok()
+        };
+        parsed.unwrap_or_else(|| {
+            self.error(format!("Invalid delay: {:?}", value), element.loc);
+            0
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    // the magnitude, which may be one past i64::MAX when negated
+    Integer(u64),
+    String(String),
+    Enum(String),
+    Ident(String),
+    Predicate(usize),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: &[&str] = &[
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "%",
+];
+
+enum Parsed {
+    Condition(Condition),
+    Expression(datamodel::Expression),
+}
+
+impl Parsed {
+    fn into_condition(self, loc: Location) -> Condition {
+        match self {
+            Parsed::Condition(condition) => condition,
+            Parsed::Expression(expr) => Condition::Expression(ExpressionCondition { expr, loc }),
+        }
+    }
+}
+
+struct ExpressionParser {
+    tokens: Vec<Token>,
+    position: usize,
+    loc: Location,
+}
+
+impl ExpressionParser {
+    fn new(source: &str, loc: Location) -> Result<ExpressionParser, ConversionError> {
+        let chars: Vec<char> = source.chars().collect();
+        let mut tokens = vec![];
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            if c.is_whitespace() {
+                i += 1;
+            } else if c == '(' || c == ')' {
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+                i += 1;
+            } else if c.is_ascii_digit() || c == '#' {
+                let start = if c == '#' { i + 1 } else { i };
+                let mut end = start;
+                while end < chars.len() && chars[end].is_ascii_digit() {
+                    end += 1;
+                }
+                let digits: String = chars[start..end].iter().collect();
+                tokens.push(if c == '#' {
+                    Token::Predicate(digits.parse().map_err(|_| {
+                        error(format!("Invalid condition placeholder: {:?}", source), loc)
+                    })?)
+                } else {
+                    Token::Integer(
+                        digits
+                            .parse()
+                            .map_err(|_| error(format!("Invalid integer: {:?}", digits), loc))?,
+                    )
+                });
+                i = end;
+            } else if c == '"' || c == '\'' {
+                let mut value = String::new();
+                let mut end = i + 1;
+                while end < chars.len() && chars[end] != c {
+                    if chars[end] == '\\' && end + 1 < chars.len() {
+                        end += 1;
+                        value.push(datamodel::unescape(chars[end]));
+                    } else {
+                        value.push(chars[end]);
+                    }
+                    end += 1;
+                }
+                if end >= chars.len() {
+                    return Err(error(format!("Unterminated literal: {:?}", source), loc));
+                }
+                tokens.push(if c == '"' {
+                    Token::String(value)
+                } else {
+                    Token::Enum(value)
+                });
+                i = end + 1;
+            } else if c.is_alphabetic() || c == '_' {
+                let mut end = i;
+                while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+                    end += 1;
+                }
+                tokens.push(Token::Ident(chars[i..end].iter().collect()));
+                i = end;
+            } else {
+                let rest: String = chars[i..].iter().take(2).collect();
+                match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    Some(op) => {
+                        tokens.push(Token::Operator(op));
+                        i += op.len();
+                    }
+                    None => return Err(error(format!("Unexpected {:?} in {:?}", c, source), loc)),
+                }
+            }
+        }
+
+        Ok(ExpressionParser {
+            tokens,
+            position: 0,
+            loc,
+        })
+    }
+
+    fn parse(mut self) -> Result<Parsed, ConversionError> {
+        let parsed = self.parse_or()?;
+        match self.tokens.get(self.position) {
+            None => Ok(parsed),
+            Some(token) => Err(self.unexpected(token)),
+        }
+    }
+
+    fn unexpected(&self, token: &Token) -> ConversionError {
+        error(format!("Unexpected {:?}", token), self.loc)
+    }
+
+    fn peek_operator(&self, operators: &[&str]) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(op)) if operators.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Parsed, ConversionError> {
+        self.parse_logical(
+            "||",
+            datamodel::BinaryOperator::Or,
+            ExpressionParser::parse_and,
+        )
+    }
+
+    fn parse_and(&mut self) -> Result<Parsed, ConversionError> {
+        self.parse_logical(
+            "&&",
+            datamodel::BinaryOperator::And,
+            ExpressionParser::parse_comparison,
+        )
+    }
+
+    fn parse_logical(
+        &mut self,
+        op: &str,
+        operator: datamodel::BinaryOperator,
+        next: fn(&mut ExpressionParser) -> Result<Parsed, ConversionError>,
+    ) -> Result<Parsed, ConversionError> {
+        let mut operands = vec![next(self)?];
+        while self.peek_operator(&[op]).is_some() {
+            self.position += 1;
+            operands.push(next(self)?);
+        }
+
+        if operands.len() == 1 {
+            return Ok(operands.pop().unwrap());
+        }
+
+        let loc = self.loc;
+        if operands
+            .iter()
+            .all(|operand| matches!(operand, Parsed::Expression(_)))
+        {
+            let mut operands = operands.into_iter().map(|operand| match operand {
+                Parsed::Expression(expr) => expr,
+                _ => unreachable!(),
+            });
+            let first = operands.next().unwrap();
+            return Ok(Parsed::Expression(operands.fold(first, |left, right| {
+                datamodel::Expression::Binary(datamodel::BinaryExpression {
+                    operator,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    loc,
+                })
+            })));
+        }
+
+        let children = Conditions {
+            children: operands
+                .into_iter()
+                .map(|operand| operand.into_condition(loc))
+                .collect(),
+            loc,
+        };
+        Ok(Parsed::Condition(match operator {
+            datamodel::BinaryOperator::And => Condition::And(children),
+            _ => Condition::Or(children),
+        }))
+    }
+
+    // binds tighter than any binary operator, so `-a + b` is `(-a) + b`
+    fn parse_unary(&mut self) -> Result<Parsed, ConversionError> {
+        let loc = self.loc;
+        match self.peek_operator(&["!", "-"]) {
+            Some(op) => {
+                self.position += 1;
+                if let (Some(&Token::Integer(value)), "-") = (self.tokens.get(self.position), op) {
+                    if value <= i64::MIN.unsigned_abs() {
+                        self.position += 1;
+                        return Ok(Parsed::Expression(datamodel::Expression::Integer(
+                            datamodel::IntegerLiteral {
+                                value: (value as i64).wrapping_neg(),
+                                loc,
+                            },
+                        )));
+                    }
+                }
+                let argument = self.parse_unary()?;
+                match (op, argument) {
+                    ("!", Parsed::Condition(condition)) => {
+                        Ok(Parsed::Condition(Condition::Not(Not {
+                            condition: Box::new(condition),
+                            loc,
+                        })))
+                    }
+                    (op, Parsed::Expression(expr)) => Ok(Parsed::Expression(
+                        datamodel::Expression::Unary(datamodel::UnaryExpression {
+                            operator: if op == "!" {
+                                datamodel::UnaryOperator::Not
+                            } else {
+                                datamodel::UnaryOperator::Negate
+                            },
+                            argument: Box::new(expr),
+                            loc,
+                        }),
+                    )),
+                    _ => Err(error("Expected a data expression".to_string(), loc)),
+                }
+            }
+            None => self.parse_primary(),
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Parsed, ConversionError> {
+        let left = self.parse_additive()?;
+        match self.peek_operator(&["==", "!=", "<=", ">=", "<", ">"]) {
+            Some(op) => {
+                self.position += 1;
+                let right = self.parse_additive()?;
+                let operator = match op {
+                    "==" => datamodel::BinaryOperator::Eq,
+                    "!=" => datamodel::BinaryOperator::Ne,
+                    "<=" => datamodel::BinaryOperator::Le,
+                    ">=" => datamodel::BinaryOperator::Ge,
+                    "<" => datamodel::BinaryOperator::Lt,
+                    _ => datamodel::BinaryOperator::Gt,
+                };
+                self.binary(operator, left, right)
+            }
+            None => Ok(left),
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Parsed, ConversionError> {
+        let mut left = self.parse_multiplicative()?;
+        while let Some(op) = self.peek_operator(&["+", "-"]) {
+            self.position += 1;
+            let right = self.parse_multiplicative()?;
+            let operator = if op == "+" {
+                datamodel::BinaryOperator::Add
+            } else {
+                datamodel::BinaryOperator::Sub
+            };
+            left = self.binary(operator, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Parsed, ConversionError> {
+        let mut left = self.parse_unary()?;
+        while let Some(op) = self.peek_operator(&["*", "/", "%"]) {
+            self.position += 1;
+            let right = self.parse_unary()?;
+            let operator = match op {
+                "*" => datamodel::BinaryOperator::Mul,
+                "/" => datamodel::BinaryOperator::Div,
+                _ => datamodel::BinaryOperator::Rem,
+            };
+            left = self.binary(operator, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn binary(
+        &self,
+        operator: datamodel::BinaryOperator,
+        left: Parsed,
+        right: Parsed,
+    ) -> Result<Parsed, ConversionError> {
+        match (left, right) {
+            (Parsed::Expression(left), Parsed::Expression(right)) => Ok(Parsed::Expression(
+                datamodel::Expression::Binary(datamodel::BinaryExpression {
+                    operator,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                    loc: self.loc,
+                }),
+            )),
+            _ => Err(error(
+                format!("Expected data expressions around {:?}", operator),
+                self.loc,
+            )),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Parsed, ConversionError> {
+        let loc = self.loc;
+        let token = match self.tokens.get(self.position) {
+            Some(token) => token.clone(),
+            None => return Err(error("Unexpected end of expression".to_string(), loc)),
+        };
+        self.position += 1;
+
+        Ok(match token {
+            Token::Integer(value) => {
+                if value > i64::MAX as u64 {
+                    return Err(error(format!("Invalid integer: {}", value), loc));
+                }
+                Parsed::Expression(datamodel::Expression::Integer(datamodel::IntegerLiteral {
+                    value: value as i64,
+                    loc,
+                }))
+            }
+            Token::String(value) => {
+                Parsed::Expression(datamodel::Expression::String(datamodel::StringLiteral {
+                    value,
+                    loc,
+                }))
+            }
+            Token::Enum(value) => {
+                Parsed::Expression(datamodel::Expression::Enum(datamodel::EnumLiteral {
+                    value,
+                    loc,
+                }))
+            }
+            Token::Predicate(id) => Parsed::Condition(Condition::Predicate(Predicate { id, loc })),
+            Token::Ident(ref ident) if ident == "true" || ident == "false" => {
+                Parsed::Expression(datamodel::Expression::Boolean(datamodel::BooleanLiteral {
+                    value: ident == "true",
+                    loc,
+                }))
+            }
+            Token::Ident(ref ident)
+                if ident == "In" && self.tokens.get(self.position) == Some(&Token::Open) =>
+            {
+                let state = match self.tokens.get(self.position + 1) {
+                    Some(Token::String(state)) | Some(Token::Enum(state)) => state.clone(),
+                    _ => return Err(error("Expected a state id in In(...)".to_string(), loc)),
+                };
+                if self.tokens.get(self.position + 2) != Some(&Token::Close) {
+                    return Err(error("Expected ) after In(...)".to_string(), loc));
+                }
+                self.position += 3;
+                Parsed::Condition(Condition::In(In { state, loc }))
+            }
+            Token::Ident(id) => Parsed::Expression(datamodel::Expression::Variable(
+                datamodel::VariableReference { id, loc },
+            )),
+            Token::Open => {
+                let parsed = self.parse_or()?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => self.position += 1,
+                    _ => return Err(error("Expected )".to_string(), loc)),
+                }
+                parsed
+            }
+            token => return Err(self.unexpected(&token)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::core::Core;
+    use ast::microstep::Microstep;
+    use ast::statechart::to_scxml::to_scxml;
+    use runtime::interpreter::{Host, Interpreter};
+
+    fn child(nodes: &[Node], idx: usize) -> &State {
+        match nodes[idx] {
+            Node::State(ref state) => state,
+            ref other => panic!("expected a state, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_elements_are_located_at_their_start_tags() {
+        let source = "<scxml>\n  <state id=\"a\">\n\n    <state id=\"b\"/>\n  </state>\n</scxml>\n";
+        let statechart = from_scxml(source).unwrap();
+
+        let a = child(&statechart.children, 0);
+        assert_eq!((a.loc.start.line, a.loc.start.column), (2, 2));
+        assert_eq!((a.loc.end.line, a.loc.end.column), (5, 2));
+
+        let b = child(&a.children, 0);
+        assert_eq!(b.id.as_ref().unwrap(), "b");
+        assert_eq!((b.loc.start.line, b.loc.start.column), (4, 4));
+    }
+
+    #[test]
+    fn parse_errors_are_located_where_they_occur() {
+        let errors = from_scxml("<scxml>\n  <state>\n  </final>\n</scxml>").unwrap_err();
+        assert_eq!(errors[0].loc.start.line, 3);
+    }
+
+    #[test]
+    fn literals_round_trip() {
+        let literals = [
+            "plain",
+            "quote \" and 'apostrophe'",
+            "back\\slash \\n",
+            "tab\tline\nreturn\r",
+            "ünïcode \u{7f}",
+        ];
+        for literal in &literals {
+            let condition = format!(
+                "x == {} && y != {} && In({})",
+                datamodel::quote(literal, '"'),
+                datamodel::quote(literal, '\''),
+                datamodel::quote(literal, '"')
+            );
+            let source = format!(
+                "<scxml><state id=\"{}\"><transition cond=\"{}\"/></state></scxml>",
+                to_scxml_attr(literal),
+                to_scxml_attr(&condition)
+            );
+            let statechart = from_scxml(&source).unwrap();
+            let written = to_scxml(&statechart);
+            // conforming parsers turn raw whitespace in attribute values into spaces
+            assert!(!written.contains(&['\t', '\r'][..]), "{:?}", written);
+            let reread = from_scxml(&written).unwrap();
+
+            let ids: Vec<_> = [&statechart, &reread]
+                .iter()
+                .map(|statechart| child(&statechart.children, 0).id.clone())
+                .collect();
+            assert_eq!(ids, vec![Some(literal.to_string()); 2]);
+
+            let conditions: Vec<Condition> = [&statechart, &reread]
+                .iter()
+                .map(
+                    |statechart| match child(&statechart.children, 0).children[0] {
+                        Node::Transition(ref transition) => transition.condition.clone().unwrap(),
+                        ref other => panic!("expected a transition, got {:?}", other),
+                    },
+                )
+                .collect();
+            for condition in &conditions {
+                let children = match condition {
+                    Condition::And(node) => &node.children,
+                    other => panic!("expected a conjunction, got {:?}", other),
+                };
+                let (string, enumeration) = match (&children[0], &children[1]) {
+                    (Condition::Expression(left), Condition::Expression(right)) => {
+                        (&left.expr, &right.expr)
+                    }
+                    other => panic!("expected comparisons, got {:?}", other),
+                };
+                match string {
+                    datamodel::Expression::Binary(node) => match node.right.as_ref() {
+                        datamodel::Expression::String(node) => assert_eq!(&node.value, literal),
+                        other => panic!("expected a string, got {:?}", other),
+                    },
+                    other => panic!("expected a comparison, got {:?}", other),
+                }
+                match enumeration {
+                    datamodel::Expression::Binary(node) => match node.right.as_ref() {
+                        datamodel::Expression::Enum(node) => assert_eq!(&node.value, literal),
+                        other => panic!("expected an enum, got {:?}", other),
+                    },
+                    other => panic!("expected a comparison, got {:?}", other),
+                }
+                match &children[2] {
+                    Condition::In(node) => assert_eq!(&node.state, literal),
+                    other => panic!("expected In, got {:?}", other),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn negation_round_trips() {
+        let reparse = |source: &str| {
+            let expr = parse_expression(source, Location::default()).unwrap();
+            let written = expr.to_string();
+            let reread = parse_expression(&written, Location::default()).unwrap();
+            assert_eq!(reread.to_string(), written);
+            written
+        };
+        assert_eq!(reparse("-a + b"), "-a + b");
+        assert_eq!(reparse("-(a + b)"), "-(a + b)");
+        assert_eq!(reparse("x - -1"), "x - -1");
+        assert_eq!(reparse("x + -1 * !y"), "x + (-1 * !y)");
+        assert_eq!(reparse("!x == y"), "!x == y");
+
+        let min = i64::MIN.to_string();
+        match parse_expression(&min, Location::default()).unwrap() {
+            datamodel::Expression::Integer(node) => assert_eq!(node.value, i64::MIN),
+            other => panic!("expected an integer, got {:?}", other),
+        }
+        assert_eq!(reparse(&format!("x - {}", min)), format!("x - {}", min));
+        assert!(parse_expression("9223372036854775808", Location::default()).is_err());
+    }
+
+    #[test]
+    fn if_conditions_may_name_states() {
+        struct Scripts(Vec<usize>);
+
+        impl Host for Scripts {
+            fn condition(&mut self, _id: usize) -> bool {
+                false
+            }
+
+            fn execute(&mut self, id: usize) {
+                self.0.push(id);
+            }
+        }
+
+        let source = r##"<scxml>
+            <parallel id="p">
+                <state id="a">
+                    <onevent event="0">
+                        <if cond="In('b2')">
+                            <script id="1"/>
+                        <elseif cond="In('b1') &amp;&amp; !#0"/>
+                            <script id="2"/>
+                        <else/>
+                            <script id="3"/>
+                        </if>
+                    </onevent>
+                </state>
+                <state id="b">
+                    <state id="b1"/>
+                    <state id="b2"/>
+                </state>
+            </parallel>
+        </scxml>"##;
+        let core: Result<Core, _> = from_scxml(source).unwrap().into();
+        let microstep: Result<Microstep, _> = core.unwrap().into();
+        let microstep = microstep.unwrap();
+        let mut interpreter = Interpreter::new(&microstep);
+        let mut scripts = Scripts(vec![]);
+        interpreter.start(&mut scripts).unwrap();
+        interpreter.dispatch(0, &mut scripts).unwrap();
+        assert_eq!(scripts.0, vec![2]);
+    }
+
+    #[test]
+    fn if_conditions_naming_missing_states_are_errors() {
+        let source = r#"<scxml><state id="a"><onentry>
+            <if cond="In('missing')"><script id="1"/></if>
+        </onentry></state></scxml>"#;
+        let core: Result<Core, _> = from_scxml(source).unwrap().into();
+        let errors = core.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Missing state: \"missing\"");
+        assert_eq!(errors[0].loc.start.line, 2);
+    }
+
+    fn to_scxml_attr(value: &str) -> String {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('"', "&quot;")
+            .replace('\n', "&#10;")
+            .replace('\t', "&#9;")
+            .replace('\r', "&#13;")
+    }
+}
@@ -0,0 +1,722 @@
+use ast::conversion_error::{ConversionError, Errors};
+use ast::datamodel::{self, Type, Variable};
+use ast::executable::{self, Executable};
+use ast::json_location::Locations;
+use ast::location::{Location, Point};
+use ast::statechart::*;
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+
+const SOURCE: &str = "statechart/ast/statechart/from_xstate";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Symbols {
+    #[serde(default)]
+    pub events: Vec<String>,
+
+    #[serde(default)]
+    pub actions: Vec<String>,
+
+    #[serde(default)]
+    pub guards: Vec<String>,
+}
+
+impl Symbols {
+    pub fn event(&mut self, name: &str) -> EventId {
+        intern(&mut self.events, name)
+    }
+
+    pub fn action(&mut self, name: &str) -> ExecutableId {
+        intern(&mut self.actions, name)
+    }
+
+    pub fn guard(&mut self, name: &str) -> ConditonId {
+        intern(&mut self.guards, name)
+    }
+
+    pub fn event_name(&self, id: EventId) -> String {
+        lookup(&self.events, id)
+    }
+
+    pub fn action_name(&self, id: ExecutableId) -> String {
+        lookup(&self.actions, id)
+    }
+
+    pub fn guard_name(&self, id: ConditonId) -> String {
+        lookup(&self.guards, id)
+    }
+}
+
+fn intern(names: &mut Vec<String>, name: &str) -> usize {
+    match names.iter().position(|n| n == name) {
+        Some(idx) => idx,
+        None => {
+            names.push(name.to_string());
+            names.len() - 1
+        }
+    }
+}
+
+fn lookup(names: &[String], id: usize) -> String {
+    names.get(id).cloned().unwrap_or_else(|| id.to_string())
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct XState {
+    pub id: Option<String>,
+    pub statechart: Statechart,
+    pub symbols: Symbols,
+}
+
+pub fn after_event(delay: usize, state: &str) -> String {
+    format!("xstate.after({})#{}", delay, state)
+}
+
+pub fn parse_after_event(name: &str) -> Option<usize> {
+    let rest = name.strip_prefix("xstate.after(")?;
+    rest[..rest.find(")#")?].parse().ok()
+}
+
+pub fn from_xstate(source: &str) -> Result<XState, Errors> {
+    let value: Value = serde_json::from_str(source).map_err(|err| {
+        // serde_json counts columns from 1
+        let point = Point {
+            line: err.line(),
+            column: err.column().saturating_sub(1),
+        };
+        vec![error(
+            err.to_string(),
+            Location {
+                start: point,
+                end: point,
+                source: None,
+            },
+        )]
+    })?;
+    let locations = Locations::new(source, &value);
+    let config = match value.as_object() {
+        Some(config) => config,
+        None => {
+            return Err(vec![error(
+                "Expected a machine config object".to_string(),
+                locations.get(&value),
+            )])
+        }
+    };
+
+    let id = config
+        .get("id")
+        .and_then(Value::as_str)
+        .map(|s| s.to_string());
+    let mut importer = Importer {
+        machine: id.clone().unwrap_or_else(|| "(machine)".to_string()),
+        locations,
+        ..Default::default()
+    };
+    importer.index(config, &[]);
+
+    let (initial, children) = importer.children(config, &[]);
+    let statechart = Statechart {
+        initital: initial,
+        datamodel: importer.context(config),
+        children,
+        loc: importer.loc(config),
+        ..Default::default()
+    };
+
+    if importer.errors.is_empty() {
+        Ok(XState {
+            id,
+            statechart,
+            symbols: importer.symbols,
+        })
+    } else {
+        Err(importer.errors)
+    }
+}
+
+fn error(message: String, loc: Location) -> ConversionError {
+    ConversionError {
+        message,
+        fatal: true,
+        source: SOURCE.to_string(),
+        loc,
+    }
+}
+
+#[derive(Default)]
+struct Importer {
+    machine: String,
+    symbols: Symbols,
+    paths: HashMap<Vec<String>, String>,
+    ids: HashMap<String, Vec<String>>,
+    locations: Locations,
+    errors: Errors,
+}
+
+impl Importer {
+    fn error(&mut self, message: String, loc: Location) {
+        self.errors.push(error(message, loc));
+    }
+
+    fn loc<T>(&self, node: &T) -> Location {
+        self.locations.get(node)
+    }
+
+    fn index(&mut self, config: &Map<String, Value>, path: &[String]) {
+        let default_id = if path.is_empty() {
+            self.machine.clone()
+        } else {
+            format!("{}.{}", self.machine, path.join("."))
+        };
+        self.ids.insert(default_id, path.to_vec());
+
+        if !path.is_empty() {
+            let id = match config.get("id").and_then(Value::as_str) {
+                Some(id) => {
+                    self.ids.insert(id.to_string(), path.to_vec());
+                    id.to_string()
+                }
+                None => path.join("."),
+            };
+            self.paths.insert(path.to_vec(), id);
+        }
+
+        for (key, child) in states(config) {
+            let mut child_path = path.to_vec();
+            child_path.push(key.clone());
+            self.index(child, &child_path);
+        }
+    }
+
+    fn resolve(&mut self, source: &[String], target: &str, loc: Location) -> Option<String> {
+        let path = if let Some(reference) = target.strip_prefix('#') {
+            self.resolve_reference(reference)
+        } else if let Some(relative) = target.strip_prefix('.') {
+            Some(join(source, relative))
+        } else {
+            Some(join(&source[..source.len().saturating_sub(1)], target))
+        };
+
+        match path.and_then(|path| self.paths.get(&path)) {
+            Some(id) => Some(id.clone()),
+            None => {
+                self.error(format!("Missing target: {:?}", target), loc);
+                None
+            }
+        }
+    }
+
+    fn resolve_reference(&self, reference: &str) -> Option<Vec<String>> {
+        if let Some(path) = self.ids.get(reference) {
+            return Some(path.clone());
+        }
+        // `#id.child.path` references the longest matching id first
+        reference
+            .rmatch_indices('.')
+            .filter_map(|(i, _)| {
+                self.ids
+                    .get(&reference[..i])
+                    .map(|path| join(path, &reference[i + 1..]))
+            })
+            .next()
+    }
+
+    fn children(
+        &mut self,
+        config: &Map<String, Value>,
+        path: &[String],
+    ) -> (Vec<String>, Vec<Node>) {
+        let mut children = vec![];
+        let id = self.paths.get(path).cloned().unwrap_or_default();
+
+        let entry = config.get("entry").or_else(|| config.get("onEntry"));
+        let exit = config.get("exit").or_else(|| config.get("onExit"));
+        let entry_loc = entry.map_or_else(|| self.loc(config), |entry| self.loc(entry));
+        let exit_loc = exit.map_or_else(|| self.loc(config), |exit| self.loc(exit));
+        let mut on_entry = self.actions(entry);
+        let mut on_exit = self.actions(exit);
+
+        if let Some(after) = config.get("after") {
+            for (delay, transition) in self.after(after) {
+                let name = after_event(delay, &id);
+                let event = self.symbols.event(&name);
+                let loc = self.loc(transition);
+                on_entry.push(Executable::Send(executable::Send {
+                    event,
+                    id: Some(name.clone()),
+                    delay,
+                    loc,
+                    ..Default::default()
+                }));
+                on_exit.push(Executable::Cancel(executable::Cancel { id: name, loc }));
+                children.extend(self.transitions(transition, Some(event), path));
+            }
+        }
+
+        if !on_entry.is_empty() {
+            children.insert(
+                0,
+                Node::OnEntry(OnEntry {
+                    children: on_entry,
+                    loc: entry_loc,
+                }),
+            );
+        }
+        if !on_exit.is_empty() {
+            children.push(Node::OnExit(OnExit {
+                children: on_exit,
+                loc: exit_loc,
+            }));
+        }
+
+        match config.get("on") {
+            Some(Value::Object(on)) => {
+                for (name, transition) in on {
+                    let event = match name.as_str() {
+                        "" => None,
+                        "*" => {
+                            let loc = self.loc(transition);
+                            self.error("Unsupported wildcard event: \"*\"".to_string(), loc);
+                            continue;
+                        }
+                        name => Some(self.symbols.event(name)),
+                    };
+                    children.extend(self.transitions(transition, event, path));
+                }
+            }
+            Some(Value::Array(on)) => {
+                for transition in on {
+                    let event = transition
+                        .get("event")
+                        .and_then(Value::as_str)
+                        .map(|name| self.symbols.event(name));
+                    children.extend(self.transitions(transition, event, path));
+                }
+            }
+            Some(on) => {
+                let loc = self.loc(on);
+                self.error(format!("Invalid \"on\" in {:?}", id), loc)
+            }
+            None => (),
+        }
+
+        if let Some(always) = config.get("always") {
+            children.extend(self.transitions(always, None, path));
+        }
+
+        let initial = config.get("initial").and_then(Value::as_str);
+        let mut initial_ids = vec![];
+        for (key, child) in self::states(config) {
+            let mut child_path = path.to_vec();
+            child_path.push(key.clone());
+            if Some(key.as_str()) == initial {
+                initial_ids.push(self.paths[&child_path].clone());
+            }
+            children.push(self.state(child, &child_path));
+        }
+        if let (Some(initial), true) = (initial, initial_ids.is_empty()) {
+            let loc = self.loc(&config["initial"]);
+            self.error(format!("Missing initial state: {:?}", initial), loc);
+        }
+
+        (initial_ids, children)
+    }
+
+    fn state(&mut self, config: &Map<String, Value>, path: &[String]) -> Node {
+        let id = Some(self.paths[path].clone());
+        let loc = self.loc(config);
+        match config.get("type").and_then(Value::as_str) {
+            Some("parallel") => Node::Parallel(Parallel {
+                id,
+                children: self.children(config, path).1,
+                loc,
+            }),
+            Some("final") => Node::Final(Final {
+                id,
+                children: self.children(config, path).1,
+                loc,
+            }),
+            Some("history") => {
+                let children = match config.get("target") {
+                    Some(target) => self.transitions(target, None, path),
+                    None => vec![],
+                };
+                Node::History(History {
+                    id,
+                    t: match config.get("history").and_then(Value::as_str) {
+                        Some("deep") => HistoryType::Deep,
+                        _ => HistoryType::Shallow,
+                    },
+                    children,
+                    loc,
+                })
+            }
+            Some("atomic") | Some("compound") | None => {
+                let (initial, children) = self.children(config, path);
+                Node::State(State {
+                    id,
+                    initial,
+                    children,
+                    loc,
+                    ..Default::default()
+                })
+            }
+            Some(t) => {
+                let type_loc = self.loc(&config["type"]);
+                self.error(format!("Invalid state type: {:?}", t), type_loc);
+                Node::State(State {
+                    id,
+                    loc,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    fn after<'a>(&mut self, after: &'a Value) -> Vec<(usize, &'a Value)> {
+        match after {
+            Value::Object(after) => after
+                .iter()
+                .filter_map(|(delay, transition)| match delay.parse() {
+                    Ok(delay) => Some((delay, transition)),
+                    Err(_) => {
+                        let loc = self.loc(transition);
+                        self.error(format!("Unsupported delay: {:?}", delay), loc);
+                        None
+                    }
+                })
+                .collect(),
+            Value::Array(after) => after
+                .iter()
+                .filter_map(
+                    |transition| match transition.get("delay").and_then(Value::as_u64) {
+                        Some(delay) => Some((delay as usize, transition)),
+                        None => {
+                            let loc = self.loc(transition);
+                            self.error("Unsupported delay in \"after\"".to_string(), loc);
+                            None
+                        }
+                    },
+                )
+                .collect(),
+            _ => {
+                let loc = self.loc(after);
+                self.error("Invalid \"after\"".to_string(), loc);
+                vec![]
+            }
+        }
+    }
+
+    fn transitions(
+        &mut self,
+        config: &Value,
+        event: Option<EventId>,
+        path: &[String],
+    ) -> Vec<Node> {
+        let loc = self.loc(config);
+        match config {
+            Value::Array(configs) => configs
+                .iter()
+                .flat_map(|config| self.transitions(config, event, path))
+                .collect(),
+            Value::String(target) => {
+                vec![self.transition(&Map::new(), &[target], event, path, loc)]
+            }
+            Value::Object(config) => {
+                let targets = match config.get("target") {
+                    Some(Value::String(target)) => vec![target.as_str()],
+                    Some(Value::Array(targets)) => {
+                        targets.iter().filter_map(Value::as_str).collect()
+                    }
+                    _ => vec![],
+                };
+                vec![self.transition(config, &targets, event, path, loc)]
+            }
+            Value::Null => vec![],
+            _ => {
+                self.error("Invalid transition".to_string(), loc);
+                vec![]
+            }
+        }
+    }
+
+    fn transition(
+        &mut self,
+        config: &Map<String, Value>,
+        targets: &[&str],
+        event: Option<EventId>,
+        path: &[String],
+        loc: Location,
+    ) -> Node {
+        // XState v4 treats transitions to child states as internal by default
+        let is_internal = match config.get("internal").and_then(Value::as_bool) {
+            Some(is_internal) => is_internal,
+            None => !targets.is_empty() && targets.iter().all(|t| t.starts_with('.')),
+        };
+
+        Node::Transition(Transition {
+            event,
+            targets: targets
+                .iter()
+                .filter_map(|target| self.resolve(path, target, loc))
+                .collect(),
+            t: if is_internal {
+                TransitionType::Internal
+            } else {
+                TransitionType::External
+            },
+            condition: self.condition(config, loc),
+            children: self.actions(config.get("actions")),
+            loc,
+        })
+    }
+
+    fn condition(&mut self, config: &Map<String, Value>, loc: Location) -> Option<Condition> {
+        let mut conditions = vec![];
+
+        if let Some(guard) = config.get("cond").or_else(|| config.get("guard")) {
+            let loc = self.loc(guard);
+            match name(guard) {
+                Some(name) => conditions.push(Condition::Predicate(Predicate {
+                    id: self.symbols.guard(name),
+                    loc,
+                })),
+                None => self.error("Invalid guard".to_string(), loc),
+            }
+        }
+
+        if let Some(state) = config.get("in") {
+            let loc = self.loc(state);
+            let path = match state.as_str() {
+                Some(state) if state.starts_with('#') => self.resolve_reference(&state[1..]),
+                Some(state) => Some(join(&[], state)),
+                None => None,
+            };
+            match path.and_then(|path| self.paths.get(&path)) {
+                Some(id) => conditions.push(Condition::In(In {
+                    state: id.clone(),
+                    loc,
+                })),
+                None => self.error(format!("Missing state: {}", state), loc),
+            }
+        }
+
+        match conditions.len() {
+            0 => None,
+            1 => conditions.pop(),
+            _ => Some(Condition::And(Conditions {
+                children: conditions,
+                loc,
+            })),
+        }
+    }
+
+    fn actions(&mut self, config: Option<&Value>) -> Vec<Executable> {
+        match config {
+            Some(Value::Array(actions)) => actions
+                .iter()
+                .filter_map(|action| self.action(action))
+                .collect(),
+            Some(action) => self.action(action).into_iter().collect(),
+            None => vec![],
+        }
+    }
+
+    fn action(&mut self, config: &Value) -> Option<Executable> {
+        let loc = self.loc(config);
+        let t = match name(config) {
+            Some(t) => t,
+            None => {
+                self.error("Invalid action".to_string(), loc);
+                return None;
+            }
+        };
+        let event = config.get("event").and_then(name);
+
+        Some(match (t, event) {
+            ("xstate.raise", Some(event)) => Executable::Raise(executable::Raise {
+                event: self.symbols.event(event),
+                loc,
+            }),
+            ("xstate.send", Some(event)) => Executable::Send(executable::Send {
+                event: self.symbols.event(event),
+                target: config
+                    .get("to")
+                    .and_then(Value::as_str)
+                    .map(|s| s.to_string()),
+                id: config
+                    .get("id")
+                    .and_then(Value::as_str)
+                    .map(|s| s.to_string()),
+                delay: config.get("delay").and_then(Value::as_u64).unwrap_or(0) as usize,
+                loc,
+            }),
+            ("xstate.cancel", _) => Executable::Cancel(executable::Cancel {
+                id: config
+                    .get("sendId")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                loc,
+            }),
+            (t, _) => Executable::Script(executable::Script {
+                id: self.symbols.action(t),
+                loc,
+            }),
+        })
+    }
+
+    fn context(&mut self, config: &Map<String, Value>) -> Vec<Variable> {
+        let context = match config.get("context") {
+            Some(Value::Object(context)) => context,
+            _ => return vec![],
+        };
+
+        // only scalar context values have a datamodel representation
+        context
+            .iter()
+            .filter_map(|(id, value)| {
+                let loc = self.loc(value);
+                let (t, init) = match value {
+                    Value::Bool(value) => (
+                        Type::Boolean,
+                        datamodel::Expression::Boolean(datamodel::BooleanLiteral {
+                            value: *value,
+                            loc,
+                        }),
+                    ),
+                    Value::Number(value) => (
+                        Type::Integer,
+                        datamodel::Expression::Integer(datamodel::IntegerLiteral {
+                            value: value.as_i64()?,
+                            loc,
+                        }),
+                    ),
+                    Value::String(value) => (
+                        Type::String,
+                        datamodel::Expression::String(datamodel::StringLiteral {
+                            value: value.clone(),
+                            loc,
+                        }),
+                    ),
+                    _ => return None,
+                };
+                Some(Variable {
+                    id: id.clone(),
+                    t,
+                    init: Some(init),
+                    loc,
+                })
+            })
+            .collect()
+    }
+}
+
+fn name(config: &Value) -> Option<&str> {
+    match config {
+        Value::String(name) => Some(name),
+        Value::Object(config) => config.get("type").and_then(Value::as_str),
+        _ => None,
+    }
+}
+
+fn states(config: &Map<String, Value>) -> Vec<(&String, &Map<String, Value>)> {
+    match config.get("states") {
+        Some(Value::Object(states)) => states
+            .iter()
+            .filter_map(|(key, state)| state.as_object().map(|state| (key, state)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn join(base: &[String], relative: &str) -> Vec<String> {
+    let mut path = base.to_vec();
+    path.extend(relative.split('.').map(|key| key.to_string()));
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn child_ids(children: &[Node]) -> Vec<String> {
+        children
+            .iter()
+            .filter_map(|node| match node {
+                Node::State(node) => node.id.clone(),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn states_keep_their_source_order() {
+        let source = r#"{
+  "id": "m",
+  "initial": "b",
+  "states": {
+    "a": {},
+    "b": { "initial": "b2", "states": { "b1": {}, "b2": {} } }
+  }
+}"#;
+        let statechart = from_xstate(source).unwrap().statechart;
+
+        assert_eq!(child_ids(&statechart.children), vec!["a", "b"]);
+        assert_eq!(statechart.initital, vec!["b"]);
+        match &statechart.children[1] {
+            Node::State(b) => {
+                assert_eq!(child_ids(&b.children), vec!["b.b1", "b.b2"]);
+                assert_eq!(b.initial, vec!["b.b2"]);
+            }
+            node => panic!("{:?}", node),
+        }
+    }
+
+    #[test]
+    fn nodes_are_located_in_the_source() {
+        let source = r#"{
+  "id": "m",
+  "states": {
+    "a": {
+      "on": { "go": "b" }
+    },
+    "b": {}
+  }
+}"#;
+        let statechart = from_xstate(source).unwrap().statechart;
+
+        let a = match &statechart.children[0] {
+            Node::State(a) => a,
+            node => panic!("{:?}", node),
+        };
+        assert_eq!((a.loc.start.line, a.loc.start.column), (4, 4));
+        assert_eq!((a.loc.end.line, a.loc.end.column), (6, 5));
+        match &a.children[0] {
+            Node::Transition(transition) => {
+                let start = transition.loc.start;
+                assert_eq!((start.line, start.column), (5, 14));
+            }
+            node => panic!("{:?}", node),
+        }
+    }
+
+    #[test]
+    fn errors_are_located_where_they_occur() {
+        let source = r#"{
+  "id": "m",
+  "states": {
+    "a": { "on": { "go": "missing" } }
+  }
+}"#;
+        let errors = from_xstate(source).unwrap_err();
+        assert_eq!(errors[0].message, "Missing target: \"missing\"");
+        let start = errors[0].loc.start;
+        assert_eq!((start.line, start.column), (4, 19));
+
+        let errors = from_xstate("{\n  \"id\": }").unwrap_err();
+        let start = errors[0].loc.start;
+        assert_eq!((start.line, start.column), (2, 8));
+    }
+}
@@ -1,7 +1,12 @@
 use ast::conversion_error::{ConversionError, Errors};
 use ast::core;
+use ast::core::bitset::BitSet;
+use ast::datamodel;
+use ast::executable::{self, Executable};
+use ast::location::Location;
 use ast::statechart;
 use std::collections::HashMap;
+use std::ops::Range;
 
 impl Into<Result<core::Core, Errors>> for statechart::Statechart {
     fn into(self) -> Result<core::Core, Errors> {
@@ -10,19 +15,25 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
         let mut ancestors = vec![];
         let mut transitions = vec![];
         let mut targets = HashMap::new();
+        let mut conditions = HashMap::new();
+        // executables are resolved like conditions once every state id is known
+        let mut on_transition = vec![];
+        let mut on_state = vec![];
         let mut state_ids = HashMap::new();
         let mut binding = statechart::Binding::Late;
         let root_loc = self.loc;
 
-        for event in statechart::Node::Statechart(self).iter() {
+        let root = statechart::Node::Statechart(self);
+        for event in root.iter() {
             match event {
-                statechart::IteratorEvent::Enter(node) => match node.as_ref() {
+                statechart::IteratorEvent::Enter(node) => match node {
                     statechart::Node::Statechart(node) => {
                         let idx = states.len();
                         binding = node.binding;
                         states.push(core::State {
                             idx,
                             t: core::StateType::Compound,
+                            datamodel: node.datamodel.clone(),
                             loc: node.loc,
                             ..Default::default()
                         });
@@ -40,6 +51,14 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                             loc: node.loc,
                             ..Default::default()
                         });
+                        match binding {
+                            statechart::Binding::Early => {
+                                states[0].datamodel.append(&mut node.datamodel.clone());
+                            }
+                            statechart::Binding::Late => {
+                                states[idx].datamodel.append(&mut node.datamodel.clone());
+                            }
+                        }
                         ancestors.push(idx);
                     }
                     statechart::Node::Parallel(node) => {
@@ -61,12 +80,22 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                         let idx = transitions.len();
                         states[source].transitions.push(idx);
                         targets.insert(idx, node.targets.clone());
+                        if let Some(ref condition) = node.condition {
+                            conditions.insert(idx, condition.clone());
+                        }
+                        on_transition.push((idx, &node.children));
                         let transition = core::Transition {
                             idx,
-                            t: core::TransitionType::External,
+                            t: match node.t {
+                                statechart::TransitionType::Internal => {
+                                    core::TransitionType::Internal
+                                }
+                                statechart::TransitionType::External => {
+                                    core::TransitionType::External
+                                }
+                            },
                             source,
                             event: node.event,
-                            condition: node.condition,
                             loc: node.loc,
                             ..Default::default()
                         };
@@ -77,12 +106,15 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                         let idx = transitions.len();
                         let event = node.event;
                         states[source].transitions.push(idx);
+                        if let Some(ref condition) = node.condition {
+                            conditions.insert(idx, condition.clone());
+                        }
+                        on_transition.push((idx, &node.children));
                         let transition = core::Transition {
                             idx,
                             t: core::TransitionType::OnEvent,
                             source,
                             event,
-                            condition: node.condition,
                             loc: node.loc,
                             ..Default::default()
                         };
@@ -116,20 +148,20 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                     }
                     statechart::Node::OnInit(node) => match binding {
                         statechart::Binding::Early => {
-                            states[0].on_init.append(&mut node.children.clone());
+                            on_state.push((0, Hook::Init, &node.children));
                         }
                         statechart::Binding::Late => {
                             let &idx = ancestors.last().unwrap();
-                            states[idx].on_init.append(&mut node.children.clone());
+                            on_state.push((idx, Hook::Init, &node.children));
                         }
                     },
                     statechart::Node::OnEntry(node) => {
                         let &idx = ancestors.last().unwrap();
-                        states[idx].on_enter.append(&mut node.children.clone());
+                        on_state.push((idx, Hook::Enter, &node.children));
                     }
                     statechart::Node::OnExit(node) => {
                         let &idx = ancestors.last().unwrap();
-                        states[idx].on_exit.append(&mut node.children.clone());
+                        on_state.push((idx, Hook::Exit, &node.children));
                     }
                     statechart::Node::History(node) => {
                         let idx = states.len();
@@ -153,7 +185,7 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                         states[idx].invocations.push(node.id);
                     }
                 },
-                statechart::IteratorEvent::Exit(node) => match node.as_ref() {
+                statechart::IteratorEvent::Exit(node) => match node {
                     statechart::Node::Statechart(_)
                     | statechart::Node::State(_)
                     | statechart::Node::Parallel(_)
@@ -186,8 +218,13 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                                 states[idx].initial = states[idx].children.clone();
                             }
                             core::StateType::Compound => {
-                                // TODO
-                                states[idx].initial = vec![*states[idx].children.first().unwrap()];
+                                let initial = match node {
+                                    statechart::Node::Statechart(node) => &node.initital,
+                                    statechart::Node::State(node) => &node.initial,
+                                    _ => unreachable!(),
+                                };
+                                states[idx].initial =
+                                    resolve_initial(&states[idx], initial, &state_ids, &mut errors);
                             }
                             core::StateType::HistoryShallow => {
                                 // TODO filter history children
@@ -219,6 +256,16 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
             }
         }
 
+        for (idx, hook, children) in on_state {
+            let mut children = resolve_executables(children, &state_ids, &mut errors);
+            match hook {
+                Hook::Init => states[idx].on_init.append(&mut children),
+                Hook::Enter => states[idx].on_enter.append(&mut children),
+                Hook::Exit => states[idx].on_exit.append(&mut children),
+            }
+        }
+
+        let tree = StateTree::new(&states);
         for (transition_id, state_targets) in targets {
             let mut transition = &mut transitions[transition_id];
             for state_target in state_targets {
@@ -233,11 +280,21 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
                     });
                 }
             }
-            transition.exits = get_exit_set(&transition, &states);
-            transition.exits.sort();
+            transition.exits = tree.exit_set(transition);
+        }
+
+        for (transition_id, condition) in conditions {
+            transitions[transition_id].condition =
+                Some(resolve_condition(&condition, &state_ids, &mut errors));
+        }
+        for (transition_id, children) in on_transition {
+            transitions[transition_id].on_transition =
+                resolve_executables(children, &state_ids, &mut errors);
         }
 
-        compute_conflicts(&mut transitions, &states);
+        check_datamodel(&states, &transitions, &mut errors);
+
+        compute_conflicts(&mut transitions, &tree);
 
         if !errors.is_empty() {
             Err(errors)
@@ -251,115 +308,895 @@ impl Into<Result<core::Core, Errors>> for statechart::Statechart {
     }
 }
 
-fn compute_conflicts(transitions: &mut [core::Transition], states: &[core::State]) {
-    let cloned: Vec<core::Transition> = transitions.into();
-    for mut transition in transitions {
-        transition.conflicts = get_conflicts(&transition, &cloned, &states);
-        transition.conflicts.sort();
+// the child named by the `initial` attribute, or the first child without one
+fn resolve_initial(
+    state: &core::State,
+    initial: &[String],
+    state_ids: &HashMap<String, core::StateId>,
+    errors: &mut Errors,
+) -> Vec<core::StateId> {
+    let first = vec![state.children[0]];
+    let id = match initial {
+        [] => return first,
+        [id] => id,
+        _ => {
+            errors.push(ConversionError {
+                message: format!("Expected one initial state, found {:?}", initial),
+                fatal: true,
+                source: "statechart/ast/statechart/to_core".to_string(),
+                loc: state.loc,
+            });
+            return first;
+        }
+    };
+    match state_ids.get(id) {
+        Some(&child) if state.children.contains(&child) => vec![child],
+        _ => {
+            errors.push(ConversionError {
+                message: format!("Initial state {:?} is not a child state", id),
+                fatal: true,
+                source: "statechart/ast/statechart/to_core".to_string(),
+                loc: state.loc,
+            });
+            first
+        }
     }
 }
 
-fn get_conflicts(
-    transition: &core::Transition,
-    transitions: &[core::Transition],
-    states: &[core::State],
-) -> Vec<core::TransitionId> {
-    transitions
+fn resolve_condition(
+    condition: &statechart::Condition,
+    state_ids: &HashMap<String, core::StateId>,
+    errors: &mut Errors,
+) -> core::Condition {
+    match condition {
+        statechart::Condition::Predicate(node) => core::Condition::Predicate(core::Predicate {
+            id: node.id,
+            loc: node.loc,
+        }),
+        statechart::Condition::In(node) => {
+            let state = match state_ids.get(&node.state) {
+                Some(idx) => *idx,
+                None => {
+                    errors.push(ConversionError {
+                        message: format!("Missing state: {:?}", node.state),
+                        fatal: true,
+                        source: "statechart/ast/statechart/to_core".to_string(),
+                        loc: node.loc,
+                    });
+                    0
+                }
+            };
+            core::Condition::In(core::In {
+                state,
+                loc: node.loc,
+            })
+        }
+        statechart::Condition::And(node) => {
+            core::Condition::And(resolve_conditions(node, state_ids, errors))
+        }
+        statechart::Condition::Or(node) => {
+            core::Condition::Or(resolve_conditions(node, state_ids, errors))
+        }
+        statechart::Condition::Not(node) => core::Condition::Not(core::Not {
+            condition: Box::new(resolve_condition(&node.condition, state_ids, errors)),
+            loc: node.loc,
+        }),
+        statechart::Condition::Expression(node) => {
+            core::Condition::Expression(core::ExpressionCondition {
+                expr: node.expr.clone(),
+                loc: node.loc,
+            })
+        }
+    }
+}
+
+enum Hook {
+    Init,
+    Enter,
+    Exit,
+}
+
+fn resolve_executables(
+    executables: &[Executable],
+    state_ids: &HashMap<String, core::StateId>,
+    errors: &mut Errors,
+) -> Vec<core::Executable> {
+    executables
         .iter()
-        .filter(|t2| t2.idx != transition.idx)
-        .filter(|t2| has_conflict(transition, t2, states))
-        .map(|t2| t2.idx)
+        .map(|executable| resolve_executable(executable, state_ids, errors))
         .collect()
 }
 
-fn has_conflict(t1: &core::Transition, t2: &core::Transition, states: &[core::State]) -> bool {
-    let s1 = get_transition_source(t1, states);
-    let s2 = get_transition_source(t2, states);
+// the same executable with the conditions of `if` and `elseif` naming states by index
+fn resolve_executable(
+    executable: &Executable,
+    state_ids: &HashMap<String, core::StateId>,
+    errors: &mut Errors,
+) -> core::Executable {
+    match executable {
+        Executable::Script(node) => core::Executable::Script(*node),
+        Executable::Raise(node) => core::Executable::Raise(*node),
+        Executable::Send(node) => core::Executable::Send(node.clone()),
+        Executable::Assign(node) => core::Executable::Assign(node.clone()),
+        Executable::Log(node) => core::Executable::Log(node.clone()),
+        Executable::If(node) => core::Executable::If(core::If {
+            condition: resolve_condition(&node.condition, state_ids, errors),
+            children: resolve_executables(&node.children, state_ids, errors),
+            else_if: node
+                .else_if
+                .iter()
+                .map(|else_if| core::ElseIf {
+                    condition: resolve_condition(&else_if.condition, state_ids, errors),
+                    children: resolve_executables(&else_if.children, state_ids, errors),
+                    loc: else_if.loc,
+                })
+                .collect(),
+            otherwise: resolve_executables(&node.otherwise, state_ids, errors),
+            loc: node.loc,
+        }),
+        Executable::Foreach(node) => core::Executable::Foreach(executable::Foreach {
+            array: node.array,
+            item: node.item.clone(),
+            index: node.index.clone(),
+            children: resolve_executables(&node.children, state_ids, errors),
+            loc: node.loc,
+        }),
+        Executable::Cancel(node) => core::Executable::Cancel(node.clone()),
+    }
+}
+
+fn resolve_conditions(
+    conditions: &statechart::Conditions,
+    state_ids: &HashMap<String, core::StateId>,
+    errors: &mut Errors,
+) -> core::Conditions {
+    core::Conditions {
+        children: conditions
+            .children
+            .iter()
+            .map(|condition| resolve_condition(condition, state_ids, errors))
+            .collect(),
+        loc: conditions.loc,
+    }
+}
+
+fn check_datamodel(states: &[core::State], transitions: &[core::Transition], errors: &mut Errors) {
+    let mut types = HashMap::new();
+    for state in states {
+        for variable in &state.datamodel {
+            if types.contains_key(&variable.id) {
+                errors.push(datamodel_error(
+                    format!("Duplicate variable: {:?}", variable.id),
+                    variable.loc,
+                ));
+            } else {
+                types.insert(variable.id.clone(), variable.t.clone());
+            }
+        }
+    }
+
+    for state in states {
+        for variable in &state.datamodel {
+            if let Some(ref init) = variable.init {
+                check_assignment(&variable.id, init, &types, errors);
+            }
+        }
+        check_executables(&state.on_init, &types, errors);
+        check_executables(&state.on_enter, &types, errors);
+        check_executables(&state.on_exit, &types, errors);
+    }
+
+    for transition in transitions {
+        if let Some(ref condition) = transition.condition {
+            check_condition(condition, &types, errors);
+        }
+        check_executables(&transition.on_transition, &types, errors);
+    }
+}
+
+fn check_condition(
+    condition: &core::Condition,
+    types: &HashMap<datamodel::VariableId, datamodel::Type>,
+    errors: &mut Errors,
+) {
+    match condition {
+        core::Condition::And(node) | core::Condition::Or(node) => {
+            for child in &node.children {
+                check_condition(child, types, errors);
+            }
+        }
+        core::Condition::Not(node) => check_condition(&node.condition, types, errors),
+        core::Condition::Expression(node) => match node.expr.type_of(types) {
+            Ok(datamodel::Type::Boolean) => (),
+            Ok(t) => errors.push(datamodel_error(
+                format!("Expected a boolean condition, found {:?}", t),
+                node.loc,
+            )),
+            Err(err) => errors.push(datamodel_error(err.message, err.loc)),
+        },
+        _ => (),
+    }
+}
+
+fn check_executables(
+    executables: &[core::Executable],
+    types: &HashMap<datamodel::VariableId, datamodel::Type>,
+    errors: &mut Errors,
+) {
+    for executable in executables {
+        match executable {
+            core::Executable::Assign(node) => {
+                check_assignment(&node.location, &node.expr, types, errors)
+            }
+            core::Executable::Log(node) => {
+                if let Some(Err(err)) = node.expr.as_ref().map(|expr| expr.type_of(types)) {
+                    errors.push(datamodel_error(err.message, err.loc));
+                }
+            }
+            core::Executable::If(node) => {
+                check_condition(&node.condition, types, errors);
+                check_executables(&node.children, types, errors);
+                for else_if in &node.else_if {
+                    check_condition(&else_if.condition, types, errors);
+                    check_executables(&else_if.children, types, errors);
+                }
+                check_executables(&node.otherwise, types, errors);
+            }
+            core::Executable::Foreach(node) => check_executables(&node.children, types, errors),
+            _ => (),
+        }
+    }
+}
+
+fn check_assignment(
+    location: &str,
+    expr: &datamodel::Expression,
+    types: &HashMap<datamodel::VariableId, datamodel::Type>,
+    errors: &mut Errors,
+) {
+    let t = match types.get(location) {
+        Some(t) => t,
+        None => {
+            errors.push(datamodel_error(
+                format!("Unknown variable: {:?}", location),
+                expr.loc(),
+            ));
+            return;
+        }
+    };
 
-    s1.idx == s2.idx || has_insersection(&t1.exits, &t2.exits) || s1.descendants.contains(&s2.idx)
-        || s2.descendants.contains(&s1.idx)
+    match expr.type_of(types) {
+        Ok(ref value) if t.accepts(value) => (),
+        Ok(value) => errors.push(datamodel_error(
+            format!(
+                "Cannot assign {:?} to {:?} of type {:?}",
+                value, location, t
+            ),
+            expr.loc(),
+        )),
+        Err(err) => errors.push(datamodel_error(err.message, err.loc)),
+    }
 }
 
-fn has_insersection<V: PartialEq>(arr1: &[V], arr2: &[V]) -> bool {
-    arr1.iter().any(|v| arr2.contains(v))
+fn datamodel_error(message: String, loc: Location) -> ConversionError {
+    ConversionError {
+        message,
+        fatal: true,
+        source: "statechart/ast/statechart/to_core".to_string(),
+        loc,
+    }
 }
 
-fn get_exit_set<'a>(
-    transition: &'a core::Transition,
+fn compute_conflicts(transitions: &mut [core::Transition], tree: &StateTree) {
+    let cloned: Vec<core::Transition> = transitions.into();
+    let conflicts = tree.conflicts(&cloned);
+    for transition in transitions {
+        transition.conflicts = conflicts.get(transition);
+    }
+}
+
+// the descendants of a state. States are numbered in pre-order, so these are the range of
+// ids right after the state; cores numbered some other way fall back to a bitset.
+enum Descendants {
+    Range(Range<core::StateId>),
+    Set(BitSet),
+}
+
+impl Descendants {
+    fn new(state: &core::State) -> Descendants {
+        let start = state.idx + 1;
+        let end = start + state.descendants.len();
+        let mut seen = BitSet::new(state.descendants.len());
+        let in_range = state.descendants.iter().all(|&idx| {
+            if idx < start || idx >= end {
+                return false;
+            }
+            seen.insert(idx - start);
+            true
+        });
+        if in_range && seen.len() == state.descendants.len() {
+            return Descendants::Range(start..end);
+        }
+
+        let capacity = state.descendants.iter().max().map_or(0, |max| max + 1);
+        let mut set = BitSet::new(capacity);
+        for &idx in &state.descendants {
+            set.insert(idx);
+        }
+        Descendants::Set(set)
+    }
+
+    fn contains(&self, idx: core::StateId) -> bool {
+        match self {
+            Descendants::Range(range) => range.contains(&idx),
+            Descendants::Set(set) => set.contains(idx),
+        }
+    }
+
+    fn to_vec(&self) -> Vec<core::StateId> {
+        match self {
+            Descendants::Range(range) => range.clone().collect(),
+            Descendants::Set(set) => set.iter().collect(),
+        }
+    }
+}
+
+// answers descendant queries for the states of a core in constant time, so exit sets and
+// conflicts can be computed for charts with thousands of states
+pub struct StateTree<'a> {
     states: &'a [core::State],
-) -> Vec<core::StateId> {
-    let domain = get_transition_domain(transition, states);
+    descendants: Vec<Descendants>,
+}
 
-    let targets = &transition.targets;
+impl<'a> StateTree<'a> {
+    pub fn new(states: &'a [core::State]) -> StateTree<'a> {
+        StateTree {
+            states,
+            descendants: states.iter().map(Descendants::new).collect(),
+        }
+    }
 
-    domain
-        .descendants
-        .iter()
-        .cloned()
-        .filter(|idx| {
-            let state = &states[*idx];
-            match state.t {
+    pub fn is_descendant(&self, idx: core::StateId, ancestor: core::StateId) -> bool {
+        self.descendants[ancestor].contains(idx)
+    }
+
+    // the states exited by `transition`, in ascending order
+    pub fn exit_set(&self, transition: &core::Transition) -> Vec<core::StateId> {
+        let domain = self.transition_domain(transition);
+        let targets = &transition.targets;
+
+        self.descendants[domain]
+            .to_vec()
+            .into_iter()
+            .filter(|&idx| match self.states[idx].t {
                 core::StateType::Atomic
                 | core::StateType::Compound
                 | core::StateType::Parallel
                 | core::StateType::Final => {
-                    !(targets.contains(idx) || are_descendants(&state.descendants, targets))
+                    !(targets.contains(&idx) || self.are_descendants(targets, idx))
                 }
                 _ => false,
+            })
+            .collect()
+    }
+
+    // indexes `transitions` by source and by domain for `Conflicts::get`
+    pub fn conflicts<'b>(&'b self, transitions: &'b [core::Transition]) -> Conflicts<'b> {
+        let sources: Vec<core::StateId> = transitions
+            .iter()
+            .map(|transition| self.transition_source(transition))
+            .collect();
+        let mut by_source: Vec<usize> = (0..transitions.len()).collect();
+        by_source.sort_by_key(|&position| sources[position]);
+
+        let capacity = transitions
+            .iter()
+            .flat_map(|transition| transition.exits.iter().map(|exit| exit + 1))
+            .fold(self.states.len(), usize::max);
+        let exits: Vec<BitSet> = transitions
+            .iter()
+            .map(|transition| exit_bits(transition, capacity))
+            .collect();
+
+        let mut domains = vec![0; transitions.len()];
+        let mut by_domain = vec![];
+        let mut unscoped = vec![];
+        for (position, transition) in transitions.iter().enumerate() {
+            if transition.exits.is_empty() {
+                continue;
             }
-        })
-        .collect()
-}
+            match self.exit_domain(transition) {
+                Some(domain) => {
+                    domains[position] = domain;
+                    by_domain.push(position);
+                }
+                None => unscoped.push(position),
+            }
+        }
+        by_domain.sort_by_key(|&position| domains[position]);
 
-fn get_transition_domain<'a>(
-    transition: &'a core::Transition,
-    states: &'a [core::State],
-) -> &'a core::State {
-    let source = get_transition_source(transition, states);
-    let targets = &transition.targets;
+        Conflicts {
+            tree: self,
+            transitions,
+            capacity,
+            sources,
+            by_source,
+            exits,
+            domains,
+            by_domain,
+            unscoped,
+        }
+    }
+
+    fn are_descendants(&self, targets: &[core::StateId], ancestor: core::StateId) -> bool {
+        targets
+            .iter()
+            .all(|&target| self.is_descendant(target, ancestor))
+    }
+
+    // calls `f` with ranges of ids covering the state and its descendants
+    fn each_subtree_range<F: FnMut(Range<core::StateId>)>(&self, idx: core::StateId, mut f: F) {
+        match self.descendants[idx] {
+            Descendants::Range(ref range) => f(idx..range.end),
+            Descendants::Set(ref set) => {
+                f(idx..idx + 1);
+                for idx in set.iter() {
+                    f(idx..idx + 1);
+                }
+            }
+        }
+    }
 
-    if transition.t == core::TransitionType::Internal && source.t == core::StateType::Compound
-        && are_descendants(&source.descendants, &targets)
-    {
-        return source;
+    // the ancestors of the state, as far as the descendant sets agree
+    fn ancestors(&self, idx: core::StateId) -> Vec<core::StateId> {
+        self.states[idx]
+            .ancestors
+            .iter()
+            .cloned()
+            .filter(|&ancestor| self.is_descendant(idx, ancestor))
+            .collect()
     }
 
-    find_lcca(states, &source, &targets)
+    // the transition domain when every exited state lies inside it, which holds for the
+    // exits `exit_set` computes
+    fn exit_domain(&self, transition: &core::Transition) -> Option<core::StateId> {
+        let domain = self.transition_domain(transition);
+        if transition
+            .exits
+            .iter()
+            .all(|&exit| self.is_descendant(exit, domain))
+        {
+            Some(domain)
+        } else {
+            None
+        }
+    }
+
+    fn transition_source(&self, transition: &core::Transition) -> core::StateId {
+        let source = &self.states[transition.source];
+        if source.t == core::StateType::Initial {
+            source.parent
+        } else {
+            source.idx
+        }
+    }
+
+    fn transition_domain(&self, transition: &core::Transition) -> core::StateId {
+        let source = self.transition_source(transition);
+        let targets = &transition.targets;
+
+        if transition.t == core::TransitionType::Internal
+            && self.states[source].t == core::StateType::Compound
+            && self.are_descendants(targets, source)
+        {
+            return source;
+        }
+
+        self.find_lcca(source, targets)
+    }
+
+    fn find_lcca(&self, source: core::StateId, targets: &[core::StateId]) -> core::StateId {
+        self.states[source]
+            .ancestors
+            .iter()
+            .rev()
+            .cloned()
+            .filter(|&anc| {
+                matches!(
+                    self.states[anc].t,
+                    core::StateType::Atomic | core::StateType::Compound | core::StateType::Parallel
+                )
+            })
+            .find(|&anc| self.is_descendant(source, anc) && self.are_descendants(targets, anc))
+            .unwrap_or(source)
+    }
 }
 
-fn find_lcca<'a>(
-    states: &'a [core::State],
-    source: &'a core::State,
-    targets: &'a [core::StateId],
-) -> &'a core::State {
-    let mut self_and_targets: Vec<core::StateId> = targets.into();
-    self_and_targets.push(source.idx);
-    source
-        .ancestors
-        .iter()
-        .rev()
-        .map(|anc| &states[*anc])
-        .filter(|state| match state.t {
-            core::StateType::Atomic | core::StateType::Compound | core::StateType::Parallel => true,
-            _ => false,
-        })
-        .find(|state| are_descendants(&state.descendants, &self_and_targets))
-        .unwrap_or(source)
+fn exit_bits(transition: &core::Transition, capacity: usize) -> BitSet {
+    let mut exits = BitSet::new(capacity);
+    for &exit in &transition.exits {
+        exits.insert(exit);
+    }
+    exits
 }
 
-fn are_descendants(descendants: &[core::StateId], targets: &[core::StateId]) -> bool {
-    targets.iter().all(|target| descendants.contains(target))
+// two transitions conflict when they share a source, one source is a descendant of the
+// other, or their exit sets intersect. Exit sets lie inside the transition domains, so
+// only transitions whose domains are nested need their exits compared.
+pub struct Conflicts<'a> {
+    tree: &'a StateTree<'a>,
+    transitions: &'a [core::Transition],
+    capacity: usize,
+    sources: Vec<core::StateId>,
+    // positions of the transitions ordered by source, so the transitions leaving a range
+    // of states are a slice of it
+    by_source: Vec<usize>,
+    exits: Vec<BitSet>,
+    domains: Vec<core::StateId>,
+    // positions of the transitions that exit something, ordered by domain
+    by_domain: Vec<usize>,
+    // transitions that exit states outside of their domain
+    unscoped: Vec<usize>,
 }
 
-fn get_transition_source<'a>(
-    transition: &'a core::Transition,
-    states: &'a [core::State],
-) -> &'a core::State {
-    let core::Transition { source, .. } = transition;
-    let source = &states[*source];
-    if source.t == core::StateType::Initial {
-        &states[source.parent]
-    } else {
-        source
+impl<'a> Conflicts<'a> {
+    pub fn get(&self, transition: &core::Transition) -> Vec<core::TransitionId> {
+        let tree = self.tree;
+        let source = tree.transition_source(transition);
+        let mut conflicts = BitSet::new(self.transitions.len());
+
+        tree.each_subtree_range(source, |range| {
+            conflicts.extend(slice(&self.by_source, &self.sources, range))
+        });
+        for ancestor in tree.ancestors(source) {
+            conflicts.extend(slice(
+                &self.by_source,
+                &self.sources,
+                ancestor..ancestor + 1,
+            ));
+        }
+
+        if !transition.exits.is_empty() {
+            let exits = exit_bits(transition, self.capacity.max(self.max_exit(transition)));
+            let mut candidates = BitSet::new(self.transitions.len());
+            match tree.exit_domain(transition) {
+                Some(domain) => {
+                    tree.each_subtree_range(domain, |range| {
+                        candidates.extend(slice(&self.by_domain, &self.domains, range))
+                    });
+                    for ancestor in tree.ancestors(domain) {
+                        candidates.extend(slice(
+                            &self.by_domain,
+                            &self.domains,
+                            ancestor..ancestor + 1,
+                        ));
+                    }
+                    candidates.extend(self.unscoped.iter().cloned());
+                }
+                None => {
+                    candidates.extend(self.by_domain.iter().cloned());
+                    candidates.extend(self.unscoped.iter().cloned());
+                }
+            }
+            for position in candidates.iter() {
+                if !conflicts.contains(position) && exits.intersects(&self.exits[position]) {
+                    conflicts.insert(position);
+                }
+            }
+        }
+
+        conflicts
+            .iter()
+            .map(|position| self.transitions[position].idx)
+            .filter(|&idx| idx != transition.idx)
+            .collect()
+    }
+
+    fn max_exit(&self, transition: &core::Transition) -> usize {
+        transition
+            .exits
+            .iter()
+            .map(|exit| exit + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// the positions in `order` whose key is in `range`, given `order` is sorted by key
+fn slice<'a>(
+    order: &'a [usize],
+    keys: &[core::StateId],
+    range: Range<core::StateId>,
+) -> &'a [usize] {
+    let start = order.partition_point(|&position| keys[position] < range.start);
+    let end = order.partition_point(|&position| keys[position] < range.end);
+    &order[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::builder::{ParallelBuilder, StateBuilder};
+    use ast::statechart::{HistoryType, Statechart};
+    use std::time::Instant;
+
+    // generates a chart `depth` levels deep where every state but the leaves has `width`
+    // children. The second child of each state is parallel and every compound state has a
+    // history, an internal transition to its first child and transitions to random states.
+    struct Generator {
+        depth: usize,
+        width: usize,
+        seed: u64,
+        ids: Vec<String>,
+    }
+
+    impl Generator {
+        fn new(depth: usize, width: usize) -> Generator {
+            let mut generator = Generator {
+                depth,
+                width,
+                seed: 1,
+                ids: vec![],
+            };
+            let mut ids = vec![];
+            generator.collect_ids("s", 0, &mut ids);
+            generator.ids = ids;
+            generator
+        }
+
+        fn collect_ids(&self, id: &str, level: usize, ids: &mut Vec<String>) {
+            ids.push(id.to_string());
+            if level < self.depth {
+                if !self.is_parallel(id) {
+                    ids.push(format!("{}_h", id));
+                }
+                for i in 0..self.width {
+                    self.collect_ids(&format!("{}_{}", id, i), level + 1, ids);
+                }
+            }
+        }
+
+        fn is_parallel(&self, id: &str) -> bool {
+            id.ends_with("_1")
+        }
+
+        fn next(&mut self, bound: usize) -> usize {
+            self.seed = self
+                .seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (self.seed >> 33) as usize % bound
+        }
+
+        fn target(&mut self) -> String {
+            let idx = self.next(self.ids.len());
+            self.ids[idx].clone()
+        }
+
+        fn build(mut self) -> core::Core {
+            Statechart::builder()
+                .state("s", |s| self.compound(s, "s", 0))
+                .build_core()
+                .unwrap()
+        }
+
+        fn compound(&mut self, mut s: StateBuilder, id: &str, level: usize) -> StateBuilder {
+            if level < self.depth {
+                let t = if self.next(2) == 0 {
+                    HistoryType::Deep
+                } else {
+                    HistoryType::Shallow
+                };
+                s = s.history(&format!("{}_h", id), t);
+                for i in 0..self.width {
+                    let child = format!("{}_{}", id, i);
+                    s = if level + 1 < self.depth && self.is_parallel(&child) {
+                        s.parallel(&child, |p| self.parallel(p, &child, level + 1))
+                    } else {
+                        s.state(&child, |c| self.compound(c, &child, level + 1))
+                    };
+                }
+                let first = format!("{}_0", id);
+                s = s.transition(|t| t.event(0).target(&first).internal());
+            }
+            let (a, b) = (self.target(), self.target());
+            s.on(self.next(3), &a)
+                .transition(|t| t.event(1).target(&a).target(&b))
+        }
+
+        fn parallel(&mut self, mut p: ParallelBuilder, id: &str, level: usize) -> ParallelBuilder {
+            for i in 0..self.width {
+                let child = format!("{}_{}", id, i);
+                p = p.state(&child, |c| self.compound(c, &child, level + 1));
+            }
+            let target = self.target();
+            p.on(2, &target)
+        }
+    }
+
+    // the exit sets and conflicts as they were computed before `StateTree`, from the
+    // ancestor and descendant lists alone
+    fn reference(chart: &core::Core) -> Vec<(Vec<usize>, Vec<usize>)> {
+        let states = &chart.states;
+        let source = |transition: &core::Transition| {
+            let source = &states[transition.source];
+            match source.t {
+                core::StateType::Initial => &states[source.parent],
+                _ => source,
+            }
+        };
+        let below = |ancestor: &core::State, ids: &[usize]| {
+            ids.iter().all(|id| ancestor.descendants.contains(id))
+        };
+        let exits: Vec<Vec<usize>> = chart
+            .transitions
+            .iter()
+            .map(|transition| {
+                let from = source(transition);
+                let targets = &transition.targets;
+                let mut from_and_targets = targets.clone();
+                from_and_targets.push(from.idx);
+                let domain = if transition.t == core::TransitionType::Internal
+                    && from.t == core::StateType::Compound
+                    && below(from, targets)
+                {
+                    from
+                } else {
+                    from.ancestors
+                        .iter()
+                        .rev()
+                        .map(|idx| &states[*idx])
+                        .filter(|state| {
+                            matches!(
+                                state.t,
+                                core::StateType::Atomic
+                                    | core::StateType::Compound
+                                    | core::StateType::Parallel
+                            )
+                        })
+                        .find(|state| below(state, &from_and_targets))
+                        .unwrap_or(from)
+                };
+                let mut exits: Vec<usize> = domain
+                    .descendants
+                    .iter()
+                    .cloned()
+                    .filter(|idx| {
+                        let state = &states[*idx];
+                        match state.t {
+                            core::StateType::Atomic
+                            | core::StateType::Compound
+                            | core::StateType::Parallel
+                            | core::StateType::Final => {
+                                !(targets.contains(idx) || below(state, targets))
+                            }
+                            _ => false,
+                        }
+                    })
+                    .collect();
+                exits.sort();
+                exits
+            })
+            .collect();
+
+        chart
+            .transitions
+            .iter()
+            .map(|t1| {
+                let s1 = source(t1);
+                let conflicts = chart
+                    .transitions
+                    .iter()
+                    .filter(|t2| t2.idx != t1.idx)
+                    .filter(|t2| {
+                        let s2 = source(t2);
+                        s1.idx == s2.idx
+                            || exits[t1.idx]
+                                .iter()
+                                .any(|exit| exits[t2.idx].contains(exit))
+                            || s1.descendants.contains(&s2.idx)
+                            || s2.descendants.contains(&s1.idx)
+                    })
+                    .map(|t2| t2.idx)
+                    .collect();
+                (exits[t1.idx].clone(), conflicts)
+            })
+            .collect()
+    }
+
+    fn derived(chart: &core::Core) -> Vec<(Vec<usize>, Vec<usize>)> {
+        chart
+            .transitions
+            .iter()
+            .map(|transition| (transition.exits.clone(), transition.conflicts.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn exits_and_conflicts_match_the_reference() {
+        for &(depth, width) in &[(1, 2), (2, 3), (3, 3), (4, 2)] {
+            let chart = Generator::new(depth, width).build();
+            let has_state = |t| chart.states.iter().any(|state| state.t == t);
+            assert_eq!(has_state(core::StateType::Parallel), depth > 1);
+            assert!(
+                has_state(core::StateType::HistoryDeep)
+                    || has_state(core::StateType::HistoryShallow)
+            );
+            assert!(chart
+                .transitions
+                .iter()
+                .any(|transition| transition.t == core::TransitionType::Internal));
+            assert_eq!(derived(&chart), reference(&chart), "{}x{}", depth, width);
+        }
+    }
+
+    #[test]
+    fn renumbered_states_fall_back_to_sets() {
+        let chart = Generator::new(3, 3).build();
+        let tree = StateTree::new(&chart.states);
+        assert!(tree
+            .descendants
+            .iter()
+            .all(|descendants| matches!(descendants, Descendants::Range(_))));
+
+        // reversing the ids of every state but the root breaks up the descendant ranges
+        let len = chart.states.len();
+        let renumber = |idx: usize| if idx == 0 { 0 } else { len - idx };
+        let renumber_all =
+            |ids: &[usize]| -> Vec<usize> { ids.iter().map(|idx| renumber(*idx)).collect() };
+        let mut states = chart.states.clone();
+        for state in states.iter_mut() {
+            state.idx = renumber(state.idx);
+            state.parent = renumber(state.parent);
+            state.children = renumber_all(&state.children);
+            state.ancestors = renumber_all(&state.ancestors);
+            state.descendants = renumber_all(&state.descendants);
+            state.initial = renumber_all(&state.initial);
+        }
+        states.sort_by_key(|state| state.idx);
+        let mut transitions = chart.transitions.clone();
+        for transition in transitions.iter_mut() {
+            transition.source = renumber(transition.source);
+            transition.targets = renumber_all(&transition.targets);
+            transition.exits = renumber_all(&transition.exits);
+        }
+
+        let renumbered = StateTree::new(&states);
+        assert!(renumbered
+            .descendants
+            .iter()
+            .any(|descendants| matches!(descendants, Descendants::Set(_))));
+        let conflicts = renumbered.conflicts(&transitions);
+        for (transition, original) in transitions.iter().zip(&chart.transitions) {
+            let mut exits = renumber_all(&renumbered.exit_set(transition));
+            exits.sort();
+            assert_eq!(exits, original.exits);
+            assert_eq!(conflicts.get(transition), original.conflicts);
+        }
+    }
+
+    // cargo test --release -- --ignored --nocapture large_charts
+    #[test]
+    #[ignore]
+    fn large_charts() {
+        for &(depth, width) in &[(4, 4), (5, 4), (4, 8), (6, 4)] {
+            let generator = Generator::new(depth, width);
+            let start = Instant::now();
+            let chart = generator.build();
+            println!(
+                "{}x{}: {} states, {} transitions in {:?}",
+                depth,
+                width,
+                chart.states.len(),
+                chart.transitions.len(),
+                start.elapsed()
+            );
+            // the reference is quadratic in the exits of every pair of transitions
+            if chart.states.len() < 1000 {
+                let start = Instant::now();
+                reference(&chart);
+                println!("  reference in {:?}", start.elapsed());
+            }
+        }
     }
 }
@@ -0,0 +1,344 @@
+use ast::datamodel::{self, Expression, Type, Variable};
+use ast::executable::Executable;
+use ast::statechart::*;
+use std::fmt::Write;
+
+pub const SCXML_NAMESPACE: &str = "http://www.w3.org/2005/07/scxml";
+
+pub fn to_scxml(statechart: &Statechart) -> String {
+    let mut writer = Writer::default();
+    writeln!(writer.out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+
+    let mut attrs = vec![
+        ("xmlns", SCXML_NAMESPACE.to_string()),
+        ("version", "1.0".to_string()),
+    ];
+    if !statechart.initital.is_empty() {
+        attrs.push(("initial", statechart.initital.join(" ")));
+    }
+    if let Binding::Early = statechart.binding {
+        attrs.push(("binding", "early".to_string()));
+    }
+
+    let is_empty = statechart.datamodel.is_empty() && statechart.children.is_empty();
+    writer.open("scxml", &attrs, is_empty);
+    if !is_empty {
+        writer.datamodel(&statechart.datamodel);
+        writer.nodes(&statechart.children);
+        writer.close("scxml");
+    }
+
+    writer.out
+}
+
+pub fn condition_to_string(condition: &Condition) -> String {
+    match condition {
+        Condition::Predicate(node) => format!("#{}", node.id),
+        Condition::In(node) => format!("In({})", datamodel::quote(&node.state, '"')),
+        Condition::And(node) => conditions_to_string(&node.children, " && "),
+        Condition::Or(node) => conditions_to_string(&node.children, " || "),
+        Condition::Not(node) => format!("!{}", operand_to_string(&node.condition)),
+        Condition::Expression(node) => node.expr.to_string(),
+    }
+}
+
+fn conditions_to_string(conditions: &[Condition], separator: &str) -> String {
+    conditions
+        .iter()
+        .map(operand_to_string)
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+fn operand_to_string(condition: &Condition) -> String {
+    match condition {
+        Condition::And(_)
+        | Condition::Or(_)
+        | Condition::Expression(ExpressionCondition {
+            expr: Expression::Binary(_),
+            ..
+        }) => format!("({})", condition_to_string(condition)),
+        _ => condition_to_string(condition),
+    }
+}
+
+pub fn type_to_string(t: &Type) -> String {
+    match t {
+        Type::Integer => "integer".to_string(),
+        Type::Boolean => "boolean".to_string(),
+        Type::String => "string".to_string(),
+        Type::Enum(variants) => format!("enum({})", variants.join(", ")),
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    out: String,
+    depth: usize,
+}
+
+impl Writer {
+    fn open(&mut self, name: &str, attrs: &[(&str, String)], is_empty: bool) {
+        write!(self.out, "{}<{}", "  ".repeat(self.depth), name).unwrap();
+        for (key, value) in attrs {
+            write!(self.out, " {}=\"{}\"", key, escape(value)).unwrap();
+        }
+        if is_empty {
+            writeln!(self.out, "/>").unwrap();
+        } else {
+            writeln!(self.out, ">").unwrap();
+            self.depth += 1;
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        self.depth -= 1;
+        writeln!(self.out, "{}</{}>", "  ".repeat(self.depth), name).unwrap();
+    }
+
+    fn element<F: FnOnce(&mut Writer)>(
+        &mut self,
+        name: &str,
+        attrs: &[(&str, String)],
+        is_empty: bool,
+        children: F,
+    ) {
+        self.open(name, attrs, is_empty);
+        if !is_empty {
+            children(self);
+            self.close(name);
+        }
+    }
+
+    fn datamodel(&mut self, datamodel: &[Variable]) {
+        if datamodel.is_empty() {
+            return;
+        }
+        self.element("datamodel", &[], false, |writer| {
+            for variable in datamodel {
+                let mut attrs = vec![
+                    ("id", variable.id.clone()),
+                    ("type", type_to_string(&variable.t)),
+                ];
+                if let Some(ref init) = variable.init {
+                    attrs.push(("expr", init.to_string()));
+                }
+                writer.open("data", &attrs, true);
+            }
+        });
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::Statechart(node) => {
+                let is_empty = node.datamodel.is_empty() && node.children.is_empty();
+                self.element("scxml", &[], is_empty, |writer| {
+                    writer.datamodel(&node.datamodel);
+                    writer.nodes(&node.children);
+                });
+            }
+            Node::State(node) => {
+                let mut attrs = id_attr(&node.id);
+                if !node.initial.is_empty() {
+                    attrs.push(("initial", node.initial.join(" ")));
+                }
+                let is_empty = node.datamodel.is_empty() && node.children.is_empty();
+                self.element("state", &attrs, is_empty, |writer| {
+                    writer.datamodel(&node.datamodel);
+                    writer.nodes(&node.children);
+                });
+            }
+            Node::Parallel(node) => {
+                self.element(
+                    "parallel",
+                    &id_attr(&node.id),
+                    node.children.is_empty(),
+                    |writer| writer.nodes(&node.children),
+                );
+            }
+            Node::Transition(node) => {
+                let mut attrs = vec![];
+                if let Some(event) = node.event {
+                    attrs.push(("event", event.to_string()));
+                }
+                if let Some(ref condition) = node.condition {
+                    attrs.push(("cond", condition_to_string(condition)));
+                }
+                if !node.targets.is_empty() {
+                    attrs.push(("target", node.targets.join(" ")));
+                }
+                if let TransitionType::Internal = node.t {
+                    attrs.push(("type", "internal".to_string()));
+                }
+                self.element("transition", &attrs, node.children.is_empty(), |writer| {
+                    writer.executables(&node.children)
+                });
+            }
+            Node::OnEvent(node) => {
+                let mut attrs = vec![];
+                if let Some(event) = node.event {
+                    attrs.push(("event", event.to_string()));
+                }
+                if let Some(ref condition) = node.condition {
+                    attrs.push(("cond", condition_to_string(condition)));
+                }
+                self.element("onevent", &attrs, node.children.is_empty(), |writer| {
+                    writer.executables(&node.children)
+                });
+            }
+            Node::Initial(node) => {
+                self.element("initial", &[], node.children.is_empty(), |writer| {
+                    writer.nodes(&node.children)
+                });
+            }
+            Node::Final(node) => {
+                self.element(
+                    "final",
+                    &id_attr(&node.id),
+                    node.children.is_empty(),
+                    |writer| writer.nodes(&node.children),
+                );
+            }
+            Node::OnInit(node) => {
+                self.element("oninit", &[], node.children.is_empty(), |writer| {
+                    writer.executables(&node.children)
+                });
+            }
+            Node::OnEntry(node) => {
+                self.element("onentry", &[], node.children.is_empty(), |writer| {
+                    writer.executables(&node.children)
+                });
+            }
+            Node::OnExit(node) => {
+                self.element("onexit", &[], node.children.is_empty(), |writer| {
+                    writer.executables(&node.children)
+                });
+            }
+            Node::History(node) => {
+                let mut attrs = id_attr(&node.id);
+                if let HistoryType::Deep = node.t {
+                    attrs.push(("type", "deep".to_string()));
+                }
+                self.element("history", &attrs, node.children.is_empty(), |writer| {
+                    writer.nodes(&node.children)
+                });
+            }
+            Node::Invoke(node) => {
+                self.open("invoke", &[("id", node.id.to_string())], true);
+            }
+        }
+    }
+
+    fn executables(&mut self, executables: &[Executable]) {
+        for executable in executables {
+            self.executable(executable);
+        }
+    }
+
+    fn executable(&mut self, executable: &Executable) {
+        match executable {
+            Executable::Script(node) => {
+                self.open("script", &[("id", node.id.to_string())], true);
+            }
+            Executable::Raise(node) => {
+                self.open("raise", &[("event", node.event.to_string())], true);
+            }
+            Executable::Send(node) => {
+                let mut attrs = vec![("event", node.event.to_string())];
+                if let Some(ref target) = node.target {
+                    attrs.push(("target", target.clone()));
+                }
+                if let Some(ref id) = node.id {
+                    attrs.push(("id", id.clone()));
+                }
+                if node.delay > 0 {
+                    attrs.push(("delay", format!("{}ms", node.delay)));
+                }
+                self.open("send", &attrs, true);
+            }
+            Executable::Assign(node) => {
+                let attrs = [
+                    ("location", node.location.clone()),
+                    ("expr", node.expr.to_string()),
+                ];
+                self.open("assign", &attrs, true);
+            }
+            Executable::Log(node) => {
+                let mut attrs = vec![];
+                if let Some(ref label) = node.label {
+                    attrs.push(("label", label.clone()));
+                }
+                if let Some(ref expr) = node.expr {
+                    attrs.push(("expr", expr.to_string()));
+                }
+                self.open("log", &attrs, true);
+            }
+            Executable::If(node) => {
+                let is_empty = node.children.is_empty()
+                    && node.else_if.is_empty()
+                    && node.otherwise.is_empty();
+                let attrs = [("cond", condition_to_string(&node.condition))];
+                self.element("if", &attrs, is_empty, |writer| {
+                    writer.executables(&node.children);
+                    for else_if in &node.else_if {
+                        writer.open(
+                            "elseif",
+                            &[("cond", condition_to_string(&else_if.condition))],
+                            true,
+                        );
+                        writer.executables(&else_if.children);
+                    }
+                    if !node.otherwise.is_empty() {
+                        writer.open("else", &[], true);
+                        writer.executables(&node.otherwise);
+                    }
+                });
+            }
+            Executable::Foreach(node) => {
+                let mut attrs = vec![
+                    ("array", node.array.to_string()),
+                    ("item", node.item.clone()),
+                ];
+                if let Some(ref index) = node.index {
+                    attrs.push(("index", index.clone()));
+                }
+                self.element("foreach", &attrs, node.children.is_empty(), |writer| {
+                    writer.executables(&node.children)
+                });
+            }
+            Executable::Cancel(node) => {
+                self.open("cancel", &[("sendid", node.id.clone())], true);
+            }
+        }
+    }
+}
+
+fn id_attr(id: &Option<String>) -> Vec<(&'static str, String)> {
+    match id {
+        Some(id) => vec![("id", id.clone())],
+        None => vec![],
+    }
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\t' => escaped.push_str("&#9;"),
+            '\r' => escaped.push_str("&#13;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
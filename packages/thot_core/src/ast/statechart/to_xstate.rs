@@ -0,0 +1,496 @@
+use ast::datamodel::{Expression, Value as DataValue, Variable};
+use ast::executable::Executable;
+use ast::statechart::from_xstate::{parse_after_event, Symbols, XState};
+use ast::statechart::to_scxml::condition_to_string;
+use ast::statechart::*;
+use serde_json::{self, Map, Value};
+use std::collections::HashMap;
+
+pub fn to_xstate(machine: &XState) -> String {
+    let id = machine.id.clone().unwrap_or_else(|| "machine".to_string());
+    let mut exporter = Exporter {
+        machine: id.clone(),
+        symbols: &machine.symbols,
+        paths: HashMap::new(),
+    };
+    exporter.index(&machine.statechart.children, &[], "");
+
+    let mut config = Map::new();
+    config.insert("id".to_string(), Value::from(id));
+    let context = context(&machine.statechart.datamodel);
+    if !context.is_empty() {
+        config.insert("context".to_string(), Value::Object(context));
+    }
+    exporter.children(
+        &mut config,
+        &machine.statechart.children,
+        &machine.statechart.initital,
+        &[],
+        true,
+    );
+
+    serde_json::to_string_pretty(&Value::Object(config)).unwrap()
+}
+
+struct Exporter<'a> {
+    machine: String,
+    symbols: &'a Symbols,
+    paths: HashMap<String, Vec<String>>,
+}
+
+impl<'a> Exporter<'a> {
+    fn index(&mut self, nodes: &[Node], path: &[String], parent: &str) {
+        let mut i = 0;
+        for node in nodes {
+            let (id, children) = match node {
+                Node::Statechart(node) => (&None, &node.children),
+                Node::State(node) => (&node.id, &node.children),
+                Node::Parallel(node) => (&node.id, &node.children),
+                Node::Final(node) => (&node.id, &node.children),
+                Node::History(node) => (&node.id, &node.children),
+                _ => continue,
+            };
+            let key = key(id, parent, i);
+            i += 1;
+            let mut child_path = path.to_vec();
+            child_path.push(key);
+            if let Some(id) = id {
+                self.paths.insert(id.clone(), child_path.clone());
+            }
+            self.index(
+                children,
+                &child_path,
+                id.as_ref().map_or("", |s| s.as_str()),
+            );
+        }
+    }
+
+    fn children(
+        &self,
+        config: &mut Map<String, Value>,
+        nodes: &[Node],
+        initial: &[String],
+        path: &[String],
+        is_compound: bool,
+    ) {
+        let mut entry = vec![];
+        let mut exit = vec![];
+        let mut on = Map::new();
+        let mut always = vec![];
+        let mut after = Map::new();
+        let mut states = Map::new();
+        let mut initial_key = initial.first().and_then(|id| self.child_key(id, path));
+
+        for node in nodes {
+            match node {
+                Node::OnEntry(node) => entry.extend(
+                    node.children
+                        .iter()
+                        .filter(|e| !is_after_send(e))
+                        .map(|e| self.action(e)),
+                ),
+                Node::OnExit(node) => exit.extend(
+                    node.children
+                        .iter()
+                        .filter(|e| !is_after_cancel(e))
+                        .map(|e| self.action(e)),
+                ),
+                Node::Transition(transition) => {
+                    let value = self.transition(
+                        &transition.targets,
+                        transition.t,
+                        &transition.condition,
+                        &transition.children,
+                        path,
+                    );
+                    match transition.event {
+                        None => always.push(value),
+                        Some(event) => {
+                            let name = self.symbols.event_name(event);
+                            match parse_after_event(&name) {
+                                Some(delay) => push(&mut after, delay.to_string(), value),
+                                None => push(&mut on, name, value),
+                            }
+                        }
+                    }
+                }
+                Node::OnEvent(node) => {
+                    let value = self.transition(
+                        &[],
+                        TransitionType::Internal,
+                        &node.condition,
+                        &node.children,
+                        path,
+                    );
+                    match node.event {
+                        None => always.push(value),
+                        Some(event) => push(&mut on, self.symbols.event_name(event), value),
+                    }
+                }
+                Node::Initial(node) => {
+                    for child in &node.children {
+                        if let Node::Transition(transition) = child {
+                            if let Some(target) = transition.targets.first() {
+                                initial_key = initial_key.or_else(|| self.child_key(target, path));
+                            }
+                        }
+                    }
+                }
+                Node::Statechart(_)
+                | Node::State(_)
+                | Node::Parallel(_)
+                | Node::Final(_)
+                | Node::History(_) => {
+                    let (key, state) = self.state(node, path, states.len());
+                    states.insert(key, Value::Object(state));
+                }
+                Node::OnInit(_) | Node::Invoke(_) => (),
+            }
+        }
+
+        if is_compound && !states.is_empty() {
+            let key = initial_key.unwrap_or_else(|| states.keys().next().unwrap().clone());
+            config.insert("initial".to_string(), Value::from(key));
+        }
+        insert_actions(config, "entry", entry);
+        insert_actions(config, "exit", exit);
+        if !on.is_empty() {
+            config.insert("on".to_string(), Value::Object(on));
+        }
+        if !always.is_empty() {
+            config.insert("always".to_string(), unwrap_single(always));
+        }
+        if !after.is_empty() {
+            config.insert("after".to_string(), Value::Object(after));
+        }
+        if !states.is_empty() {
+            config.insert("states".to_string(), Value::Object(states));
+        }
+    }
+
+    fn state(&self, node: &Node, parent: &[String], i: usize) -> (String, Map<String, Value>) {
+        let mut config = Map::new();
+        let (id, t, children, initial): (_, _, _, &[String]) = match node {
+            Node::Statechart(node) => (&None, None, &node.children, &node.initital),
+            Node::State(node) => (&node.id, None, &node.children, &node.initial),
+            Node::Parallel(node) => (&node.id, Some("parallel"), &node.children, &[]),
+            Node::Final(node) => (&node.id, Some("final"), &node.children, &[]),
+            Node::History(node) => (&node.id, Some("history"), &node.children, &[]),
+            _ => unreachable!(),
+        };
+
+        let path = match id.as_ref().and_then(|id| self.paths.get(id)) {
+            Some(path) => path.clone(),
+            None => {
+                let mut path = parent.to_vec();
+                path.push(key(&None, "", i));
+                path
+            }
+        };
+        if let Some(id) = id {
+            if *id != path.join(".") {
+                config.insert("id".to_string(), Value::from(id.clone()));
+            }
+        }
+        if let Some(t) = t {
+            config.insert("type".to_string(), Value::from(t));
+        }
+
+        if let Node::History(node) = node {
+            if let HistoryType::Deep = node.t {
+                config.insert("history".to_string(), Value::from("deep"));
+            }
+            for child in &node.children {
+                if let Node::Transition(transition) = child {
+                    config.insert(
+                        "target".to_string(),
+                        self.targets(&transition.targets, &path),
+                    );
+                }
+            }
+        } else {
+            self.children(&mut config, children, initial, &path, t.is_none());
+        }
+
+        (path.last().unwrap().clone(), config)
+    }
+
+    fn transition(
+        &self,
+        targets: &[String],
+        t: TransitionType,
+        condition: &Option<Condition>,
+        children: &[Executable],
+        path: &[String],
+    ) -> Value {
+        let mut config = Map::new();
+        if !targets.is_empty() {
+            let target = self.targets(targets, path);
+            let is_default_internal = match target {
+                Value::String(ref target) => target.starts_with('.'),
+                Value::Array(ref targets) => targets
+                    .iter()
+                    .all(|t| t.as_str().is_some_and(|t| t.starts_with('.'))),
+                _ => false,
+            };
+            config.insert("target".to_string(), target);
+            match (t, is_default_internal) {
+                (TransitionType::Internal, false) => {
+                    config.insert("internal".to_string(), Value::from(true));
+                }
+                (TransitionType::External, true) => {
+                    config.insert("internal".to_string(), Value::from(false));
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(condition) = condition {
+            self.condition(&mut config, condition);
+        }
+
+        insert_actions(
+            &mut config,
+            "actions",
+            children.iter().map(|e| self.action(e)).collect(),
+        );
+
+        match (config.len(), config.get("target")) {
+            (1, Some(target)) => target.clone(),
+            _ => Value::Object(config),
+        }
+    }
+
+    fn condition(&self, config: &mut Map<String, Value>, condition: &Condition) {
+        match condition {
+            Condition::Predicate(node) => {
+                config.insert(
+                    "cond".to_string(),
+                    Value::from(self.symbols.guard_name(node.id)),
+                );
+            }
+            Condition::In(node) => {
+                config.insert("in".to_string(), Value::from(self.reference(&node.state)));
+            }
+            Condition::And(node)
+                if node.children.len() == 2
+                    && matches!(node.children[0], Condition::Predicate(_))
+                    && matches!(node.children[1], Condition::In(_)) =>
+            {
+                self.condition(config, &node.children[0]);
+                self.condition(config, &node.children[1]);
+            }
+            condition => {
+                config.insert(
+                    "cond".to_string(),
+                    Value::from(condition_to_string(condition)),
+                );
+            }
+        }
+    }
+
+    fn action(&self, executable: &Executable) -> Value {
+        let mut config = Map::new();
+        match executable {
+            Executable::Script(node) => return Value::from(self.symbols.action_name(node.id)),
+            Executable::Raise(node) => {
+                config.insert("type".to_string(), Value::from("xstate.raise"));
+                config.insert(
+                    "event".to_string(),
+                    Value::from(self.symbols.event_name(node.event)),
+                );
+            }
+            Executable::Send(node) => {
+                config.insert("type".to_string(), Value::from("xstate.send"));
+                config.insert(
+                    "event".to_string(),
+                    Value::from(self.symbols.event_name(node.event)),
+                );
+                if let Some(ref target) = node.target {
+                    config.insert("to".to_string(), Value::from(target.clone()));
+                }
+                if let Some(ref id) = node.id {
+                    config.insert("id".to_string(), Value::from(id.clone()));
+                }
+                if node.delay > 0 {
+                    config.insert("delay".to_string(), Value::from(node.delay));
+                }
+            }
+            Executable::Cancel(node) => {
+                config.insert("type".to_string(), Value::from("xstate.cancel"));
+                config.insert("sendId".to_string(), Value::from(node.id.clone()));
+            }
+            Executable::Assign(node) => {
+                let mut assignment = Map::new();
+                assignment.insert(node.location.clone(), Value::from(node.expr.to_string()));
+                config.insert("type".to_string(), Value::from("xstate.assign"));
+                config.insert("assignment".to_string(), Value::Object(assignment));
+            }
+            Executable::Log(node) => {
+                config.insert("type".to_string(), Value::from("xstate.log"));
+                if let Some(ref label) = node.label {
+                    config.insert("label".to_string(), Value::from(label.clone()));
+                }
+                if let Some(ref expr) = node.expr {
+                    config.insert("expr".to_string(), Value::from(expr.to_string()));
+                }
+            }
+            Executable::If(node) => {
+                let mut conds = vec![self.branch(Some(&node.condition), &node.children)];
+                for else_if in &node.else_if {
+                    conds.push(self.branch(Some(&else_if.condition), &else_if.children));
+                }
+                if !node.otherwise.is_empty() {
+                    conds.push(self.branch(None, &node.otherwise));
+                }
+                config.insert("type".to_string(), Value::from("xstate.choose"));
+                config.insert("conds".to_string(), Value::Array(conds));
+            }
+            Executable::Foreach(node) => {
+                config.insert("type".to_string(), Value::from("thot.foreach"));
+                config.insert("array".to_string(), Value::from(node.array));
+                config.insert("item".to_string(), Value::from(node.item.clone()));
+                if let Some(ref index) = node.index {
+                    config.insert("index".to_string(), Value::from(index.clone()));
+                }
+                config.insert(
+                    "actions".to_string(),
+                    Value::Array(node.children.iter().map(|e| self.action(e)).collect()),
+                );
+            }
+        }
+        Value::Object(config)
+    }
+
+    fn branch(&self, condition: Option<&Condition>, children: &[Executable]) -> Value {
+        let mut config = Map::new();
+        if let Some(condition) = condition {
+            self.condition(&mut config, condition);
+        }
+        config.insert(
+            "actions".to_string(),
+            Value::Array(children.iter().map(|e| self.action(e)).collect()),
+        );
+        Value::Object(config)
+    }
+
+    fn targets(&self, targets: &[String], source: &[String]) -> Value {
+        let mut targets: Vec<Value> = targets
+            .iter()
+            .map(|target| Value::from(self.target(target, source)))
+            .collect();
+        if targets.len() == 1 {
+            targets.pop().unwrap()
+        } else {
+            Value::Array(targets)
+        }
+    }
+
+    fn target(&self, target: &str, source: &[String]) -> String {
+        let path = match self.paths.get(target) {
+            Some(path) => path,
+            None => return format!("#{}", target),
+        };
+        let parent = &source[..source.len().saturating_sub(1)];
+        if path.len() == source.len() && path.starts_with(parent) {
+            path.last().unwrap().clone()
+        } else if path.len() > source.len() && path.starts_with(source) {
+            format!(".{}", path[source.len()..].join("."))
+        } else {
+            self.reference(target)
+        }
+    }
+
+    fn reference(&self, id: &str) -> String {
+        match self.paths.get(id) {
+            Some(path) if *id == path.join(".") => format!("#{}.{}", self.machine, id),
+            _ => format!("#{}", id),
+        }
+    }
+
+    fn child_key(&self, id: &str, path: &[String]) -> Option<String> {
+        let target = self.paths.get(id)?;
+        if target.len() > path.len() && target.starts_with(path) {
+            Some(target[path.len()].clone())
+        } else {
+            None
+        }
+    }
+}
+
+fn key(id: &Option<String>, parent: &str, i: usize) -> String {
+    match id {
+        Some(id) => {
+            let prefix = format!("{}.", parent);
+            match id.strip_prefix(prefix.as_str()) {
+                Some(key) if !parent.is_empty() && !key.contains('.') => key.to_string(),
+                _ => id.clone(),
+            }
+        }
+        None => format!("state{}", i),
+    }
+}
+
+fn push(map: &mut Map<String, Value>, key: String, value: Value) {
+    match map.get_mut(&key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key, value);
+        }
+    }
+}
+
+fn unwrap_single(mut values: Vec<Value>) -> Value {
+    if values.len() == 1 {
+        values.pop().unwrap()
+    } else {
+        Value::Array(values)
+    }
+}
+
+fn insert_actions(config: &mut Map<String, Value>, key: &str, actions: Vec<Value>) {
+    if !actions.is_empty() {
+        config.insert(key.to_string(), unwrap_single(actions));
+    }
+}
+
+fn is_after_send(executable: &Executable) -> bool {
+    match executable {
+        Executable::Send(node) => node
+            .id
+            .as_ref()
+            .and_then(|id| parse_after_event(id))
+            .is_some(),
+        _ => false,
+    }
+}
+
+fn is_after_cancel(executable: &Executable) -> bool {
+    match executable {
+        Executable::Cancel(node) => parse_after_event(&node.id).is_some(),
+        _ => false,
+    }
+}
+
+fn context(datamodel: &[Variable]) -> Map<String, Value> {
+    let mut context = Map::new();
+    for variable in datamodel {
+        let value = match variable.init {
+            Some(Expression::Integer(ref node)) => Value::from(node.value),
+            Some(Expression::Boolean(ref node)) => Value::from(node.value),
+            Some(Expression::String(ref node)) => Value::from(node.value.clone()),
+            Some(Expression::Enum(ref node)) => Value::from(node.value.clone()),
+            _ => match variable.t.default_value() {
+                DataValue::Integer(value) => Value::from(value),
+                DataValue::Boolean(value) => Value::from(value),
+                DataValue::String(value) | DataValue::Enum(value) => Value::from(value),
+            },
+        };
+        context.insert(variable.id.clone(), value);
+    }
+    context
+}
@@ -0,0 +1,682 @@
+use ast::datamodel::{BinaryExpression, Expression, UnaryExpression, Variable};
+use ast::executable::{Assign, ElseIf, Executable, Foreach, If, Log};
+use ast::statechart::*;
+
+// Each method of `Visit`, `VisitMut` and `Fold` defaults to the function of the same name
+// in this module, which recurses into the children of the node. Overriding a method and
+// calling that function from it keeps the recursion going.
+
+pub trait Visit<'ast> {
+    fn visit_statechart(&mut self, node: &'ast Statechart) {
+        visit_statechart(self, node)
+    }
+    fn visit_node(&mut self, node: &'ast Node) {
+        visit_node(self, node)
+    }
+    fn visit_state(&mut self, node: &'ast State) {
+        visit_state(self, node)
+    }
+    fn visit_parallel(&mut self, node: &'ast Parallel) {
+        visit_parallel(self, node)
+    }
+    fn visit_transition(&mut self, node: &'ast Transition) {
+        visit_transition(self, node)
+    }
+    fn visit_on_event(&mut self, node: &'ast OnEvent) {
+        visit_on_event(self, node)
+    }
+    fn visit_initial(&mut self, node: &'ast Initial) {
+        visit_initial(self, node)
+    }
+    fn visit_final(&mut self, node: &'ast Final) {
+        visit_final(self, node)
+    }
+    fn visit_history(&mut self, node: &'ast History) {
+        visit_history(self, node)
+    }
+    fn visit_on_init(&mut self, node: &'ast OnInit) {
+        visit_on_init(self, node)
+    }
+    fn visit_on_entry(&mut self, node: &'ast OnEntry) {
+        visit_on_entry(self, node)
+    }
+    fn visit_on_exit(&mut self, node: &'ast OnExit) {
+        visit_on_exit(self, node)
+    }
+    fn visit_invoke(&mut self, _node: &'ast Invoke) {}
+    fn visit_condition(&mut self, node: &'ast Condition) {
+        visit_condition(self, node)
+    }
+    fn visit_executable(&mut self, node: &'ast Executable) {
+        visit_executable(self, node)
+    }
+    fn visit_variable(&mut self, node: &'ast Variable) {
+        visit_variable(self, node)
+    }
+    fn visit_expression(&mut self, node: &'ast Expression) {
+        visit_expression(self, node)
+    }
+}
+
+pub fn visit_statechart<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Statechart) {
+    for variable in &node.datamodel {
+        v.visit_variable(variable);
+    }
+    for child in &node.children {
+        v.visit_node(child);
+    }
+}
+
+pub fn visit_node<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Node) {
+    match node {
+        Node::Statechart(node) => v.visit_statechart(node),
+        Node::State(node) => v.visit_state(node),
+        Node::Parallel(node) => v.visit_parallel(node),
+        Node::Transition(node) => v.visit_transition(node),
+        Node::OnEvent(node) => v.visit_on_event(node),
+        Node::Initial(node) => v.visit_initial(node),
+        Node::Final(node) => v.visit_final(node),
+        Node::OnInit(node) => v.visit_on_init(node),
+        Node::OnEntry(node) => v.visit_on_entry(node),
+        Node::OnExit(node) => v.visit_on_exit(node),
+        Node::History(node) => v.visit_history(node),
+        Node::Invoke(node) => v.visit_invoke(node),
+    }
+}
+
+pub fn visit_state<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast State) {
+    for variable in &node.datamodel {
+        v.visit_variable(variable);
+    }
+    for child in &node.children {
+        v.visit_node(child);
+    }
+}
+
+pub fn visit_parallel<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Parallel) {
+    for child in &node.children {
+        v.visit_node(child);
+    }
+}
+
+pub fn visit_transition<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Transition) {
+    if let Some(ref condition) = node.condition {
+        v.visit_condition(condition);
+    }
+    for executable in &node.children {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_on_event<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast OnEvent) {
+    if let Some(ref condition) = node.condition {
+        v.visit_condition(condition);
+    }
+    for executable in &node.children {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_initial<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Initial) {
+    for child in &node.children {
+        v.visit_node(child);
+    }
+}
+
+pub fn visit_final<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Final) {
+    for child in &node.children {
+        v.visit_node(child);
+    }
+}
+
+pub fn visit_history<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast History) {
+    for child in &node.children {
+        v.visit_node(child);
+    }
+}
+
+pub fn visit_on_init<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast OnInit) {
+    for executable in &node.children {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_on_entry<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast OnEntry) {
+    for executable in &node.children {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_on_exit<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast OnExit) {
+    for executable in &node.children {
+        v.visit_executable(executable);
+    }
+}
+
+pub fn visit_condition<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Condition) {
+    match node {
+        Condition::Predicate(_) | Condition::In(_) => {}
+        Condition::And(node) | Condition::Or(node) => {
+            for child in &node.children {
+                v.visit_condition(child);
+            }
+        }
+        Condition::Not(node) => v.visit_condition(&node.condition),
+        Condition::Expression(node) => v.visit_expression(&node.expr),
+    }
+}
+
+pub fn visit_executable<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Executable) {
+    match node {
+        Executable::Assign(node) => v.visit_expression(&node.expr),
+        Executable::Log(node) => {
+            if let Some(ref expr) = node.expr {
+                v.visit_expression(expr);
+            }
+        }
+        Executable::If(node) => {
+            v.visit_condition(&node.condition);
+            for child in &node.children {
+                v.visit_executable(child);
+            }
+            for branch in &node.else_if {
+                v.visit_condition(&branch.condition);
+                for child in &branch.children {
+                    v.visit_executable(child);
+                }
+            }
+            for child in &node.otherwise {
+                v.visit_executable(child);
+            }
+        }
+        Executable::Foreach(node) => {
+            for child in &node.children {
+                v.visit_executable(child);
+            }
+        }
+        Executable::Script(_) | Executable::Raise(_) | Executable::Send(_) => {}
+        Executable::Cancel(_) => {}
+    }
+}
+
+pub fn visit_variable<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Variable) {
+    if let Some(ref init) = node.init {
+        v.visit_expression(init);
+    }
+}
+
+pub fn visit_expression<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast Expression) {
+    match node {
+        Expression::Unary(node) => v.visit_expression(&node.argument),
+        Expression::Binary(node) => {
+            v.visit_expression(&node.left);
+            v.visit_expression(&node.right);
+        }
+        _ => {}
+    }
+}
+
+pub trait VisitMut {
+    fn visit_statechart_mut(&mut self, node: &mut Statechart) {
+        visit_statechart_mut(self, node)
+    }
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        visit_node_mut(self, node)
+    }
+    fn visit_state_mut(&mut self, node: &mut State) {
+        visit_state_mut(self, node)
+    }
+    fn visit_parallel_mut(&mut self, node: &mut Parallel) {
+        visit_parallel_mut(self, node)
+    }
+    fn visit_transition_mut(&mut self, node: &mut Transition) {
+        visit_transition_mut(self, node)
+    }
+    fn visit_on_event_mut(&mut self, node: &mut OnEvent) {
+        visit_on_event_mut(self, node)
+    }
+    fn visit_initial_mut(&mut self, node: &mut Initial) {
+        visit_initial_mut(self, node)
+    }
+    fn visit_final_mut(&mut self, node: &mut Final) {
+        visit_final_mut(self, node)
+    }
+    fn visit_history_mut(&mut self, node: &mut History) {
+        visit_history_mut(self, node)
+    }
+    fn visit_on_init_mut(&mut self, node: &mut OnInit) {
+        visit_on_init_mut(self, node)
+    }
+    fn visit_on_entry_mut(&mut self, node: &mut OnEntry) {
+        visit_on_entry_mut(self, node)
+    }
+    fn visit_on_exit_mut(&mut self, node: &mut OnExit) {
+        visit_on_exit_mut(self, node)
+    }
+    fn visit_invoke_mut(&mut self, _node: &mut Invoke) {}
+    fn visit_condition_mut(&mut self, node: &mut Condition) {
+        visit_condition_mut(self, node)
+    }
+    fn visit_executable_mut(&mut self, node: &mut Executable) {
+        visit_executable_mut(self, node)
+    }
+    fn visit_variable_mut(&mut self, node: &mut Variable) {
+        visit_variable_mut(self, node)
+    }
+    fn visit_expression_mut(&mut self, node: &mut Expression) {
+        visit_expression_mut(self, node)
+    }
+}
+
+pub fn visit_statechart_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Statechart) {
+    for variable in &mut node.datamodel {
+        v.visit_variable_mut(variable);
+    }
+    for child in &mut node.children {
+        v.visit_node_mut(child);
+    }
+}
+
+pub fn visit_node_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Node) {
+    match node {
+        Node::Statechart(node) => v.visit_statechart_mut(node),
+        Node::State(node) => v.visit_state_mut(node),
+        Node::Parallel(node) => v.visit_parallel_mut(node),
+        Node::Transition(node) => v.visit_transition_mut(node),
+        Node::OnEvent(node) => v.visit_on_event_mut(node),
+        Node::Initial(node) => v.visit_initial_mut(node),
+        Node::Final(node) => v.visit_final_mut(node),
+        Node::OnInit(node) => v.visit_on_init_mut(node),
+        Node::OnEntry(node) => v.visit_on_entry_mut(node),
+        Node::OnExit(node) => v.visit_on_exit_mut(node),
+        Node::History(node) => v.visit_history_mut(node),
+        Node::Invoke(node) => v.visit_invoke_mut(node),
+    }
+}
+
+pub fn visit_state_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut State) {
+    for variable in &mut node.datamodel {
+        v.visit_variable_mut(variable);
+    }
+    for child in &mut node.children {
+        v.visit_node_mut(child);
+    }
+}
+
+pub fn visit_parallel_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Parallel) {
+    for child in &mut node.children {
+        v.visit_node_mut(child);
+    }
+}
+
+pub fn visit_transition_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Transition) {
+    if let Some(ref mut condition) = node.condition {
+        v.visit_condition_mut(condition);
+    }
+    for executable in &mut node.children {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_on_event_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut OnEvent) {
+    if let Some(ref mut condition) = node.condition {
+        v.visit_condition_mut(condition);
+    }
+    for executable in &mut node.children {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_initial_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Initial) {
+    for child in &mut node.children {
+        v.visit_node_mut(child);
+    }
+}
+
+pub fn visit_final_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Final) {
+    for child in &mut node.children {
+        v.visit_node_mut(child);
+    }
+}
+
+pub fn visit_history_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut History) {
+    for child in &mut node.children {
+        v.visit_node_mut(child);
+    }
+}
+
+pub fn visit_on_init_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut OnInit) {
+    for executable in &mut node.children {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_on_entry_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut OnEntry) {
+    for executable in &mut node.children {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_on_exit_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut OnExit) {
+    for executable in &mut node.children {
+        v.visit_executable_mut(executable);
+    }
+}
+
+pub fn visit_condition_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Condition) {
+    match node {
+        Condition::Predicate(_) | Condition::In(_) => {}
+        Condition::And(node) | Condition::Or(node) => {
+            for child in &mut node.children {
+                v.visit_condition_mut(child);
+            }
+        }
+        Condition::Not(node) => v.visit_condition_mut(&mut node.condition),
+        Condition::Expression(node) => v.visit_expression_mut(&mut node.expr),
+    }
+}
+
+pub fn visit_executable_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Executable) {
+    match node {
+        Executable::Assign(node) => v.visit_expression_mut(&mut node.expr),
+        Executable::Log(node) => {
+            if let Some(ref mut expr) = node.expr {
+                v.visit_expression_mut(expr);
+            }
+        }
+        Executable::If(node) => {
+            v.visit_condition_mut(&mut node.condition);
+            for child in &mut node.children {
+                v.visit_executable_mut(child);
+            }
+            for branch in &mut node.else_if {
+                v.visit_condition_mut(&mut branch.condition);
+                for child in &mut branch.children {
+                    v.visit_executable_mut(child);
+                }
+            }
+            for child in &mut node.otherwise {
+                v.visit_executable_mut(child);
+            }
+        }
+        Executable::Foreach(node) => {
+            for child in &mut node.children {
+                v.visit_executable_mut(child);
+            }
+        }
+        Executable::Script(_) | Executable::Raise(_) | Executable::Send(_) => {}
+        Executable::Cancel(_) => {}
+    }
+}
+
+pub fn visit_variable_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Variable) {
+    if let Some(ref mut init) = node.init {
+        v.visit_expression_mut(init);
+    }
+}
+
+pub fn visit_expression_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut Expression) {
+    match node {
+        Expression::Unary(node) => v.visit_expression_mut(&mut node.argument),
+        Expression::Binary(node) => {
+            v.visit_expression_mut(&mut node.left);
+            v.visit_expression_mut(&mut node.right);
+        }
+        _ => {}
+    }
+}
+
+pub trait Fold {
+    fn fold_statechart(&mut self, node: Statechart) -> Statechart {
+        fold_statechart(self, node)
+    }
+    fn fold_node(&mut self, node: Node) -> Node {
+        fold_node(self, node)
+    }
+    fn fold_state(&mut self, node: State) -> State {
+        fold_state(self, node)
+    }
+    fn fold_parallel(&mut self, node: Parallel) -> Parallel {
+        fold_parallel(self, node)
+    }
+    fn fold_transition(&mut self, node: Transition) -> Transition {
+        fold_transition(self, node)
+    }
+    fn fold_on_event(&mut self, node: OnEvent) -> OnEvent {
+        fold_on_event(self, node)
+    }
+    fn fold_initial(&mut self, node: Initial) -> Initial {
+        fold_initial(self, node)
+    }
+    fn fold_final(&mut self, node: Final) -> Final {
+        fold_final(self, node)
+    }
+    fn fold_history(&mut self, node: History) -> History {
+        fold_history(self, node)
+    }
+    fn fold_on_init(&mut self, node: OnInit) -> OnInit {
+        fold_on_init(self, node)
+    }
+    fn fold_on_entry(&mut self, node: OnEntry) -> OnEntry {
+        fold_on_entry(self, node)
+    }
+    fn fold_on_exit(&mut self, node: OnExit) -> OnExit {
+        fold_on_exit(self, node)
+    }
+    fn fold_invoke(&mut self, node: Invoke) -> Invoke {
+        node
+    }
+    fn fold_condition(&mut self, node: Condition) -> Condition {
+        fold_condition(self, node)
+    }
+    fn fold_executable(&mut self, node: Executable) -> Executable {
+        fold_executable(self, node)
+    }
+    fn fold_variable(&mut self, node: Variable) -> Variable {
+        fold_variable(self, node)
+    }
+    fn fold_expression(&mut self, node: Expression) -> Expression {
+        fold_expression(self, node)
+    }
+}
+
+fn fold_nodes<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Node>) -> Vec<Node> {
+    nodes.into_iter().map(|node| f.fold_node(node)).collect()
+}
+
+fn fold_executables<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Executable>) -> Vec<Executable> {
+    nodes
+        .into_iter()
+        .map(|node| f.fold_executable(node))
+        .collect()
+}
+
+fn fold_variables<F: Fold + ?Sized>(f: &mut F, nodes: Vec<Variable>) -> Vec<Variable> {
+    nodes
+        .into_iter()
+        .map(|node| f.fold_variable(node))
+        .collect()
+}
+
+pub fn fold_statechart<F: Fold + ?Sized>(f: &mut F, node: Statechart) -> Statechart {
+    Statechart {
+        datamodel: fold_variables(f, node.datamodel),
+        children: fold_nodes(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_node<F: Fold + ?Sized>(f: &mut F, node: Node) -> Node {
+    match node {
+        Node::Statechart(node) => Node::Statechart(f.fold_statechart(node)),
+        Node::State(node) => Node::State(f.fold_state(node)),
+        Node::Parallel(node) => Node::Parallel(f.fold_parallel(node)),
+        Node::Transition(node) => Node::Transition(f.fold_transition(node)),
+        Node::OnEvent(node) => Node::OnEvent(f.fold_on_event(node)),
+        Node::Initial(node) => Node::Initial(f.fold_initial(node)),
+        Node::Final(node) => Node::Final(f.fold_final(node)),
+        Node::OnInit(node) => Node::OnInit(f.fold_on_init(node)),
+        Node::OnEntry(node) => Node::OnEntry(f.fold_on_entry(node)),
+        Node::OnExit(node) => Node::OnExit(f.fold_on_exit(node)),
+        Node::History(node) => Node::History(f.fold_history(node)),
+        Node::Invoke(node) => Node::Invoke(f.fold_invoke(node)),
+    }
+}
+
+pub fn fold_state<F: Fold + ?Sized>(f: &mut F, node: State) -> State {
+    State {
+        datamodel: fold_variables(f, node.datamodel),
+        children: fold_nodes(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_parallel<F: Fold + ?Sized>(f: &mut F, node: Parallel) -> Parallel {
+    Parallel {
+        children: fold_nodes(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_transition<F: Fold + ?Sized>(f: &mut F, node: Transition) -> Transition {
+    Transition {
+        condition: node.condition.map(|condition| f.fold_condition(condition)),
+        children: fold_executables(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_on_event<F: Fold + ?Sized>(f: &mut F, node: OnEvent) -> OnEvent {
+    OnEvent {
+        condition: node.condition.map(|condition| f.fold_condition(condition)),
+        children: fold_executables(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_initial<F: Fold + ?Sized>(f: &mut F, node: Initial) -> Initial {
+    Initial {
+        children: fold_nodes(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_final<F: Fold + ?Sized>(f: &mut F, node: Final) -> Final {
+    Final {
+        children: fold_nodes(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_history<F: Fold + ?Sized>(f: &mut F, node: History) -> History {
+    History {
+        children: fold_nodes(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_on_init<F: Fold + ?Sized>(f: &mut F, node: OnInit) -> OnInit {
+    OnInit {
+        children: fold_executables(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_on_entry<F: Fold + ?Sized>(f: &mut F, node: OnEntry) -> OnEntry {
+    OnEntry {
+        children: fold_executables(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_on_exit<F: Fold + ?Sized>(f: &mut F, node: OnExit) -> OnExit {
+    OnExit {
+        children: fold_executables(f, node.children),
+        ..node
+    }
+}
+
+pub fn fold_condition<F: Fold + ?Sized>(f: &mut F, node: Condition) -> Condition {
+    match node {
+        Condition::And(node) => Condition::And(Conditions {
+            children: node
+                .children
+                .into_iter()
+                .map(|child| f.fold_condition(child))
+                .collect(),
+            ..node
+        }),
+        Condition::Or(node) => Condition::Or(Conditions {
+            children: node
+                .children
+                .into_iter()
+                .map(|child| f.fold_condition(child))
+                .collect(),
+            ..node
+        }),
+        Condition::Not(node) => Condition::Not(Not {
+            condition: Box::new(f.fold_condition(*node.condition)),
+            ..node
+        }),
+        Condition::Expression(node) => Condition::Expression(ExpressionCondition {
+            expr: f.fold_expression(node.expr),
+            ..node
+        }),
+        node => node,
+    }
+}
+
+pub fn fold_executable<F: Fold + ?Sized>(f: &mut F, node: Executable) -> Executable {
+    match node {
+        Executable::Assign(node) => Executable::Assign(Assign {
+            expr: f.fold_expression(node.expr),
+            ..node
+        }),
+        Executable::Log(node) => Executable::Log(Log {
+            expr: node.expr.map(|expr| f.fold_expression(expr)),
+            ..node
+        }),
+        Executable::If(node) => Executable::If(If {
+            condition: f.fold_condition(node.condition),
+            children: fold_executables(f, node.children),
+            else_if: node
+                .else_if
+                .into_iter()
+                .map(|branch| ElseIf {
+                    condition: f.fold_condition(branch.condition),
+                    children: fold_executables(f, branch.children),
+                    ..branch
+                })
+                .collect(),
+            otherwise: fold_executables(f, node.otherwise),
+            ..node
+        }),
+        Executable::Foreach(node) => Executable::Foreach(Foreach {
+            children: fold_executables(f, node.children),
+            ..node
+        }),
+        node => node,
+    }
+}
+
+pub fn fold_variable<F: Fold + ?Sized>(f: &mut F, node: Variable) -> Variable {
+    Variable {
+        init: node.init.map(|init| f.fold_expression(init)),
+        ..node
+    }
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(f: &mut F, node: Expression) -> Expression {
+    match node {
+        Expression::Unary(node) => Expression::Unary(UnaryExpression {
+            argument: Box::new(f.fold_expression(*node.argument)),
+            ..node
+        }),
+        Expression::Binary(node) => Expression::Binary(BinaryExpression {
+            left: Box::new(f.fold_expression(*node.left)),
+            right: Box::new(f.fold_expression(*node.right)),
+            ..node
+        }),
+        node => node,
+    }
+}
@@ -2,12 +2,20 @@
 extern crate serde_derive;
 
 extern crate serde;
+extern crate serde_json;
+extern crate xml;
 
 #[path = "ast"]
 pub mod ast {
     #[path = "statechart.rs"]
     pub mod statechart;
 
+    #[path = "datamodel.rs"]
+    pub mod datamodel;
+
+    #[path = "executable.rs"]
+    pub mod executable;
+
     #[path = "core.rs"]
     pub mod core;
 
@@ -17,6 +25,42 @@ pub mod ast {
     #[path = "position.rs"]
     pub mod location;
 
+    #[path = "json_position.rs"]
+    pub mod json_location;
+
     #[path = "conversion_error.rs"]
     pub mod conversion_error;
 }
+
+#[path = "runtime"]
+pub mod runtime {
+    #[path = "interpreter.rs"]
+    pub mod interpreter;
+
+    #[path = "choice.rs"]
+    pub mod choice;
+
+    #[path = "scenario.rs"]
+    pub mod scenario;
+
+    #[path = "coverage.rs"]
+    pub mod coverage;
+
+    #[path = "simulator.rs"]
+    pub mod simulator;
+}
+
+#[path = "analysis"]
+pub mod analysis {
+    #[path = "reachability.rs"]
+    pub mod reachability;
+
+    #[path = "nondeterminism.rs"]
+    pub mod nondeterminism;
+
+    #[path = "livelock.rs"]
+    pub mod livelock;
+
+    #[path = "test_generation.rs"]
+    pub mod test_generation;
+}
@@ -0,0 +1,79 @@
+use runtime::interpreter::{ConditionId, Host, RuntimeError};
+
+#[derive(Clone, Debug, Default)]
+pub struct ChoiceHost {
+    choices: Vec<(ConditionId, bool)>,
+    position: usize,
+    is_consistent: bool,
+}
+
+impl ChoiceHost {
+    pub fn choices(&self) -> &[(ConditionId, bool)] {
+        &self.choices
+    }
+}
+
+impl Host for ChoiceHost {
+    fn condition(&mut self, id: ConditionId) -> bool {
+        if self.is_consistent {
+            let answered = self.choices[..self.position]
+                .iter()
+                .find(|(condition, _)| *condition == id);
+            if let Some(&(_, value)) = answered {
+                return value;
+            }
+        }
+
+        let value = match self.choices.get(self.position) {
+            Some(&(_, value)) => value,
+            None => {
+                self.choices.push((id, false));
+                false
+            }
+        };
+        self.position += 1;
+        value
+    }
+}
+
+// runs `f` once for every combination of condition outcomes it can observe
+pub fn each_choice<T, F>(f: F) -> Result<Vec<T>, RuntimeError>
+where
+    F: FnMut(&mut ChoiceHost) -> Result<T, RuntimeError>,
+{
+    each(false, f)
+}
+
+// like `each_choice`, but a condition keeps its first answer for the rest of the run
+pub fn each_consistent_choice<T, F>(f: F) -> Result<Vec<T>, RuntimeError>
+where
+    F: FnMut(&mut ChoiceHost) -> Result<T, RuntimeError>,
+{
+    each(true, f)
+}
+
+fn each<T, F>(is_consistent: bool, mut f: F) -> Result<Vec<T>, RuntimeError>
+where
+    F: FnMut(&mut ChoiceHost) -> Result<T, RuntimeError>,
+{
+    let mut results = vec![];
+    let mut pending = vec![vec![]];
+
+    while let Some(prefix) = pending.pop() {
+        let fixed = prefix.len();
+        let mut host = ChoiceHost {
+            choices: prefix,
+            position: 0,
+            is_consistent,
+        };
+        results.push(f(&mut host)?);
+
+        for i in (fixed..host.choices.len()).rev() {
+            let mut choices = host.choices[..i].to_vec();
+            choices.push((host.choices[i].0, true));
+            pending.push(choices);
+        }
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,325 @@
+use ast::core::{Core, TransitionId};
+use ast::datamodel::Value;
+use ast::location::{Location, Point};
+use ast::microstep::SendStatement;
+use runtime::interpreter::{ExecutableId, Host, InvocationId, Step};
+use runtime::simulator::changes;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+// hit counts by core index. Executables are indexed in `Core::each_executable` order and
+// recognized by where they start, so executables sharing a start are counted together.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Coverage {
+    pub states: Vec<usize>,
+    pub transitions: Vec<usize>,
+    pub executables: Vec<usize>,
+    #[serde(skip)]
+    starts: Vec<Point>,
+}
+
+impl Coverage {
+    pub fn new(core: &Core) -> Coverage {
+        let mut starts = vec![];
+        core.each_executable(|executable| starts.push(executable.loc().start));
+        Coverage {
+            states: vec![0; core.states.len()],
+            transitions: vec![0; core.transitions.len()],
+            executables: vec![0; starts.len()],
+            starts,
+        }
+    }
+
+    // counts the transitions of every step and the states they entered, starting from the
+    // configuration before the first step
+    pub fn record(&mut self, core: &Core, previous: &[bool], steps: &[Step]) {
+        let mut previous = previous;
+        for step in steps {
+            let changes = changes(core, previous, &[], step);
+            for transition in changes.transitions {
+                self.transitions[transition] += 1;
+            }
+            for state in changes.entered {
+                self.states[state] += 1;
+            }
+            previous = &step.configuration;
+        }
+    }
+
+    pub fn execute(&mut self, loc: Location) {
+        for (hits, start) in self.executables.iter_mut().zip(&self.starts) {
+            if start.line == loc.start.line && start.column == loc.start.column {
+                *hits += 1;
+            }
+        }
+    }
+
+    pub fn merge(&mut self, other: &Coverage) {
+        for (hits, other) in self.states.iter_mut().zip(&other.states) {
+            *hits += other;
+        }
+        for (hits, other) in self.transitions.iter_mut().zip(&other.transitions) {
+            *hits += other;
+        }
+        for (hits, other) in self.executables.iter_mut().zip(&other.executables) {
+            *hits += other;
+        }
+    }
+
+    pub fn summary(&self, core: &Core) -> CoverageSummary {
+        let states: Vec<_> = core
+            .states
+            .iter()
+            .map(|state| CoveredItem {
+                idx: state.idx,
+                name: core.state_name(state.idx),
+                hits: self.states[state.idx],
+                loc: state.loc,
+            })
+            .collect();
+        let transitions: Vec<_> = core
+            .transitions
+            .iter()
+            .map(|transition| CoveredItem {
+                idx: transition.idx,
+                name: describe_transition(core, transition.idx),
+                hits: self.transitions[transition.idx],
+                loc: transition.loc,
+            })
+            .collect();
+        let mut executables = vec![];
+        core.each_executable(|executable| {
+            let idx = executables.len();
+            executables.push(CoveredItem {
+                idx,
+                name: core.executable_label(executable),
+                hits: self.executables.get(idx).cloned().unwrap_or(0),
+                loc: executable.loc(),
+            })
+        });
+        CoverageSummary {
+            totals: CoverageTotals {
+                states: Total::of(&states),
+                transitions: Total::of(&transitions),
+                executables: Total::of(&executables),
+            },
+            states,
+            transitions,
+            executables,
+        }
+    }
+
+    // states are reported as functions, transitions as branches of their source state and
+    // every item as a line
+    pub fn to_lcov(&self, core: &Core, source_file: &str) -> String {
+        let summary = self.summary(core);
+        let mut out = String::new();
+        writeln!(out, "TN:").unwrap();
+        writeln!(out, "SF:{}", source_file).unwrap();
+
+        for state in &summary.states {
+            writeln!(out, "FN:{},{}", state.loc.start.line, state.name).unwrap();
+        }
+        for state in &summary.states {
+            writeln!(out, "FNDA:{},{}", state.hits, state.name).unwrap();
+        }
+        writeln!(out, "FNF:{}", summary.totals.states.total).unwrap();
+        writeln!(out, "FNH:{}", summary.totals.states.covered).unwrap();
+
+        for transition in &summary.transitions {
+            let hits = match transition.hits {
+                0 => "-".to_string(),
+                hits => hits.to_string(),
+            };
+            writeln!(
+                out,
+                "BRDA:{},{},{},{}",
+                transition.loc.start.line,
+                core.transitions[transition.idx].source,
+                transition.idx,
+                hits
+            )
+            .unwrap();
+        }
+        writeln!(out, "BRF:{}", summary.totals.transitions.total).unwrap();
+        writeln!(out, "BRH:{}", summary.totals.transitions.covered).unwrap();
+
+        // a line is hit as often as the most frequently hit item on it
+        let mut lines = BTreeMap::new();
+        let items = summary
+            .states
+            .iter()
+            .chain(&summary.transitions)
+            .chain(&summary.executables);
+        for item in items {
+            let hits = lines.entry(item.loc.start.line).or_insert(0);
+            *hits = item.hits.max(*hits);
+        }
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{},{}", line, hits).unwrap();
+        }
+        writeln!(out, "LF:{}", lines.len()).unwrap();
+        writeln!(
+            out,
+            "LH:{}",
+            lines.values().filter(|hits| **hits > 0).count()
+        )
+        .unwrap();
+        writeln!(out, "end_of_record").unwrap();
+        out
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CoverageSummary {
+    pub totals: CoverageTotals,
+    pub states: Vec<CoveredItem>,
+    pub transitions: Vec<CoveredItem>,
+    pub executables: Vec<CoveredItem>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CoverageTotals {
+    pub states: Total,
+    pub transitions: Total,
+    pub executables: Total,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct Total {
+    pub covered: usize,
+    pub total: usize,
+}
+
+impl Total {
+    fn of(items: &[CoveredItem]) -> Total {
+        Total {
+            covered: items.iter().filter(|item| item.hits > 0).count(),
+            total: items.len(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct CoveredItem {
+    pub idx: usize,
+    pub name: String,
+    pub hits: usize,
+    pub loc: Location,
+}
+
+// forwards everything to `host` while counting executed executables
+pub struct CoverageHost<'a, H: 'a> {
+    pub host: &'a mut H,
+    pub coverage: &'a mut Coverage,
+}
+
+impl<'a, H: Host> Host for CoverageHost<'a, H> {
+    fn condition(&mut self, id: usize) -> bool {
+        self.host.condition(id)
+    }
+
+    fn execute(&mut self, id: ExecutableId) {
+        self.host.execute(id)
+    }
+
+    fn send(&mut self, statement: &SendStatement) {
+        self.host.send(statement)
+    }
+
+    fn cancel(&mut self, id: &str) {
+        self.host.cancel(id)
+    }
+
+    fn log(&mut self, label: Option<&str>, value: Option<&Value>) {
+        self.host.log(label, value)
+    }
+
+    fn foreach(&mut self, array: usize) -> usize {
+        self.host.foreach(array)
+    }
+
+    fn invoke(&mut self, id: InvocationId) {
+        self.host.invoke(id)
+    }
+
+    fn close(&mut self, id: InvocationId) {
+        self.host.close(id)
+    }
+
+    fn executed(&mut self, loc: Location) {
+        self.coverage.execute(loc);
+        self.host.executed(loc)
+    }
+}
+
+fn describe_transition(core: &Core, idx: TransitionId) -> String {
+    let transition = &core.transitions[idx];
+    let targets: Vec<_> = transition
+        .targets
+        .iter()
+        .map(|target| core.state_name(*target))
+        .collect();
+    let label = core.transition_label(transition);
+    let mut name = format!(
+        "#{} {} -> {}",
+        idx,
+        core.state_name(transition.source),
+        targets.join(", ")
+    );
+    if !label.is_empty() {
+        write!(name, " : {}", label).unwrap();
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::microstep::Microstep;
+    use ast::statechart::from_scxml::from_scxml;
+    use runtime::scenario::{parse_scenarios, run_scenario_with_coverage};
+
+    #[test]
+    fn every_executed_executable_is_counted() {
+        let statechart = from_scxml(
+            r##"<scxml xmlns="http://www.w3.org/2005/07/scxml" version="1.0" initial="a">
+  <state id="a">
+    <onentry><log label="entered"/><script id="4"/></onentry>
+    <transition event="0" target="b"><raise event="1"/></transition>
+  </state>
+  <state id="b">
+    <onentry><if cond="#0"><log label="never"/></if></onentry>
+  </state>
+</scxml>"##,
+        )
+        .unwrap();
+        let core: Result<Core, _> = statechart.into();
+        let core = core.unwrap();
+        let microstep: Result<Microstep, _> = core.clone().into();
+        let microstep = microstep.unwrap();
+        let (scenario, _) = parse_scenarios(r#"{"name": "s", "steps": [{}, {"event": 0}]}"#)
+            .unwrap()
+            .remove(0);
+        let mut coverage = Coverage::new(&core);
+        run_scenario_with_coverage(&core, &microstep, &scenario, &mut coverage).unwrap();
+
+        let summary = coverage.summary(&core);
+        let hits: Vec<_> = summary
+            .executables
+            .iter()
+            .map(|item| (item.name.as_str(), item.hits))
+            .collect();
+        assert_eq!(
+            hits,
+            vec![
+                ("log entered", 1),
+                ("script 4", 1),
+                ("if c0", 1),
+                ("log never", 0),
+                ("raise e1", 1),
+            ]
+        );
+        assert_eq!(summary.totals.executables.covered, 4);
+    }
+}
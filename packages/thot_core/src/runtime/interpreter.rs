@@ -0,0 +1,749 @@
+use ast::datamodel::{Datamodel, EvalError, Value};
+use ast::location::Location;
+use ast::microstep::*;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+
+pub type EventId = usize;
+pub type ConditionId = usize;
+pub type ExecutableId = usize;
+pub type InvocationId = usize;
+pub type TransitionId = usize;
+
+pub trait Host {
+    fn condition(&mut self, id: ConditionId) -> bool;
+
+    fn execute(&mut self, _id: ExecutableId) {}
+
+    fn send(&mut self, _statement: &SendStatement) {}
+
+    fn cancel(&mut self, _id: &str) {}
+
+    fn log(&mut self, _label: Option<&str>, _value: Option<&Value>) {}
+
+    fn foreach(&mut self, _array: usize) -> usize {
+        0
+    }
+
+    fn invoke(&mut self, _id: InvocationId) {}
+
+    fn close(&mut self, _id: InvocationId) {}
+
+    // called before every executable statement that runs, with the location of the core
+    // executable it was generated from
+    fn executed(&mut self, _loc: Location) {}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RuntimeError {
+    pub message: String,
+    pub loc: Location,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<TransitionId>,
+}
+
+impl From<EvalError> for RuntimeError {
+    fn from(err: EvalError) -> RuntimeError {
+        RuntimeError {
+            message: err.message,
+            loc: err.loc,
+            path: vec![],
+        }
+    }
+}
+
+fn error<T>(message: String, loc: Location) -> Result<T, RuntimeError> {
+    Err(RuntimeError {
+        message,
+        loc,
+        path: vec![],
+    })
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Step {
+    pub event: Option<EventId>,
+    pub configuration: Vec<bool>,
+    pub transitions: Vec<TransitionId>,
+    pub is_stable: bool,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Snapshot {
+    pub configuration: Vec<bool>,
+    pub initialized: Vec<bool>,
+    pub history: Vec<bool>,
+    pub datamodel: Vec<(String, Value)>,
+    pub queue: Vec<EventId>,
+}
+
+#[derive(Clone, Debug)]
+enum RValue {
+    Null,
+    Bool(bool),
+    Configuration(Vec<bool>),
+    Result {
+        configuration: Vec<bool>,
+        initialized: Vec<bool>,
+        history: Vec<bool>,
+        transitions: Vec<bool>,
+        is_stable: bool,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Interpreter<'a> {
+    microstep: &'a Microstep,
+    configuration: Vec<bool>,
+    initialized: Vec<bool>,
+    history: Vec<bool>,
+    datamodel: Datamodel,
+    queue: VecDeque<EventId>,
+    max_microsteps: usize,
+    // host conditions evaluated so far, their answers are not part of a snapshot
+    consulted_conditions: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(microstep: &'a Microstep) -> Interpreter<'a> {
+        let size = microstep.configuration_size;
+        Interpreter {
+            microstep,
+            configuration: vec![false; size],
+            initialized: vec![false; size],
+            history: vec![false; size],
+            datamodel: Datamodel::new(&microstep.datamodel),
+            queue: VecDeque::new(),
+            max_microsteps: 10_000,
+            consulted_conditions: 0,
+        }
+    }
+
+    pub fn with_max_microsteps(mut self, max_microsteps: usize) -> Interpreter<'a> {
+        self.max_microsteps = max_microsteps;
+        self
+    }
+
+    pub fn configuration(&self) -> &[bool] {
+        &self.configuration
+    }
+
+    // the states entered at least once, whose `on_init` has run
+    pub fn initialized(&self) -> &[bool] {
+        &self.initialized
+    }
+
+    pub fn is_active(&self, state: usize) -> bool {
+        self.configuration.get(state).cloned().unwrap_or(false)
+    }
+
+    pub fn active_states(&self) -> Vec<usize> {
+        active(&self.configuration)
+    }
+
+    pub fn datamodel(&self) -> &Datamodel {
+        &self.datamodel
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let mut datamodel: Vec<_> = self
+            .microstep
+            .datamodel
+            .iter()
+            .filter_map(|variable| {
+                self.datamodel
+                    .get(&variable.id)
+                    .map(|value| (variable.id.clone(), value.clone()))
+            })
+            .collect();
+        datamodel.sort_by(|a, b| a.0.cmp(&b.0));
+        Snapshot {
+            configuration: self.configuration.clone(),
+            initialized: self.initialized.clone(),
+            history: self.history.clone(),
+            datamodel,
+            queue: self.queue.iter().cloned().collect(),
+        }
+    }
+
+    pub fn start<H: Host>(&mut self, host: &mut H) -> Result<Vec<Step>, RuntimeError> {
+        let mut steps = vec![self.init(host)?];
+        self.stabilize(&mut steps, host)?;
+        Ok(steps)
+    }
+
+    pub fn dispatch<H: Host>(
+        &mut self,
+        event: EventId,
+        host: &mut H,
+    ) -> Result<Vec<Step>, RuntimeError> {
+        let mut steps = vec![];
+        let step = self.microstep(Some(event), host)?;
+        if !step.is_stable {
+            steps.push(step);
+        }
+        self.stabilize(&mut steps, host)?;
+        Ok(steps)
+    }
+
+    // steps until stable, failing if the microstep limit is reached or a snapshot repeats
+    // without the host being asked a condition in between, which could end the cycle
+    fn stabilize<H: Host>(
+        &mut self,
+        steps: &mut Vec<Step>,
+        host: &mut H,
+    ) -> Result<(), RuntimeError> {
+        let start = steps.len();
+        let mut seen = HashMap::new();
+        seen.insert(self.snapshot(), (start, self.consulted_conditions));
+
+        while let Some(step) = self.step(host)? {
+            steps.push(step);
+
+            let seen_at = (steps.len(), self.consulted_conditions);
+            match seen.entry(self.snapshot()) {
+                Entry::Occupied(entry) if entry.get().1 == self.consulted_conditions => {
+                    let path: Vec<_> = steps[entry.get().0..]
+                        .iter()
+                        .flat_map(|step| step.transitions.iter().cloned())
+                        .collect();
+                    let names: Vec<_> = path.iter().map(|t| format!("#{}", t)).collect();
+                    return Err(RuntimeError {
+                        message: format!(
+                            "Livelock: {} returns to an earlier state",
+                            names.join(" -> ")
+                        ),
+                        loc: Location::default(),
+                        path,
+                    });
+                }
+                Entry::Occupied(mut entry) => {
+                    entry.insert(seen_at);
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(seen_at);
+                }
+            }
+
+            if steps.len() - start >= self.max_microsteps {
+                let path = steps[start..]
+                    .iter()
+                    .flat_map(|step| step.transitions.iter().cloned())
+                    .collect();
+                return Err(RuntimeError {
+                    message: format!(
+                        "Did not stabilize within {} microsteps",
+                        self.max_microsteps
+                    ),
+                    loc: Location::default(),
+                    path,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn init<H: Host>(&mut self, host: &mut H) -> Result<Step, RuntimeError> {
+        self.datamodel = Datamodel::new(&self.microstep.datamodel);
+        self.queue.clear();
+        let function = &self.microstep.init;
+        self.call(function, vec![], None, host)
+    }
+
+    // takes an eventless microstep or, once stable, the next internal event
+    pub fn step<H: Host>(&mut self, host: &mut H) -> Result<Option<Step>, RuntimeError> {
+        let step = self.microstep(None, host)?;
+        if !step.is_stable {
+            return Ok(Some(step));
+        }
+        while let Some(event) = self.queue.pop_front() {
+            let step = self.microstep(Some(event), host)?;
+            if !step.is_stable {
+                return Ok(Some(step));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn microstep<H: Host>(
+        &mut self,
+        event: Option<EventId>,
+        host: &mut H,
+    ) -> Result<Step, RuntimeError> {
+        let args = vec![
+            RValue::Configuration(self.configuration.clone()),
+            RValue::Configuration(self.initialized.clone()),
+            RValue::Configuration(self.history.clone()),
+            RValue::Bool(event.is_some()),
+        ];
+        let function = &self.microstep.next;
+        self.call(function, args, event, host)
+    }
+
+    fn call<H: Host>(
+        &mut self,
+        function: &Function,
+        args: Vec<RValue>,
+        event: Option<EventId>,
+        host: &mut H,
+    ) -> Result<Step, RuntimeError> {
+        let mut frame = Frame {
+            env: HashMap::new(),
+            event,
+        };
+        for (param, arg) in function.params.iter().zip(args) {
+            match param {
+                Expression::Identifier(id) => {
+                    frame.env.insert(id.name.clone(), arg);
+                }
+                _ => return error("Invalid parameter".to_string(), function.loc),
+            }
+        }
+
+        let result = match self.exec_block(&function.body, &mut frame, host)? {
+            Some(result) => result,
+            None => return error("Missing return".to_string(), function.loc),
+        };
+
+        match result {
+            RValue::Result {
+                configuration,
+                initialized,
+                history,
+                transitions,
+                is_stable,
+            } => {
+                self.configuration = configuration;
+                self.initialized = initialized;
+                self.history = history;
+                Ok(Step {
+                    event,
+                    configuration: self.configuration.clone(),
+                    transitions: active(&transitions),
+                    is_stable,
+                })
+            }
+            value => error(
+                format!("Expected a microstep result, found {:?}", value),
+                function.loc,
+            ),
+        }
+    }
+
+    fn exec_block<H: Host>(
+        &mut self,
+        statements: &[Statement],
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<Option<RValue>, RuntimeError> {
+        for statement in statements {
+            if let Some(value) = self.exec(statement, frame, host)? {
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    fn exec<H: Host>(
+        &mut self,
+        statement: &Statement,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<Option<RValue>, RuntimeError> {
+        match statement {
+            Statement::VariableDeclaration(node) => {
+                let VariableDeclarationId::Identifier(ref id) = node.id;
+                let value = self.eval(&node.init, frame, host)?;
+                frame.env.insert(id.name.clone(), value);
+            }
+            Statement::AssignmentStatement(node) => {
+                let AssignmentStatementLeft::Identifier(ref id) = node.left;
+                let value = self.eval(&node.right, frame, host)?;
+                frame.env.insert(id.name.clone(), value);
+            }
+            Statement::ConfigurationDestructureDeclaration(node) => {
+                self.destructure(&node.left, &node.right, frame, host)?;
+            }
+            Statement::InvocationsDestructureDeclaration(node) => {
+                self.destructure(&node.left, &node.right, frame, host)?;
+            }
+            Statement::ReturnStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    return Ok(Some(self.eval(&node.argument, frame, host)?));
+                }
+            }
+            Statement::ExecuteStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    host.execute(node.id);
+                }
+            }
+            Statement::RaiseStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    self.queue.push_back(node.event);
+                }
+            }
+            Statement::SendStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    host.send(node);
+                }
+            }
+            Statement::DataAssignmentStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    self.datamodel.assign(&node.location, &node.expr)?;
+                }
+            }
+            Statement::LogStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    let value = match node.expr {
+                        Some(ref expr) => Some(self.datamodel.eval(expr)?),
+                        None => None,
+                    };
+                    host.log(node.label.as_deref(), value.as_ref());
+                }
+            }
+            Statement::IfStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    let body = if self.eval_bool(&node.test, frame, host)? {
+                        &node.consequent
+                    } else {
+                        &node.alternate
+                    };
+                    return self.exec_block(body, frame, host);
+                }
+            }
+            Statement::ForeachStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    for _ in 0..host.foreach(node.array) {
+                        if let Some(value) = self.exec_block(&node.body, frame, host)? {
+                            return Ok(Some(value));
+                        }
+                    }
+                }
+            }
+            Statement::CancelStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.executed(node.loc);
+                    host.cancel(&node.id);
+                }
+            }
+            Statement::InvocationOpenStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.invoke(node.id);
+                }
+            }
+            Statement::InvocationCloseStatement(node) => {
+                if self.guard(&node.guard, frame, host)? {
+                    host.close(node.id);
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn destructure<H: Host>(
+        &mut self,
+        left: &[Expression],
+        right: &Expression,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<(), RuntimeError> {
+        let values = match self.eval(right, frame, host)? {
+            RValue::Configuration(values) => values,
+            value => {
+                return error(
+                    format!("Expected a configuration, found {:?}", value),
+                    loc(right),
+                )
+            }
+        };
+        for (i, id) in left.iter().enumerate() {
+            match id {
+                Expression::Identifier(id) => {
+                    let value = values.get(i).cloned().unwrap_or(false);
+                    frame.env.insert(id.name.clone(), RValue::Bool(value));
+                }
+                expr => return error("Invalid destructure target".to_string(), loc(expr)),
+            }
+        }
+        Ok(())
+    }
+
+    fn guard<H: Host>(
+        &mut self,
+        guard: &Option<Expression>,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<bool, RuntimeError> {
+        match guard {
+            Some(guard) => self.eval_bool(guard, frame, host),
+            None => Ok(true),
+        }
+    }
+
+    fn eval_bool<H: Host>(
+        &mut self,
+        expr: &Expression,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<bool, RuntimeError> {
+        match self.eval(expr, frame, host)? {
+            RValue::Bool(value) => Ok(value),
+            value => error(format!("Expected a boolean, found {:?}", value), loc(expr)),
+        }
+    }
+
+    fn eval_configuration<H: Host>(
+        &mut self,
+        expr: &SimpleExpression,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<Vec<bool>, RuntimeError> {
+        let expr = expr.to_expression();
+        match self.eval(&expr, frame, host)? {
+            RValue::Configuration(values) => Ok(values),
+            value => error(
+                format!("Expected a configuration, found {:?}", value),
+                loc(&expr),
+            ),
+        }
+    }
+
+    fn eval<H: Host>(
+        &mut self,
+        expr: &Expression,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<RValue, RuntimeError> {
+        Ok(match expr {
+            Expression::Identifier(id) => match frame.env.get(&id.name) {
+                Some(value) => value.clone(),
+                None => return error(format!("Unknown identifier: {:?}", id.name), id.loc),
+            },
+            Expression::NullLiteral | Expression::RenderExpression => RValue::Null,
+            Expression::StringLiteral(_) | Expression::IntegerLiteral(_) => {
+                return error(format!("Unsupported expression: {:?}", expr), loc(expr))
+            }
+            Expression::BooleanLiteral(node) => RValue::Bool(node.value),
+            Expression::LogicalExpression(node) => {
+                RValue::Bool(self.eval_logical(node, frame, host)?)
+            }
+            Expression::ConfigurationCreateExpression(node) => {
+                RValue::Configuration(self.eval_arguments(&node.arguments, frame, host)?)
+            }
+            Expression::InvocationsCreateExpression(node) => {
+                RValue::Configuration(self.eval_arguments(&node.arguments, frame, host)?)
+            }
+            Expression::ConditionExpression(node) => {
+                self.consulted_conditions += 1;
+                RValue::Bool(host.condition(node.id))
+            }
+            Expression::EventExpression(node) => RValue::Bool(frame.event == Some(node.id)),
+            Expression::DatamodelExpression(node) => {
+                RValue::Bool(self.datamodel.eval_bool(&node.expr)?)
+            }
+            Expression::MicrostepResult(node) => {
+                let configuration = self.eval_configuration(&node.configuration, frame, host)?;
+                let initialized = self.eval_configuration(&node.initialized, frame, host)?;
+                let history = self.eval_configuration(&node.history, frame, host)?;
+                // microsteps compiled before the field existed report no transitions
+                let transitions = match node.transitions {
+                    SimpleExpression::NullLiteral => vec![],
+                    ref transitions => self.eval_configuration(transitions, frame, host)?,
+                };
+                let is_stable = self.eval_bool(&node.is_stable.to_expression(), frame, host)?;
+                RValue::Result {
+                    configuration,
+                    initialized,
+                    history,
+                    transitions,
+                    is_stable,
+                }
+            }
+        })
+    }
+
+    fn eval_arguments<H: Host>(
+        &mut self,
+        arguments: &[Expression],
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<Vec<bool>, RuntimeError> {
+        arguments
+            .iter()
+            .map(|argument| self.eval_bool(argument, frame, host))
+            .collect()
+    }
+
+    fn eval_logical<H: Host>(
+        &mut self,
+        node: &LogicalExpression,
+        frame: &mut Frame,
+        host: &mut H,
+    ) -> Result<bool, RuntimeError> {
+        match node.operator {
+            LogicalOperator::And => {
+                for argument in &node.arguments {
+                    if !self.eval_bool(argument, frame, host)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            LogicalOperator::Or => {
+                for argument in &node.arguments {
+                    if self.eval_bool(argument, frame, host)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            LogicalOperator::Not => match node.arguments.first() {
+                Some(argument) => Ok(!self.eval_bool(argument, frame, host)?),
+                None => error("Missing operand for Not".to_string(), node.loc),
+            },
+            LogicalOperator::Xor => {
+                let mut value = false;
+                for argument in &node.arguments {
+                    value ^= self.eval_bool(argument, frame, host)?;
+                }
+                Ok(value)
+            }
+        }
+    }
+}
+
+struct Frame {
+    env: HashMap<String, RValue>,
+    event: Option<EventId>,
+}
+
+pub fn active(configuration: &[bool]) -> Vec<usize> {
+    configuration
+        .iter()
+        .enumerate()
+        .filter(|(_, is_active)| **is_active)
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+fn loc(expr: &Expression) -> Location {
+    match expr {
+        Expression::Identifier(node) => node.loc,
+        Expression::StringLiteral(node) => node.loc,
+        Expression::BooleanLiteral(node) => node.loc,
+        Expression::IntegerLiteral(node) => node.loc,
+        Expression::LogicalExpression(node) => node.loc,
+        Expression::ConfigurationCreateExpression(node) => node.loc,
+        Expression::InvocationsCreateExpression(node) => node.loc,
+        Expression::ConditionExpression(node) => node.loc,
+        Expression::EventExpression(node) => node.loc,
+        Expression::DatamodelExpression(node) => node.loc,
+        Expression::MicrostepResult(node) => node.loc,
+        Expression::NullLiteral | Expression::RenderExpression => Location::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    struct NoConditions;
+
+    impl Host for NoConditions {
+        fn condition(&mut self, _id: usize) -> bool {
+            false
+        }
+    }
+
+    // answers true `remaining` times, then false
+    struct Countdown {
+        remaining: usize,
+    }
+
+    impl Host for Countdown {
+        fn condition(&mut self, _id: usize) -> bool {
+            if self.remaining == 0 {
+                return false;
+            }
+            self.remaining -= 1;
+            true
+        }
+    }
+
+    fn cycle(is_guarded: bool) -> Microstep {
+        let core = Statechart::builder()
+            .state("a", |s| {
+                s.transition(|t| {
+                    let t = t.target("b");
+                    if is_guarded {
+                        t.when(0)
+                    } else {
+                        t
+                    }
+                })
+            })
+            .state("b", |s| s.always("a"))
+            .build_core()
+            .unwrap();
+        let microstep: Result<Microstep, _> = core.into();
+        microstep.unwrap()
+    }
+
+    #[test]
+    fn unguarded_cycles_are_livelocks() {
+        let microstep = cycle(false);
+        let err = Interpreter::new(&microstep)
+            .start(&mut NoConditions)
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "Livelock: #1 -> #0 returns to an earlier state"
+        );
+        assert_eq!(err.path, vec![1, 0]);
+    }
+
+    #[test]
+    fn guarded_cycles_run_until_the_host_ends_them() {
+        let microstep = cycle(true);
+        let mut interpreter = Interpreter::new(&microstep);
+        let steps = interpreter.start(&mut Countdown { remaining: 3 }).unwrap();
+        assert_eq!(steps.len(), 7);
+        assert_eq!(interpreter.active_states(), vec![0, 1]);
+
+        let err = Interpreter::new(&microstep)
+            .with_max_microsteps(20)
+            .start(&mut Countdown { remaining: 100 })
+            .unwrap_err();
+        assert_eq!(err.message, "Did not stabilize within 20 microsteps");
+    }
+
+    #[test]
+    fn steps_report_the_transitions_taken() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b").on(1, "a"))
+            .state("b", |s| s.always("c"))
+            .atomic("c")
+            .build_core()
+            .unwrap();
+        let microstep: Result<Microstep, _> = core.into();
+        let microstep = microstep.unwrap();
+        let mut interpreter = Interpreter::new(&microstep);
+
+        let steps = interpreter.start(&mut NoConditions).unwrap();
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].transitions.is_empty());
+
+        let steps = interpreter.dispatch(0, &mut NoConditions).unwrap();
+        let transitions: Vec<_> = steps.iter().map(|step| step.transitions.clone()).collect();
+        assert_eq!(transitions, vec![vec![0], vec![2]]);
+        assert_eq!(interpreter.active_states(), vec![0, 3]);
+    }
+}
@@ -0,0 +1,268 @@
+use ast::core::Core;
+use ast::json_location::Locations;
+use ast::microstep::Microstep;
+use runtime::coverage::{Coverage, CoverageHost};
+use runtime::interpreter::{active, ConditionId, EventId, ExecutableId, Host, Interpreter};
+use serde_json::{self, Value};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Scenario {
+    pub name: String,
+
+    #[serde(default)]
+    pub steps: Vec<ScenarioStep>,
+}
+
+// one macrostep: starting the machine when `event` is missing, dispatching it otherwise.
+// Expectations that are left out are not checked.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ScenarioStep {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<EventId>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub conditions: BTreeMap<ConditionId, bool>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expect: Option<Vec<String>>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actions: Option<Vec<ExecutableId>>,
+}
+
+// answers conditions from a step, treating missing ones as false
+#[derive(Clone, Debug)]
+pub struct ScenarioHost<'a> {
+    conditions: &'a BTreeMap<ConditionId, bool>,
+    executed: Vec<ExecutableId>,
+}
+
+impl<'a> ScenarioHost<'a> {
+    pub fn new(step: &'a ScenarioStep) -> ScenarioHost<'a> {
+        ScenarioHost {
+            conditions: &step.conditions,
+            executed: vec![],
+        }
+    }
+
+    pub fn executed(&self) -> &[ExecutableId] {
+        &self.executed
+    }
+}
+
+impl<'a> Host for ScenarioHost<'a> {
+    fn condition(&mut self, id: ConditionId) -> bool {
+        self.conditions.get(&id).cloned().unwrap_or(false)
+    }
+
+    fn execute(&mut self, id: ExecutableId) {
+        self.executed.push(id);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScenarioFailureKind {
+    // the first step dispatches an event instead of starting the machine
+    InvalidStart,
+    // the interpreter failed, for example on a livelock
+    #[default]
+    Runtime,
+    // `expected` and `actual` list state names
+    UnexpectedConfiguration,
+    // `expected` and `actual` list executable ids
+    UnexpectedActions,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ScenarioFailure {
+    pub step: usize,
+    pub kind: ScenarioFailureKind,
+    pub message: String,
+    pub expected: Vec<String>,
+    pub actual: Vec<String>,
+}
+
+// the names of the active states, leaving out the root which is always active
+pub fn expected_states(core: &Core, configuration: &[bool]) -> Vec<String> {
+    active(configuration)
+        .into_iter()
+        .filter(|state| *state != 0)
+        .map(|state| core.state_name(state))
+        .collect()
+}
+
+// replays every step and stops at the first one that does not match
+pub fn run_scenario(
+    core: &Core,
+    microstep: &Microstep,
+    scenario: &Scenario,
+) -> Result<(), ScenarioFailure> {
+    run_scenario_with_coverage(core, microstep, scenario, &mut Coverage::new(core))
+}
+
+// like `run_scenario`, adding what the steps reached to `coverage`
+pub fn run_scenario_with_coverage(
+    core: &Core,
+    microstep: &Microstep,
+    scenario: &Scenario,
+    coverage: &mut Coverage,
+) -> Result<(), ScenarioFailure> {
+    let mut interpreter = Interpreter::new(microstep);
+    for (idx, step) in scenario.steps.iter().enumerate() {
+        let mut host = ScenarioHost::new(step);
+        let previous = interpreter.configuration().to_vec();
+        let mut covered = CoverageHost {
+            host: &mut host,
+            coverage,
+        };
+        let result = match step.event {
+            Some(event) if idx > 0 => interpreter.dispatch(event, &mut covered),
+            Some(_) => {
+                return Err(ScenarioFailure {
+                    step: idx,
+                    kind: ScenarioFailureKind::InvalidStart,
+                    message: "The first step must start the machine without an event".to_string(),
+                    ..Default::default()
+                })
+            }
+            None => interpreter.start(&mut covered),
+        };
+        let steps = result.map_err(|err| ScenarioFailure {
+            step: idx,
+            kind: ScenarioFailureKind::Runtime,
+            message: err.message,
+            ..Default::default()
+        })?;
+        coverage.record(core, &previous, &steps);
+
+        if let Some(ref expected) = step.expect {
+            let mut expected = expected.clone();
+            expected.sort();
+            let mut actual = expected_states(core, interpreter.configuration());
+            actual.sort();
+            if expected != actual {
+                return Err(ScenarioFailure {
+                    step: idx,
+                    kind: ScenarioFailureKind::UnexpectedConfiguration,
+                    message: "Unexpected configuration".to_string(),
+                    expected,
+                    actual,
+                });
+            }
+        }
+
+        if let Some(ref expected) = step.actions {
+            if expected.as_slice() != host.executed() {
+                return Err(ScenarioFailure {
+                    step: idx,
+                    kind: ScenarioFailureKind::UnexpectedActions,
+                    message: "Unexpected actions".to_string(),
+                    expected: expected.iter().map(|id| id.to_string()).collect(),
+                    actual: host.executed().iter().map(|id| id.to_string()).collect(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+// parses a scenario file along with the line every step starts on
+pub fn parse_scenarios(source: &str) -> Result<Vec<(Scenario, Vec<usize>)>, serde_json::Error> {
+    // a scenario file holds one scenario or a list of them. They are read from the source
+    // rather than from `value` so errors keep their position.
+    let value: Value = serde_json::from_str(source)?;
+    let (scenarios, values) = match value {
+        Value::Array(ref values) => (serde_json::from_str(source)?, values.iter().collect()),
+        ref value => (vec![serde_json::from_str(source)?], vec![value]),
+    };
+
+    let locations = Locations::new(source, &value);
+    Ok(scenarios
+        .into_iter()
+        .zip(values)
+        .map(|(scenario, value): (Scenario, &Value)| {
+            let lines = match value.get("steps") {
+                Some(Value::Array(steps)) => steps
+                    .iter()
+                    .map(|step| locations.get(step).start.line)
+                    .collect(),
+                _ => vec![],
+            };
+            (scenario, lines)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    #[test]
+    fn single_scenario_files_locate_their_steps() {
+        let source = r#"{
+  "name": "a \"steps\": [ name",
+  "steps": [
+    { "expect": ["a"] },
+    {
+      "event": 0, "conditions": { "1": true },
+      "expect": ["b"]
+    }, {"event": 1}
+  ]
+}"#;
+        let scenarios = parse_scenarios(source).unwrap();
+        assert_eq!(scenarios.len(), 1);
+        let (scenario, lines) = &scenarios[0];
+        assert_eq!(scenario.name, "a \"steps\": [ name");
+        assert_eq!(scenario.steps.len(), 3);
+        assert_eq!(lines, &vec![4, 5, 8]);
+    }
+
+    #[test]
+    fn scenario_lists_locate_the_steps_of_each_scenario() {
+        let source = r#"[
+  { "name": "empty" },
+  { "name": "nested", "steps": [{ "expect": [] },
+    { "event": 2 }] },
+  { "name": "last", "steps": [
+    {}
+  ] }
+]"#;
+        let scenarios = parse_scenarios(source).unwrap();
+        let lines: Vec<_> = scenarios.iter().map(|(_, lines)| lines.clone()).collect();
+        assert_eq!(lines, vec![vec![], vec![3, 4], vec![6]]);
+        assert_eq!(scenarios[1].0.steps[1].event, Some(2));
+
+        let err = parse_scenarios("[{ \"name\": 1 }]").unwrap_err();
+        assert_eq!(err.line(), 1);
+    }
+
+    #[test]
+    fn failures_have_a_kind() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b"))
+            .atomic("b")
+            .build_core()
+            .unwrap();
+        let microstep: Result<Microstep, _> = core.clone().into();
+        let microstep = microstep.unwrap();
+        let run = |source: &str| {
+            let (scenario, _) = parse_scenarios(source).unwrap().remove(0);
+            run_scenario(&core, &microstep, &scenario)
+        };
+
+        assert!(run(r#"{"name": "ok", "steps": [{}, {"event": 0, "expect": ["b"]}]}"#).is_ok());
+
+        let failure = run(r#"{"name": "start", "steps": [{"event": 0}]}"#).unwrap_err();
+        assert_eq!(failure.kind, ScenarioFailureKind::InvalidStart);
+
+        let failure = run(r#"{"name": "wrong", "steps": [{"expect": ["b"]}]}"#).unwrap_err();
+        assert_eq!(failure.kind, ScenarioFailureKind::UnexpectedConfiguration);
+        assert_eq!(
+            (failure.expected, failure.actual),
+            (vec!["b".to_string()], vec!["a".to_string()])
+        );
+    }
+}
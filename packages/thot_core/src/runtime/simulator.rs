@@ -0,0 +1,236 @@
+use ast::core::{Core, StateId, TransitionId};
+use ast::microstep::Microstep;
+use runtime::interpreter::{active, ConditionId, EventId, Host, Interpreter, RuntimeError, Step};
+use std::collections::BTreeMap;
+
+// what one microstep did, with exits deepest first and entries in document order.
+// `initialized` are the entered states whose `on_init` ran, which happens on their first entry.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Changes {
+    pub event: Option<EventId>,
+    pub transitions: Vec<TransitionId>,
+    pub exited: Vec<StateId>,
+    pub entered: Vec<StateId>,
+    pub initialized: Vec<StateId>,
+}
+
+pub fn changes(core: &Core, previous: &[bool], initialized: &[bool], step: &Step) -> Changes {
+    let was_active = |state: StateId| previous.get(state).cloned().unwrap_or(false);
+    let mut exited = vec![false; step.configuration.len()];
+    let mut entered = vec![false; step.configuration.len()];
+    for (state, is_active) in step.configuration.iter().enumerate() {
+        exited[state] = was_active(state) && !is_active;
+        entered[state] = !was_active(state) && *is_active;
+    }
+    for transition in &step.transitions {
+        // exited states that are still active were entered again
+        for state in &core.transitions[*transition].exits {
+            if was_active(*state) && step.configuration[*state] {
+                exited[*state] = true;
+                entered[*state] = true;
+            }
+        }
+    }
+
+    let states = |flags: Vec<bool>| -> Vec<StateId> {
+        flags
+            .into_iter()
+            .enumerate()
+            .filter(|(_, flag)| *flag)
+            .map(|(state, _)| state)
+            .collect()
+    };
+    let mut exited = states(exited);
+    exited.reverse();
+    let entered = states(entered);
+    let initialized = entered
+        .iter()
+        .filter(|state| !initialized.get(**state).cloned().unwrap_or(false))
+        .cloned()
+        .collect();
+    Changes {
+        event: step.event,
+        transitions: step.transitions.clone(),
+        exited,
+        entered,
+        initialized,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HistoryEntry<'a> {
+    pub command: String,
+    pub active: Vec<StateId>,
+    // the simulator before the command, which `undo` returns to
+    interpreter: Interpreter<'a>,
+    conditions: BTreeMap<ConditionId, bool>,
+}
+
+// drives the macrostep runtime one command at a time, answering conditions from values
+// set by the user and remembering every command so it can be undone
+pub struct Simulator<'a> {
+    core: &'a Core,
+    microstep: &'a Microstep,
+    interpreter: Interpreter<'a>,
+    conditions: BTreeMap<ConditionId, bool>,
+    history: Vec<HistoryEntry<'a>>,
+}
+
+impl<'a> Simulator<'a> {
+    pub fn new(core: &'a Core, microstep: &'a Microstep) -> Simulator<'a> {
+        Simulator {
+            core,
+            microstep,
+            interpreter: Interpreter::new(microstep),
+            conditions: BTreeMap::new(),
+            history: vec![],
+        }
+    }
+
+    pub fn active_states(&self) -> Vec<StateId> {
+        self.interpreter.active_states()
+    }
+
+    pub fn conditions(&self) -> &BTreeMap<ConditionId, bool> {
+        &self.conditions
+    }
+
+    pub fn history(&self) -> &[HistoryEntry<'a>] {
+        &self.history
+    }
+
+    // starts the machine with an empty history
+    pub fn start(&mut self) -> Result<Vec<Changes>, RuntimeError> {
+        let changes = self.reset()?;
+        self.history.clear();
+        Ok(changes)
+    }
+
+    // restarts the machine, keeping the condition values
+    pub fn reset(&mut self) -> Result<Vec<Changes>, RuntimeError> {
+        self.run("reset".to_string(), true, |interpreter, host| {
+            interpreter.start(host)
+        })
+    }
+
+    pub fn send(&mut self, event: EventId) -> Result<Vec<Changes>, RuntimeError> {
+        self.run(format!("send {}", event), false, |interpreter, host| {
+            interpreter.dispatch(event, host)
+        })
+    }
+
+    // takes a single eventless or internal microstep, if there is one
+    pub fn step(&mut self) -> Result<Vec<Changes>, RuntimeError> {
+        let changes = self.run("step".to_string(), false, |interpreter, host| {
+            Ok(interpreter.step(host)?.into_iter().collect())
+        })?;
+        // a stable machine has nothing to undo
+        if changes.is_empty() {
+            self.history.pop();
+        }
+        Ok(changes)
+    }
+
+    pub fn set(&mut self, condition: ConditionId, value: bool) {
+        let active = self.active_states();
+        self.push(format!("set {} {}", condition, value), active);
+        self.conditions.insert(condition, value);
+    }
+
+    // returns the command that was undone
+    pub fn undo(&mut self) -> Option<String> {
+        let entry = self.history.pop()?;
+        self.interpreter = entry.interpreter;
+        self.conditions = entry.conditions;
+        Some(entry.command)
+    }
+
+    fn push(&mut self, command: String, active: Vec<StateId>) {
+        self.history.push(HistoryEntry {
+            command,
+            active,
+            interpreter: self.interpreter.clone(),
+            conditions: self.conditions.clone(),
+        });
+    }
+
+    // runs `f` on a copy of the interpreter, or a new one if `is_restart`, and leaves the
+    // simulator untouched if it fails
+    fn run<F>(
+        &mut self,
+        command: String,
+        is_restart: bool,
+        f: F,
+    ) -> Result<Vec<Changes>, RuntimeError>
+    where
+        F: FnOnce(&mut Interpreter<'a>, &mut SimulatorHost) -> Result<Vec<Step>, RuntimeError>,
+    {
+        let mut interpreter = if is_restart {
+            Interpreter::new(self.microstep)
+        } else {
+            self.interpreter.clone()
+        };
+        let mut previous = interpreter.configuration().to_vec();
+        let mut initialized = interpreter.initialized().to_vec();
+        let mut host = SimulatorHost {
+            conditions: &self.conditions,
+        };
+        let steps = f(&mut interpreter, &mut host)?;
+
+        self.push(command, active(interpreter.configuration()));
+        self.interpreter = interpreter;
+        Ok(steps
+            .iter()
+            .map(|step| {
+                let changes = changes(self.core, &previous, &initialized, step);
+                for state in &changes.initialized {
+                    initialized[*state] = true;
+                }
+                previous = step.configuration.clone();
+                changes
+            })
+            .collect())
+    }
+}
+
+pub struct SimulatorHost<'a> {
+    conditions: &'a BTreeMap<ConditionId, bool>,
+}
+
+impl<'a> Host for SimulatorHost<'a> {
+    fn condition(&mut self, id: ConditionId) -> bool {
+        self.conditions.get(&id).cloned().unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ast::statechart::Statechart;
+
+    fn initialized(changes: &[Changes]) -> Vec<StateId> {
+        changes
+            .iter()
+            .flat_map(|changes| changes.initialized.clone())
+            .collect()
+    }
+
+    #[test]
+    fn states_are_initialized_on_their_first_entry() {
+        let core = Statechart::builder()
+            .state("a", |s| s.on(0, "b"))
+            .state("b", |s| s.on(0, "a"))
+            .build_core()
+            .unwrap();
+        let microstep: Result<Microstep, _> = core.clone().into();
+        let microstep = microstep.unwrap();
+        let mut simulator = Simulator::new(&core, &microstep);
+
+        assert_eq!(initialized(&simulator.start().unwrap()), vec![0, 1]);
+        assert_eq!(initialized(&simulator.send(0).unwrap()), vec![2]);
+        let changes = simulator.send(0).unwrap();
+        assert_eq!(changes.last().unwrap().entered, vec![1]);
+        assert!(initialized(&changes).is_empty());
+        assert_eq!(initialized(&simulator.reset().unwrap()), vec![0, 1]);
+    }
+}
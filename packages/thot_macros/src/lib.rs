@@ -0,0 +1,554 @@
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
+extern crate syn;
+extern crate thot_core;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::ToTokens;
+use std::collections::HashMap;
+use syn::parse::{Parse, ParseStream};
+use syn::{braced, parenthesized, Ident, Token, Visibility};
+use thot_core::ast::conversion_error::Errors;
+use thot_core::ast::core::to_rust::{to_rust, RustOptions};
+use thot_core::ast::core::Core;
+use thot_core::ast::executable::{Executable, Script};
+use thot_core::ast::location::{Location, Point};
+use thot_core::ast::statechart::*;
+
+mod kw {
+    syn::custom_keyword!(events);
+    syn::custom_keyword!(guards);
+    syn::custom_keyword!(actions);
+    syn::custom_keyword!(state);
+    syn::custom_keyword!(parallel);
+    syn::custom_keyword!(history);
+    syn::custom_keyword!(deep);
+    syn::custom_keyword!(shallow);
+    syn::custom_keyword!(on);
+    syn::custom_keyword!(always);
+    syn::custom_keyword!(internal);
+    syn::custom_keyword!(entry);
+    syn::custom_keyword!(exit);
+}
+
+// compiles a statechart into a module of typed enums and a `Machine` at compile time.
+// The crate using it also depends on thot_core, which runs the generated machine.
+//
+// statechart! {
+//     pub mod light {
+//         events { Timer, Power }
+//         guards { IsNight }
+//         actions { Blink }
+//
+//         // the first child of each state is its initial state
+//         state green {
+//             on Timer if IsNight => yellow / Blink;
+//             on Timer => red;
+//         }
+//         state yellow {
+//             entry / Blink;
+//             always => red;
+//         }
+//         state red {
+//             on Timer if !in(green) && !IsNight => green;
+//         }
+//     }
+// }
+#[proc_macro]
+pub fn statechart(input: TokenStream) -> TokenStream {
+    let chart = syn::parse_macro_input!(input as Chart);
+    match expand(&chart) {
+        Ok(tokens) => tokens,
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+struct Chart {
+    visibility: Visibility,
+    name: Ident,
+    events: Vec<Ident>,
+    guards: Vec<Ident>,
+    actions: Vec<Ident>,
+    body: Vec<Item>,
+}
+
+enum Item {
+    State(StateKind, Ident, Vec<Item>),
+    Transition(TransitionItem),
+    Entry(Vec<Ident>),
+    Exit(Vec<Ident>),
+}
+
+enum StateKind {
+    State,
+    Parallel,
+    Final,
+    History(HistoryType),
+}
+
+struct TransitionItem {
+    keyword: Span,
+    is_internal: bool,
+    event: Option<Ident>,
+    guard: Option<Guard>,
+    targets: Vec<Ident>,
+    actions: Vec<Ident>,
+}
+
+enum Guard {
+    Name(Ident),
+    In(Ident),
+    Not(Span, Box<Guard>),
+    And(Span, Vec<Guard>),
+    Or(Span, Vec<Guard>),
+}
+
+impl Parse for Chart {
+    fn parse(input: ParseStream) -> syn::Result<Chart> {
+        let visibility = input.parse()?;
+        input.parse::<Token![mod]>()?;
+        let name = input.parse()?;
+        let content;
+        braced!(content in input);
+
+        let mut events = vec![];
+        let mut guards = vec![];
+        let mut actions = vec![];
+        let mut body = vec![];
+        while !content.is_empty() {
+            if content.peek(kw::events) {
+                content.parse::<kw::events>()?;
+                events.extend(parse_names(&content)?);
+            } else if content.peek(kw::guards) {
+                content.parse::<kw::guards>()?;
+                guards.extend(parse_names(&content)?);
+            } else if content.peek(kw::actions) {
+                content.parse::<kw::actions>()?;
+                actions.extend(parse_names(&content)?);
+            } else {
+                body.push(content.parse()?);
+            }
+        }
+
+        Ok(Chart {
+            visibility,
+            name,
+            events,
+            guards,
+            actions,
+            body,
+        })
+    }
+}
+
+// `{ A, B, C }`
+fn parse_names(input: ParseStream) -> syn::Result<Vec<Ident>> {
+    let content;
+    braced!(content in input);
+    let names = content.parse_terminated(Ident::parse, Token![,])?;
+    Ok(names.into_iter().collect())
+}
+
+// `a, b, c` up to the next `/` or `;`
+fn parse_list(input: ParseStream) -> syn::Result<Vec<Ident>> {
+    let mut names = vec![input.parse()?];
+    while input.peek(Token![,]) {
+        input.parse::<Token![,]>()?;
+        names.push(input.parse()?);
+    }
+    Ok(names)
+}
+
+fn parse_actions(input: ParseStream) -> syn::Result<Vec<Ident>> {
+    if input.peek(Token![/]) {
+        input.parse::<Token![/]>()?;
+        parse_list(input)
+    } else {
+        Ok(vec![])
+    }
+}
+
+impl Parse for Item {
+    fn parse(input: ParseStream) -> syn::Result<Item> {
+        let lookahead = input.lookahead1();
+        let item = if lookahead.peek(kw::state) || lookahead.peek(kw::parallel) {
+            let kind = if input.peek(kw::state) {
+                input.parse::<kw::state>()?;
+                StateKind::State
+            } else {
+                input.parse::<kw::parallel>()?;
+                StateKind::Parallel
+            };
+            let name = input.parse()?;
+            // atomic states may leave out their body
+            if input.peek(Token![;]) {
+                input.parse::<Token![;]>()?;
+                return Ok(Item::State(kind, name, vec![]));
+            }
+            let content;
+            braced!(content in input);
+            let mut children = vec![];
+            while !content.is_empty() {
+                children.push(content.parse()?);
+            }
+            return Ok(Item::State(kind, name, children));
+        } else if lookahead.peek(Token![final]) {
+            input.parse::<Token![final]>()?;
+            Item::State(StateKind::Final, input.parse()?, vec![])
+        } else if lookahead.peek(kw::history) {
+            input.parse::<kw::history>()?;
+            let name = input.parse()?;
+            let t = if input.peek(kw::deep) {
+                input.parse::<kw::deep>()?;
+                HistoryType::Deep
+            } else {
+                if input.peek(kw::shallow) {
+                    input.parse::<kw::shallow>()?;
+                }
+                HistoryType::Shallow
+            };
+            Item::State(StateKind::History(t), name, vec![])
+        } else if lookahead.peek(kw::entry) {
+            input.parse::<kw::entry>()?;
+            Item::Entry(parse_actions(input)?)
+        } else if lookahead.peek(kw::exit) {
+            input.parse::<kw::exit>()?;
+            Item::Exit(parse_actions(input)?)
+        } else if lookahead.peek(kw::on)
+            || lookahead.peek(kw::always)
+            || lookahead.peek(kw::internal)
+        {
+            Item::Transition(input.parse()?)
+        } else {
+            return Err(lookahead.error());
+        };
+        input.parse::<Token![;]>()?;
+        Ok(item)
+    }
+}
+
+// `[internal] on Event [if guard] [=> targets] [/ actions]` or
+// `[internal] always [if guard] [=> targets] [/ actions]`
+impl Parse for TransitionItem {
+    fn parse(input: ParseStream) -> syn::Result<TransitionItem> {
+        let is_internal = input.peek(kw::internal);
+        if is_internal {
+            input.parse::<kw::internal>()?;
+        }
+        let (keyword, event) = if input.peek(kw::always) {
+            (input.parse::<kw::always>()?.span, None)
+        } else {
+            let keyword = input.parse::<kw::on>()?.span;
+            (keyword, Some(input.parse()?))
+        };
+        let guard = if input.peek(Token![if]) {
+            input.parse::<Token![if]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        let targets = if input.peek(Token![=>]) {
+            input.parse::<Token![=>]>()?;
+            parse_list(input)?
+        } else {
+            vec![]
+        };
+        let actions = parse_actions(input)?;
+
+        Ok(TransitionItem {
+            keyword,
+            is_internal,
+            event,
+            guard,
+            targets,
+            actions,
+        })
+    }
+}
+
+// `||` binds looser than `&&`, which binds looser than `!`
+impl Parse for Guard {
+    fn parse(input: ParseStream) -> syn::Result<Guard> {
+        let span = input.span();
+        let mut children = vec![parse_and(input)?];
+        while input.peek(Token![||]) {
+            input.parse::<Token![||]>()?;
+            children.push(parse_and(input)?);
+        }
+        Ok(match children.len() {
+            1 => children.pop().unwrap(),
+            _ => Guard::Or(span, children),
+        })
+    }
+}
+
+fn parse_and(input: ParseStream) -> syn::Result<Guard> {
+    let span = input.span();
+    let mut children = vec![parse_unary(input)?];
+    while input.peek(Token![&&]) {
+        input.parse::<Token![&&]>()?;
+        children.push(parse_unary(input)?);
+    }
+    Ok(match children.len() {
+        1 => children.pop().unwrap(),
+        _ => Guard::And(span, children),
+    })
+}
+
+fn parse_unary(input: ParseStream) -> syn::Result<Guard> {
+    if input.peek(Token![!]) {
+        let span = input.parse::<Token![!]>()?.span;
+        Ok(Guard::Not(span, Box::new(parse_unary(input)?)))
+    } else if input.peek(Token![in]) {
+        input.parse::<Token![in]>()?;
+        let content;
+        parenthesized!(content in input);
+        Ok(Guard::In(content.parse()?))
+    } else if input.peek(syn::token::Paren) {
+        let content;
+        parenthesized!(content in input);
+        content.parse()
+    } else {
+        Ok(Guard::Name(input.parse()?))
+    }
+}
+
+fn expand(chart: &Chart) -> syn::Result<TokenStream> {
+    let mut lower = Lower {
+        spans: vec![],
+        events: ids(&chart.events),
+        guards: ids(&chart.guards),
+        actions: ids(&chart.actions),
+        errors: vec![],
+    };
+    let loc = lower.loc(chart.name.span());
+    let statechart = Statechart {
+        children: chart.body.iter().map(|item| lower.node(item)).collect(),
+        loc,
+        ..Default::default()
+    };
+    if let Some(error) = lower.errors.into_iter().reduce(|mut error, other| {
+        error.combine(other);
+        error
+    }) {
+        return Err(error);
+    }
+
+    let spans = lower.spans;
+    let core: Result<Core, Errors> = statechart.into();
+    let core = core.map_err(|errors| compile_errors(errors, &spans))?;
+
+    let options = RustOptions {
+        module: chart.name.to_string(),
+        visibility: chart.visibility.to_token_stream().to_string(),
+        events: names(&chart.events),
+        guards: names(&chart.guards),
+        actions: names(&chart.actions),
+    };
+    let source = to_rust(&core, &options).map_err(|errors| compile_errors(errors, &spans))?;
+    source
+        .parse()
+        .map_err(|err| syn::Error::new(chart.name.span(), format!("{:?}", err)))
+}
+
+// ids are numbered from 1 in the order they are declared
+fn ids(names: &[Ident]) -> HashMap<String, usize> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| (name.to_string(), idx + 1))
+        .collect()
+}
+
+fn names(names: &[Ident]) -> Vec<(String, usize)> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(idx, name)| (name.to_string(), idx + 1))
+        .collect()
+}
+
+fn name(ident: &Ident) -> String {
+    ident.to_string()
+}
+
+// the column of each synthetic location indexes into the spans of the macro input, and
+// line 0 tells them apart from the default location
+fn compile_errors(errors: Errors, spans: &[Span]) -> syn::Error {
+    errors
+        .into_iter()
+        .map(|error| {
+            let span = match error.loc.start {
+                Point { line: 0, column } if column < spans.len() => spans[column],
+                _ => Span::call_site(),
+            };
+            syn::Error::new(span, error.message)
+        })
+        .reduce(|mut error, other| {
+            error.combine(other);
+            error
+        })
+        .unwrap_or_else(|| syn::Error::new(Span::call_site(), "Invalid statechart"))
+}
+
+struct Lower {
+    spans: Vec<Span>,
+    events: HashMap<String, usize>,
+    guards: HashMap<String, usize>,
+    actions: HashMap<String, usize>,
+    errors: Vec<syn::Error>,
+}
+
+impl Lower {
+    fn loc(&mut self, span: Span) -> Location {
+        let point = Point {
+            line: 0,
+            column: self.spans.len(),
+        };
+        self.spans.push(span);
+        Location {
+            start: point,
+            end: point,
+            source: None,
+        }
+    }
+
+    fn id(&mut self, kind: &str, ident: &Ident) -> usize {
+        let ids = match kind {
+            "event" => &self.events,
+            "guard" => &self.guards,
+            _ => &self.actions,
+        };
+        match ids.get(&ident.to_string()) {
+            Some(id) => *id,
+            None => {
+                self.errors.push(syn::Error::new(
+                    ident.span(),
+                    format!("Undeclared {}: {}", kind, ident),
+                ));
+                0
+            }
+        }
+    }
+
+    fn scripts(&mut self, actions: &[Ident]) -> Vec<Executable> {
+        actions
+            .iter()
+            .map(|action| {
+                Executable::Script(Script {
+                    id: self.id("action", action),
+                    loc: self.loc(action.span()),
+                })
+            })
+            .collect()
+    }
+
+    fn node(&mut self, item: &Item) -> Node {
+        match item {
+            Item::State(kind, ident, items) => {
+                let id = Some(name(ident));
+                let loc = self.loc(ident.span());
+                let children = items.iter().map(|item| self.node(item)).collect();
+                match kind {
+                    StateKind::State => Node::State(State {
+                        id,
+                        children,
+                        loc,
+                        ..Default::default()
+                    }),
+                    StateKind::Parallel => Node::Parallel(Parallel { id, children, loc }),
+                    StateKind::Final => Node::Final(Final { id, children, loc }),
+                    StateKind::History(t) => Node::History(History {
+                        id,
+                        t: *t,
+                        children,
+                        loc,
+                    }),
+                }
+            }
+            Item::Transition(transition) => {
+                // errors about a transition usually concern its targets
+                let span = match transition.targets.first() {
+                    Some(target) => target.span(),
+                    None => transition.keyword,
+                };
+                Node::Transition(Transition {
+                    event: transition
+                        .event
+                        .as_ref()
+                        .map(|event| self.id("event", event)),
+                    targets: transition.targets.iter().map(name).collect(),
+                    t: if transition.is_internal {
+                        TransitionType::Internal
+                    } else {
+                        TransitionType::External
+                    },
+                    condition: transition.guard.as_ref().map(|guard| self.condition(guard)),
+                    children: self.scripts(&transition.actions),
+                    loc: self.loc(span),
+                })
+            }
+            Item::Entry(actions) => Node::OnEntry(OnEntry {
+                children: self.scripts(actions),
+                ..Default::default()
+            }),
+            Item::Exit(actions) => Node::OnExit(OnExit {
+                children: self.scripts(actions),
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn condition(&mut self, guard: &Guard) -> Condition {
+        match guard {
+            Guard::Name(ident) => Condition::Predicate(Predicate {
+                id: self.id("guard", ident),
+                loc: self.loc(ident.span()),
+            }),
+            Guard::In(ident) => Condition::In(In {
+                state: name(ident),
+                loc: self.loc(ident.span()),
+            }),
+            Guard::Not(span, guard) => Condition::Not(Not {
+                condition: Box::new(self.condition(guard)),
+                loc: self.loc(*span),
+            }),
+            Guard::And(span, guards) => Condition::And(Conditions {
+                children: guards.iter().map(|guard| self.condition(guard)).collect(),
+                loc: self.loc(*span),
+            }),
+            Guard::Or(span, guards) => Condition::Or(Conditions {
+                children: guards.iter().map(|guard| self.condition(guard)).collect(),
+                loc: self.loc(*span),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_errors(source: &str) -> syn::Error {
+        let chart: Chart = syn::parse_str(source).unwrap();
+        match expand(&chart) {
+            Ok(_) => panic!("expected {:?} not to compile", source),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn missing_targets_are_reported_at_the_target() {
+        let error = expand_errors(
+            "mod light {
+                state green { on Timer => nowhere; }
+                events { Timer }
+            }",
+        );
+        assert_eq!(error.to_string(), "Missing target: \"nowhere\"");
+        let start = error.span().start();
+        assert_eq!((start.line, start.column), (2, 42));
+    }
+}
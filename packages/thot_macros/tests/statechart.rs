@@ -0,0 +1,68 @@
+extern crate thot_core;
+extern crate thot_macros;
+
+use thot_macros::statechart;
+
+statechart! {
+    mod light {
+        events { Timer, Power }
+        guards { IsNight }
+        actions { Blink }
+
+        state green {
+            on Timer if IsNight => yellow / Blink;
+            on Timer => red;
+        }
+        state yellow {
+            entry / Blink;
+            always => red;
+        }
+        state red {
+            on Timer if !in(green) && !IsNight => green;
+        }
+    }
+}
+
+#[derive(Default)]
+struct Street {
+    is_night: bool,
+    blinks: usize,
+}
+
+impl light::Actions for Street {
+    fn guard(&mut self, guard: light::Guard) -> bool {
+        match guard {
+            light::Guard::IsNight => self.is_night,
+        }
+    }
+
+    fn action(&mut self, action: light::Action) {
+        match action {
+            light::Action::Blink => self.blinks += 1,
+        }
+    }
+}
+
+#[test]
+fn the_expanded_machine_runs() {
+    let mut street = Street::default();
+    let mut machine = light::Machine::new();
+    machine.start(&mut street).unwrap();
+    assert_eq!(machine.active_states(), vec![light::State::Green]);
+
+    machine.send(light::Event::Timer, &mut street).unwrap();
+    assert_eq!(machine.active_states(), vec![light::State::Red]);
+    machine.send(light::Event::Timer, &mut street).unwrap();
+    assert!(machine.is_active(light::State::Green));
+
+    // yellow blinks on the way in and moves on to red without an event
+    street.is_night = true;
+    machine.send(light::Event::Timer, &mut street).unwrap();
+    assert_eq!(machine.active_states(), vec![light::State::Red]);
+    assert_eq!(street.blinks, 2);
+
+    // red stays red at night and ignores events it has no transition for
+    machine.send(light::Event::Timer, &mut street).unwrap();
+    machine.send(light::Event::Power, &mut street).unwrap();
+    assert_eq!(machine.active_states(), vec![light::State::Red]);
+}